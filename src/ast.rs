@@ -1,9 +1,34 @@
+//! The syntax tree built by the [`Parser`](crate::Parser).
+//!
+//! A program is an [`Ast`]: a list of [`Stmt`]s, each wrapped in a
+//! [`WithSpan`] giving its line and byte range in the source. Names and
+//! string literals borrow from the source text through the `'a` lifetime;
+//! [`Stmt::into_owned`] and [`Expr::into_owned`] detach them.
+//!
+//! Variables, `this` and `super` carry an `Option<Slot>` that is `None`
+//! after parsing. The [`Resolver`](crate::Resolver) fills it in for locals
+//! and leaves it `None` for globals.
+//!
+//! Use a [`Visitor`] to inspect a tree, a [`VisitorMut`] to rewrite it in
+//! place, or a [`Fold`] to build a new tree from it.
+//!
+//! The variants and fields here only change in a new major version.
+
+pub mod fold;
+pub mod visit;
+pub mod visit_mut;
+
 use std::{
     borrow::Cow,
     fmt::{self, Write},
+    rc::Rc,
 };
 
-use crate::position::WithSpan;
+use crate::{number::format_literal, position::WithSpan};
+
+pub use fold::Fold;
+pub use visit::Visitor;
+pub use visit_mut::VisitorMut;
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum UnaryOperator {
@@ -11,7 +36,7 @@ pub enum UnaryOperator {
     Minus,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BinaryOperator {
     Plus,
     Minus,
@@ -25,34 +50,90 @@ pub enum BinaryOperator {
     EqualEqual,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogicalOperator {
+    And,
+    Or,
+}
+
+/// The location of a resolved local variable: how many scopes to walk out
+/// from the use site and the variable's index within that scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
+
+impl Slot {
+    #[inline]
+    pub fn new(depth: usize, index: usize) -> Self {
+        Self { depth, index }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Expr<'a> {
+    /// `left op right` for arithmetic, comparison and equality.
     Binary(
         Box<WithSpan<Expr<'a>>>,
         WithSpan<BinaryOperator>,
         Box<WithSpan<Expr<'a>>>,
     ),
+    /// `(inner)`.
     Grouping(Box<WithSpan<Expr<'a>>>),
     Number(f64),
     Boolean(bool),
     Nil,
-    This,
+    This(Option<Slot>),
     String(Cow<'a, str>),
+    /// `"a ${b} c"`: string literals and the expressions between them, in
+    /// order. Empty fragments are left out.
+    Interpolation(Vec<WithSpan<Expr<'a>>>),
     Unary(WithSpan<UnaryOperator>, Box<WithSpan<Expr<'a>>>),
+    Variable(Cow<'a, str>, Option<Slot>),
+    /// `name = value`.
+    Assign(
+        WithSpan<Cow<'a, str>>,
+        Box<WithSpan<Expr<'a>>>,
+        Option<Slot>,
+    ),
+    /// `left and right` or `left or right`, which may skip `right`.
+    Logical(
+        Box<WithSpan<Expr<'a>>>,
+        WithSpan<LogicalOperator>,
+        Box<WithSpan<Expr<'a>>>,
+    ),
+    /// `callee(args...)`.
+    Call(Box<WithSpan<Expr<'a>>>, Vec<WithSpan<Expr<'a>>>),
+    /// `object.name`.
+    Get(Box<WithSpan<Expr<'a>>>, WithSpan<Cow<'a, str>>),
+    /// `object.name = value`.
+    Set(
+        Box<WithSpan<Expr<'a>>>,
+        WithSpan<Cow<'a, str>>,
+        Box<WithSpan<Expr<'a>>>,
+    ),
+    /// `super.method`.
+    Super(WithSpan<Cow<'a, str>>, Option<Slot>),
+    /// `[items...]`.
+    List(Vec<WithSpan<Expr<'a>>>),
+    /// `list[index]`.
+    Index(Box<WithSpan<Expr<'a>>>, Box<WithSpan<Expr<'a>>>),
+    /// `list[index] = value`.
+    SetIndex(
+        Box<WithSpan<Expr<'a>>>,
+        Box<WithSpan<Expr<'a>>>,
+        Box<WithSpan<Expr<'a>>>,
+    ),
 }
 
 impl fmt::Display for Expr<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Nil => f.write_str("nil"),
-            Self::This => f.write_str("this"),
+            Self::This(_) => f.write_str("this"),
             Self::Boolean(v) => write!(f, "{v}"),
-            Self::Number(v) => {
-                if v.fract() == 0.0 {
-                    write!(f, "{v}.0")
-                } else {
-                    write!(f, "{v}")
-                }
-            }
+            Self::Number(v) => f.write_str(&format_literal(*v)),
             Self::Binary(a, o, b) => {
                 f.write_char('(')?;
                 f.write_str(match o.get_value() {
@@ -71,24 +152,283 @@ impl fmt::Display for Expr<'_> {
             }
             Self::Grouping(g) => write!(f, "(group {})", g.get_value()),
             Self::String(v) => f.write_str(v),
+            Self::Interpolation(parts) => {
+                f.write_str("(interpolate")?;
+                for part in parts {
+                    write!(f, " {}", part.get_value())?;
+                }
+                f.write_char(')')
+            }
             Self::Unary(u, v) => match u.get_value() {
                 UnaryOperator::Bang => write!(f, "(! {})", v.get_value()),
                 UnaryOperator::Minus => write!(f, "(- {})", v.get_value()),
             },
+            Self::Variable(name, _) => f.write_str(name),
+            Self::Assign(name, v, _) => write!(f, "(= {} {})", name.get_value(), v.get_value()),
+            Self::Logical(a, o, b) => {
+                let o = match o.get_value() {
+                    LogicalOperator::And => "and",
+                    LogicalOperator::Or => "or",
+                };
+                write!(f, "({o} {} {})", a.get_value(), b.get_value())
+            }
+            Self::Call(callee, args) => {
+                write!(f, "(call {}", callee.get_value())?;
+                for arg in args {
+                    write!(f, " {}", arg.get_value())?;
+                }
+                f.write_char(')')
+            }
+            Self::Get(object, name) => write!(f, "(. {} {})", object.get_value(), name.get_value()),
+            Self::Set(object, name, v) => write!(
+                f,
+                "(= (. {} {}) {})",
+                object.get_value(),
+                name.get_value(),
+                v.get_value()
+            ),
+            Self::Super(method, _) => write!(f, "(. super {})", method.get_value()),
+            Self::List(items) => {
+                f.write_str("(list")?;
+                for item in items {
+                    write!(f, " {}", item.get_value())?;
+                }
+                f.write_char(')')
+            }
+            Self::Index(list, index) => {
+                write!(f, "(index {} {})", list.get_value(), index.get_value())
+            }
+            Self::SetIndex(list, index, v) => write!(
+                f,
+                "(= (index {} {}) {})",
+                list.get_value(),
+                index.get_value(),
+                v.get_value()
+            ),
+        }
+    }
+}
+
+/// A function or method: `fun name(params...) { body }`.
+#[derive(Debug, Clone)]
+pub struct FunctionDecl<'a> {
+    pub name: WithSpan<Cow<'a, str>>,
+    pub params: Vec<WithSpan<Cow<'a, str>>>,
+    pub body: Vec<WithSpan<Stmt<'a>>>,
+}
+
+impl fmt::Display for FunctionDecl<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(fun {} (", self.name.get_value())?;
+        for (i, param) in self.params.iter().enumerate() {
+            if i > 0 {
+                f.write_char(' ')?;
+            }
+            f.write_str(param.get_value())?;
         }
+        f.write_char(')')?;
+        for stmt in &self.body {
+            write!(f, " {}", stmt.get_value())?;
+        }
+        f.write_char(')')
+    }
+}
+
+/// `class name < superclass { methods... }`.
+#[derive(Debug, Clone)]
+pub struct ClassDecl<'a> {
+    pub name: WithSpan<Cow<'a, str>>,
+    pub superclass: Option<WithSpan<Expr<'a>>>,
+    pub methods: Vec<Rc<FunctionDecl<'a>>>,
+}
+
+impl fmt::Display for ClassDecl<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(class {}", self.name.get_value())?;
+        if let Some(superclass) = &self.superclass {
+            write!(f, " < {}", superclass.get_value())?;
+        }
+        for method in &self.methods {
+            write!(f, " {method}")?;
+        }
+        f.write_char(')')
     }
 }
 
+#[derive(Debug, Clone)]
 pub enum Stmt<'a> {
+    /// An expression followed by `;`, evaluated for its effects.
     Expression(Box<WithSpan<Expr<'a>>>),
+    Print(Box<WithSpan<Expr<'a>>>),
+    /// `var name = init;`, where the initializer is optional.
+    Var(WithSpan<Cow<'a, str>>, Option<Box<WithSpan<Expr<'a>>>>),
+    /// `{ stmts... }`, which opens a scope.
+    Block(Vec<WithSpan<Stmt<'a>>>),
+    /// `if (cond) then else otherwise`.
+    If(
+        Box<WithSpan<Expr<'a>>>,
+        Box<WithSpan<Stmt<'a>>>,
+        Option<Box<WithSpan<Stmt<'a>>>>,
+    ),
+    /// `while (cond) body`. `for` loops are desugared into blocks
+    /// containing one.
+    While(Box<WithSpan<Expr<'a>>>, Box<WithSpan<Stmt<'a>>>),
+    Function(Rc<FunctionDecl<'a>>),
+    Return(Option<Box<WithSpan<Expr<'a>>>>),
+    Class(ClassDecl<'a>),
+    /// `import "path" as name;`, where `name` defaults to the file stem.
+    Import(WithSpan<Cow<'a, str>>, WithSpan<Cow<'a, str>>),
 }
 
 impl fmt::Display for Stmt<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Expression(v) => write!(f, "{}", v.get_value()),
+            Self::Print(v) => write!(f, "(print {})", v.get_value()),
+            Self::Var(name, None) => write!(f, "(var {})", name.get_value()),
+            Self::Var(name, Some(v)) => write!(f, "(var {} {})", name.get_value(), v.get_value()),
+            Self::Block(stmts) => {
+                f.write_str("(block")?;
+                for stmt in stmts {
+                    write!(f, " {}", stmt.get_value())?;
+                }
+                f.write_char(')')
+            }
+            Self::If(cond, then, None) => {
+                write!(f, "(if {} {})", cond.get_value(), then.get_value())
+            }
+            Self::If(cond, then, Some(otherwise)) => write!(
+                f,
+                "(if {} {} {})",
+                cond.get_value(),
+                then.get_value(),
+                otherwise.get_value()
+            ),
+            Self::While(cond, body) => {
+                write!(f, "(while {} {})", cond.get_value(), body.get_value())
+            }
+            Self::Function(decl) => write!(f, "{decl}"),
+            Self::Return(None) => f.write_str("(return)"),
+            Self::Return(Some(v)) => write!(f, "(return {})", v.get_value()),
+            Self::Class(decl) => write!(f, "{decl}"),
+            Self::Import(path, name) => {
+                write!(f, "(import \"{}\" {})", path.get_value(), name.get_value())
+            }
         }
     }
 }
 
+/// A parsed program: its top-level statements in order.
 pub type Ast<'a> = Vec<WithSpan<Stmt<'a>>>;
+
+fn owned_name(name: WithSpan<Cow<'_, str>>) -> WithSpan<Cow<'static, str>> {
+    name.map(|v| Cow::Owned(v.into_owned()))
+}
+
+fn owned_expr(expr: WithSpan<Expr<'_>>) -> Box<WithSpan<Expr<'static>>> {
+    Box::new(expr.map(Expr::into_owned))
+}
+
+fn owned_stmt(stmt: WithSpan<Stmt<'_>>) -> Box<WithSpan<Stmt<'static>>> {
+    Box::new(stmt.map(Stmt::into_owned))
+}
+
+impl Expr<'_> {
+    /// Detaches the expression from the source text it was parsed from.
+    pub fn into_owned(self) -> Expr<'static> {
+        match self {
+            Self::Binary(a, o, b) => Expr::Binary(owned_expr(*a), o, owned_expr(*b)),
+            Self::Grouping(g) => Expr::Grouping(owned_expr(*g)),
+            Self::Number(v) => Expr::Number(v),
+            Self::Boolean(v) => Expr::Boolean(v),
+            Self::Nil => Expr::Nil,
+            Self::This(slot) => Expr::This(slot),
+            Self::String(v) => Expr::String(Cow::Owned(v.into_owned())),
+            Self::Interpolation(parts) => Expr::Interpolation(
+                parts
+                    .into_iter()
+                    .map(|part| part.map(Expr::into_owned))
+                    .collect(),
+            ),
+            Self::Unary(o, v) => Expr::Unary(o, owned_expr(*v)),
+            Self::Variable(name, slot) => Expr::Variable(Cow::Owned(name.into_owned()), slot),
+            Self::Assign(name, v, slot) => Expr::Assign(owned_name(name), owned_expr(*v), slot),
+            Self::Logical(a, o, b) => Expr::Logical(owned_expr(*a), o, owned_expr(*b)),
+            Self::Call(callee, args) => Expr::Call(
+                owned_expr(*callee),
+                args.into_iter()
+                    .map(|arg| arg.map(Expr::into_owned))
+                    .collect(),
+            ),
+            Self::Get(object, name) => Expr::Get(owned_expr(*object), owned_name(name)),
+            Self::Set(object, name, v) => {
+                Expr::Set(owned_expr(*object), owned_name(name), owned_expr(*v))
+            }
+            Self::Super(method, slot) => Expr::Super(owned_name(method), slot),
+            Self::List(items) => Expr::List(
+                items
+                    .into_iter()
+                    .map(|item| item.map(Expr::into_owned))
+                    .collect(),
+            ),
+            Self::Index(list, index) => Expr::Index(owned_expr(*list), owned_expr(*index)),
+            Self::SetIndex(list, index, v) => {
+                Expr::SetIndex(owned_expr(*list), owned_expr(*index), owned_expr(*v))
+            }
+        }
+    }
+}
+
+fn owned_function(decl: Rc<FunctionDecl<'_>>) -> Rc<FunctionDecl<'static>> {
+    Rc::new(
+        Rc::try_unwrap(decl)
+            .unwrap_or_else(|decl| (*decl).clone())
+            .into_owned(),
+    )
+}
+
+impl FunctionDecl<'_> {
+    /// Detaches the declaration from the source text it was parsed from.
+    pub fn into_owned(self) -> FunctionDecl<'static> {
+        FunctionDecl {
+            name: owned_name(self.name),
+            params: self.params.into_iter().map(owned_name).collect(),
+            body: self
+                .body
+                .into_iter()
+                .map(|stmt| stmt.map(Stmt::into_owned))
+                .collect(),
+        }
+    }
+}
+
+impl Stmt<'_> {
+    /// Detaches the statement from the source text it was parsed from.
+    pub fn into_owned(self) -> Stmt<'static> {
+        match self {
+            Self::Expression(v) => Stmt::Expression(owned_expr(*v)),
+            Self::Print(v) => Stmt::Print(owned_expr(*v)),
+            Self::Var(name, v) => Stmt::Var(owned_name(name), v.map(|v| owned_expr(*v))),
+            Self::Block(stmts) => Stmt::Block(
+                stmts
+                    .into_iter()
+                    .map(|stmt| stmt.map(Stmt::into_owned))
+                    .collect(),
+            ),
+            Self::If(cond, then, otherwise) => Stmt::If(
+                owned_expr(*cond),
+                owned_stmt(*then),
+                otherwise.map(|v| owned_stmt(*v)),
+            ),
+            Self::While(cond, body) => Stmt::While(owned_expr(*cond), owned_stmt(*body)),
+            Self::Function(decl) => Stmt::Function(owned_function(decl)),
+            Self::Return(v) => Stmt::Return(v.map(|v| owned_expr(*v))),
+            Self::Class(decl) => Stmt::Class(ClassDecl {
+                name: owned_name(decl.name),
+                superclass: decl.superclass.map(|v| v.map(Expr::into_owned)),
+                methods: decl.methods.into_iter().map(owned_function).collect(),
+            }),
+            Self::Import(path, name) => Stmt::Import(owned_name(path), owned_name(name)),
+        }
+    }
+}
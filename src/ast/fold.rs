@@ -0,0 +1,163 @@
+//! Rebuilding a syntax tree node by node.
+
+use std::rc::Rc;
+
+use crate::WithSpan;
+
+use super::{ClassDecl, Expr, FunctionDecl, Stmt};
+
+/// Consumes a syntax tree and builds a new one, for rewrites that replace
+/// nodes with nodes of another kind.
+///
+/// Every method defaults to the matching `walk_*` function, which folds the
+/// node's children and rebuilds the node around them with the same span.
+pub trait Fold<'a> {
+    fn fold_stmt(&mut self, stmt: WithSpan<Stmt<'a>>) -> WithSpan<Stmt<'a>> {
+        walk_stmt(self, stmt)
+    }
+
+    fn fold_expr(&mut self, expr: WithSpan<Expr<'a>>) -> WithSpan<Expr<'a>> {
+        walk_expr(self, expr)
+    }
+
+    fn fold_function(&mut self, decl: FunctionDecl<'a>) -> FunctionDecl<'a> {
+        walk_function(self, decl)
+    }
+
+    fn fold_class(&mut self, decl: ClassDecl<'a>) -> ClassDecl<'a> {
+        walk_class(self, decl)
+    }
+}
+
+/// Folds a boxed expression, reusing its allocation.
+fn fold_boxed<'a, F: Fold<'a> + ?Sized>(
+    folder: &mut F,
+    mut expr: Box<WithSpan<Expr<'a>>>,
+) -> Box<WithSpan<Expr<'a>>> {
+    *expr = folder.fold_expr(*expr);
+    expr
+}
+
+fn fold_all<'a, F: Fold<'a> + ?Sized>(
+    folder: &mut F,
+    exprs: Vec<WithSpan<Expr<'a>>>,
+) -> Vec<WithSpan<Expr<'a>>> {
+    exprs
+        .into_iter()
+        .map(|expr| folder.fold_expr(expr))
+        .collect()
+}
+
+/// Folds a function shared through `Rc`, copying it if it is also
+/// referenced elsewhere.
+fn fold_shared<'a, F: Fold<'a> + ?Sized>(
+    folder: &mut F,
+    decl: Rc<FunctionDecl<'a>>,
+) -> Rc<FunctionDecl<'a>> {
+    let decl = Rc::try_unwrap(decl).unwrap_or_else(|decl| (*decl).clone());
+    Rc::new(folder.fold_function(decl))
+}
+
+/// Folds the statements and expressions directly inside `stmt`.
+pub fn walk_stmt<'a, F: Fold<'a> + ?Sized>(
+    folder: &mut F,
+    stmt: WithSpan<Stmt<'a>>,
+) -> WithSpan<Stmt<'a>> {
+    stmt.map(|stmt| match stmt {
+        Stmt::Expression(expr) => Stmt::Expression(fold_boxed(folder, expr)),
+        Stmt::Print(expr) => Stmt::Print(fold_boxed(folder, expr)),
+        Stmt::Var(name, init) => Stmt::Var(name, init.map(|init| fold_boxed(folder, init))),
+        Stmt::Block(stmts) => Stmt::Block(
+            stmts
+                .into_iter()
+                .map(|stmt| folder.fold_stmt(stmt))
+                .collect(),
+        ),
+        Stmt::If(cond, then, otherwise) => Stmt::If(
+            fold_boxed(folder, cond),
+            Box::new(folder.fold_stmt(*then)),
+            otherwise.map(|otherwise| Box::new(folder.fold_stmt(*otherwise))),
+        ),
+        Stmt::While(cond, body) => {
+            Stmt::While(fold_boxed(folder, cond), Box::new(folder.fold_stmt(*body)))
+        }
+        Stmt::Function(decl) => Stmt::Function(fold_shared(folder, decl)),
+        Stmt::Return(value) => Stmt::Return(value.map(|value| fold_boxed(folder, value))),
+        Stmt::Class(decl) => Stmt::Class(folder.fold_class(decl)),
+        Stmt::Import(path, name) => Stmt::Import(path, name),
+    })
+}
+
+/// Folds the subexpressions of `expr`.
+pub fn walk_expr<'a, F: Fold<'a> + ?Sized>(
+    folder: &mut F,
+    expr: WithSpan<Expr<'a>>,
+) -> WithSpan<Expr<'a>> {
+    expr.map(|expr| match expr {
+        Expr::Number(_)
+        | Expr::Boolean(_)
+        | Expr::Nil
+        | Expr::This(_)
+        | Expr::String(_)
+        | Expr::Variable(..)
+        | Expr::Super(..) => expr,
+        Expr::Binary(left, operator, right) => Expr::Binary(
+            fold_boxed(folder, left),
+            operator,
+            fold_boxed(folder, right),
+        ),
+        Expr::Logical(left, operator, right) => Expr::Logical(
+            fold_boxed(folder, left),
+            operator,
+            fold_boxed(folder, right),
+        ),
+        Expr::Grouping(inner) => Expr::Grouping(fold_boxed(folder, inner)),
+        Expr::Unary(operator, inner) => Expr::Unary(operator, fold_boxed(folder, inner)),
+        Expr::Interpolation(parts) => Expr::Interpolation(fold_all(folder, parts)),
+        Expr::List(items) => Expr::List(fold_all(folder, items)),
+        Expr::Assign(name, value, slot) => Expr::Assign(name, fold_boxed(folder, value), slot),
+        Expr::Call(callee, args) => Expr::Call(fold_boxed(folder, callee), fold_all(folder, args)),
+        Expr::Get(object, name) => Expr::Get(fold_boxed(folder, object), name),
+        Expr::Set(object, name, value) => {
+            Expr::Set(fold_boxed(folder, object), name, fold_boxed(folder, value))
+        }
+        Expr::Index(list, index) => {
+            Expr::Index(fold_boxed(folder, list), fold_boxed(folder, index))
+        }
+        Expr::SetIndex(list, index, value) => Expr::SetIndex(
+            fold_boxed(folder, list),
+            fold_boxed(folder, index),
+            fold_boxed(folder, value),
+        ),
+    })
+}
+
+/// Folds the body of a function.
+pub fn walk_function<'a, F: Fold<'a> + ?Sized>(
+    folder: &mut F,
+    decl: FunctionDecl<'a>,
+) -> FunctionDecl<'a> {
+    FunctionDecl {
+        body: decl
+            .body
+            .into_iter()
+            .map(|stmt| folder.fold_stmt(stmt))
+            .collect(),
+        ..decl
+    }
+}
+
+/// Folds the superclass expression and the methods of a class.
+pub fn walk_class<'a, F: Fold<'a> + ?Sized>(folder: &mut F, decl: ClassDecl<'a>) -> ClassDecl<'a> {
+    ClassDecl {
+        name: decl.name,
+        superclass: decl
+            .superclass
+            .map(|superclass| folder.fold_expr(superclass)),
+        methods: decl
+            .methods
+            .into_iter()
+            .map(|method| fold_shared(folder, method))
+            .collect(),
+    }
+}
@@ -0,0 +1,125 @@
+//! Read-only traversal of a syntax tree.
+
+use crate::WithSpan;
+
+use super::{ClassDecl, Expr, FunctionDecl, Stmt};
+
+/// Walks a syntax tree by shared reference.
+///
+/// Every method defaults to the matching `walk_*` function, which visits
+/// the node's children. Override a method to act on a node, and call the
+/// `walk_*` function from it to keep descending.
+pub trait Visitor<'a> {
+    fn visit_stmt(&mut self, stmt: &WithSpan<Stmt<'a>>) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &WithSpan<Expr<'a>>) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_function(&mut self, decl: &FunctionDecl<'a>) {
+        walk_function(self, decl);
+    }
+
+    fn visit_class(&mut self, decl: &ClassDecl<'a>) {
+        walk_class(self, decl);
+    }
+}
+
+/// Visits the statements and expressions directly inside `stmt`.
+pub fn walk_stmt<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, stmt: &WithSpan<Stmt<'a>>) {
+    match stmt.get_value() {
+        Stmt::Expression(expr) | Stmt::Print(expr) => visitor.visit_expr(expr),
+        Stmt::Var(_, init) => {
+            if let Some(init) = init {
+                visitor.visit_expr(init);
+            }
+        }
+        Stmt::Block(stmts) => {
+            for stmt in stmts {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        Stmt::If(cond, then, otherwise) => {
+            visitor.visit_expr(cond);
+            visitor.visit_stmt(then);
+            if let Some(otherwise) = otherwise {
+                visitor.visit_stmt(otherwise);
+            }
+        }
+        Stmt::While(cond, body) => {
+            visitor.visit_expr(cond);
+            visitor.visit_stmt(body);
+        }
+        Stmt::Function(decl) => visitor.visit_function(decl),
+        Stmt::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        Stmt::Class(decl) => visitor.visit_class(decl),
+        Stmt::Import(..) => {}
+    }
+}
+
+/// Visits the subexpressions of `expr`.
+pub fn walk_expr<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, expr: &WithSpan<Expr<'a>>) {
+    match expr.get_value() {
+        Expr::Number(_)
+        | Expr::Boolean(_)
+        | Expr::Nil
+        | Expr::This(_)
+        | Expr::String(_)
+        | Expr::Variable(..)
+        | Expr::Super(..) => {}
+        Expr::Binary(left, _, right) | Expr::Logical(left, _, right) => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        Expr::Grouping(inner) | Expr::Unary(_, inner) => visitor.visit_expr(inner),
+        Expr::Interpolation(items) | Expr::List(items) => {
+            for item in items {
+                visitor.visit_expr(item);
+            }
+        }
+        Expr::Assign(_, value, _) => visitor.visit_expr(value),
+        Expr::Call(callee, args) => {
+            visitor.visit_expr(callee);
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        Expr::Get(object, _) => visitor.visit_expr(object),
+        Expr::Set(object, _, value) => {
+            visitor.visit_expr(object);
+            visitor.visit_expr(value);
+        }
+        Expr::Index(list, index) => {
+            visitor.visit_expr(list);
+            visitor.visit_expr(index);
+        }
+        Expr::SetIndex(list, index, value) => {
+            visitor.visit_expr(list);
+            visitor.visit_expr(index);
+            visitor.visit_expr(value);
+        }
+    }
+}
+
+/// Visits the body of a function.
+pub fn walk_function<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, decl: &FunctionDecl<'a>) {
+    for stmt in &decl.body {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+/// Visits the superclass expression and the methods of a class.
+pub fn walk_class<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, decl: &ClassDecl<'a>) {
+    if let Some(superclass) = &decl.superclass {
+        visitor.visit_expr(superclass);
+    }
+    for method in &decl.methods {
+        visitor.visit_function(method);
+    }
+}
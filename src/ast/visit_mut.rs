@@ -0,0 +1,128 @@
+//! In-place traversal of a syntax tree.
+
+use std::rc::Rc;
+
+use crate::WithSpan;
+
+use super::{ClassDecl, Expr, FunctionDecl, Stmt};
+
+/// Walks a syntax tree by mutable reference, for rewrites that keep the
+/// tree's shape.
+///
+/// Every method defaults to the matching `walk_*` function, which visits
+/// the node's children. Function declarations are shared through `Rc`; one
+/// that is also referenced elsewhere is copied before it is visited.
+pub trait VisitorMut<'a> {
+    fn visit_stmt_mut(&mut self, stmt: &mut WithSpan<Stmt<'a>>) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut WithSpan<Expr<'a>>) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_function_mut(&mut self, decl: &mut FunctionDecl<'a>) {
+        walk_function(self, decl);
+    }
+
+    fn visit_class_mut(&mut self, decl: &mut ClassDecl<'a>) {
+        walk_class(self, decl);
+    }
+}
+
+/// Visits the statements and expressions directly inside `stmt`.
+pub fn walk_stmt<'a, V: VisitorMut<'a> + ?Sized>(visitor: &mut V, stmt: &mut WithSpan<Stmt<'a>>) {
+    match stmt.get_value_mut() {
+        Stmt::Expression(expr) | Stmt::Print(expr) => visitor.visit_expr_mut(expr),
+        Stmt::Var(_, init) => {
+            if let Some(init) = init {
+                visitor.visit_expr_mut(init);
+            }
+        }
+        Stmt::Block(stmts) => {
+            for stmt in stmts {
+                visitor.visit_stmt_mut(stmt);
+            }
+        }
+        Stmt::If(cond, then, otherwise) => {
+            visitor.visit_expr_mut(cond);
+            visitor.visit_stmt_mut(then);
+            if let Some(otherwise) = otherwise {
+                visitor.visit_stmt_mut(otherwise);
+            }
+        }
+        Stmt::While(cond, body) => {
+            visitor.visit_expr_mut(cond);
+            visitor.visit_stmt_mut(body);
+        }
+        Stmt::Function(decl) => visitor.visit_function_mut(Rc::make_mut(decl)),
+        Stmt::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expr_mut(value);
+            }
+        }
+        Stmt::Class(decl) => visitor.visit_class_mut(decl),
+        Stmt::Import(..) => {}
+    }
+}
+
+/// Visits the subexpressions of `expr`.
+pub fn walk_expr<'a, V: VisitorMut<'a> + ?Sized>(visitor: &mut V, expr: &mut WithSpan<Expr<'a>>) {
+    match expr.get_value_mut() {
+        Expr::Number(_)
+        | Expr::Boolean(_)
+        | Expr::Nil
+        | Expr::This(_)
+        | Expr::String(_)
+        | Expr::Variable(..)
+        | Expr::Super(..) => {}
+        Expr::Binary(left, _, right) | Expr::Logical(left, _, right) => {
+            visitor.visit_expr_mut(left);
+            visitor.visit_expr_mut(right);
+        }
+        Expr::Grouping(inner) | Expr::Unary(_, inner) => visitor.visit_expr_mut(inner),
+        Expr::Interpolation(items) | Expr::List(items) => {
+            for item in items {
+                visitor.visit_expr_mut(item);
+            }
+        }
+        Expr::Assign(_, value, _) => visitor.visit_expr_mut(value),
+        Expr::Call(callee, args) => {
+            visitor.visit_expr_mut(callee);
+            for arg in args {
+                visitor.visit_expr_mut(arg);
+            }
+        }
+        Expr::Get(object, _) => visitor.visit_expr_mut(object),
+        Expr::Set(object, _, value) => {
+            visitor.visit_expr_mut(object);
+            visitor.visit_expr_mut(value);
+        }
+        Expr::Index(list, index) => {
+            visitor.visit_expr_mut(list);
+            visitor.visit_expr_mut(index);
+        }
+        Expr::SetIndex(list, index, value) => {
+            visitor.visit_expr_mut(list);
+            visitor.visit_expr_mut(index);
+            visitor.visit_expr_mut(value);
+        }
+    }
+}
+
+/// Visits the body of a function.
+pub fn walk_function<'a, V: VisitorMut<'a> + ?Sized>(visitor: &mut V, decl: &mut FunctionDecl<'a>) {
+    for stmt in &mut decl.body {
+        visitor.visit_stmt_mut(stmt);
+    }
+}
+
+/// Visits the superclass expression and the methods of a class.
+pub fn walk_class<'a, V: VisitorMut<'a> + ?Sized>(visitor: &mut V, decl: &mut ClassDecl<'a>) {
+    if let Some(superclass) = &mut decl.superclass {
+        visitor.visit_expr_mut(superclass);
+    }
+    for method in &mut decl.methods {
+        visitor.visit_function_mut(Rc::make_mut(method));
+    }
+}
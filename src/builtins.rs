@@ -0,0 +1,110 @@
+//! Native functions every program starts with.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    mem,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    callable::NativeFunction,
+    number::format_number,
+    value::{List, Value},
+};
+
+/// Bytes held by a program's objects, checked against
+/// [`Limits::max_allocation`](crate::Limits::max_allocation).
+pub(crate) type Meter = Rc<Cell<usize>>;
+
+#[inline]
+pub(crate) fn charge(meter: &Meter, bytes: usize) {
+    meter.set(meter.get().saturating_add(bytes));
+}
+
+/// Converts a Lox number into an index into a sequence of length `len`.
+/// `insert` may also target one past the end.
+pub(crate) fn list_index(index: f64, len: usize, inclusive: bool) -> Result<usize, String> {
+    if index.fract() != 0.0 {
+        return Err(format!(
+            "List index must be an integer, got {}.",
+            format_number(index)
+        ));
+    }
+    let end = if inclusive { len + 1 } else { len };
+    if index < 0.0 || index >= end as f64 {
+        return Err(format!(
+            "List index {} out of range for length {len}.",
+            format_number(index)
+        ));
+    }
+    Ok(index as usize)
+}
+
+fn list<'v>(name: &str, value: &'v Value) -> Result<&'v Rc<RefCell<List>>, String> {
+    match value {
+        Value::List(list) => Ok(list),
+        v => Err(format!("{name}() expects a list, got {}.", v.type_name())),
+    }
+}
+
+fn number(name: &str, value: &Value) -> Result<f64, String> {
+    value
+        .as_number()
+        .ok_or_else(|| format!("{name}() expects a number, got {}.", value.type_name()))
+}
+
+fn native<F>(globals: &mut HashMap<String, Value>, name: &str, arity: usize, func: F)
+where
+    F: Fn(&[Value]) -> Result<Value, String> + 'static,
+{
+    let function = NativeFunction::new(name, arity, func);
+    globals.insert(name.to_string(), Value::NativeFunction(Rc::new(function)));
+}
+
+pub(crate) fn globals(meter: &Meter) -> HashMap<String, Value> {
+    let mut globals = HashMap::new();
+    native(&mut globals, "clock", 0, |_| {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| e.to_string())?;
+        Ok(Value::Number(now.as_secs_f64()))
+    });
+    native(&mut globals, "len", 1, |args| match &args[0] {
+        Value::List(list) => Ok(Value::Number(list.borrow().len() as f64)),
+        Value::String(s) => Ok(Value::Number(s.chars().count() as f64)),
+        v => Err(format!(
+            "len() expects a list or string, got {}.",
+            v.type_name()
+        )),
+    });
+    let m = Rc::clone(meter);
+    native(&mut globals, "push", 2, move |args| {
+        list("push", &args[0])?.borrow_mut().push(args[1].clone());
+        charge(&m, mem::size_of::<Value>());
+        Ok(Value::Nil)
+    });
+    native(&mut globals, "pop", 1, |args| {
+        list("pop", &args[0])?
+            .borrow_mut()
+            .pop()
+            .ok_or_else(|| "Can't pop from an empty list.".to_string())
+    });
+    let m = Rc::clone(meter);
+    native(&mut globals, "insert", 3, move |args| {
+        let list = list("insert", &args[0])?;
+        let len = list.borrow().len();
+        let index = list_index(number("insert", &args[1])?, len, true)?;
+        list.borrow_mut().insert(index, args[2].clone());
+        charge(&m, mem::size_of::<Value>());
+        Ok(Value::Nil)
+    });
+    native(&mut globals, "remove", 2, |args| {
+        let list = list("remove", &args[0])?;
+        let len = list.borrow().len();
+        let index = list_index(number("remove", &args[1])?, len, false)?;
+        Ok(list.borrow_mut().remove(index))
+    });
+    globals
+}
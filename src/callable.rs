@@ -0,0 +1,93 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use crate::{
+    ast::{FunctionDecl, Slot},
+    environment::Environment,
+    module::Globals,
+    value::Value,
+};
+
+pub type NativeFn = dyn Fn(&[Value]) -> Result<Value, String>;
+
+/// A user-defined function together with the environment it closes over.
+pub struct Function {
+    pub(crate) decl: Rc<FunctionDecl<'static>>,
+    pub(crate) closure: Option<Rc<RefCell<Environment>>>,
+    pub(crate) globals: Globals,
+    pub(crate) is_initializer: bool,
+}
+
+impl Function {
+    /// Returns a copy of the method whose closure has `this` bound to `instance`.
+    pub fn bind(&self, instance: Value) -> Function {
+        let mut environment = Environment::new(self.closure.clone());
+        environment.define(instance);
+        Function {
+            decl: Rc::clone(&self.decl),
+            closure: Some(Rc::new(RefCell::new(environment))),
+            globals: Rc::clone(&self.globals),
+            is_initializer: self.is_initializer,
+        }
+    }
+
+    /// The instance a bound method was bound to.
+    pub(crate) fn this(&self) -> Value {
+        match &self.closure {
+            Some(closure) => closure.borrow().get_at(Slot::new(0, 0)),
+            None => Value::Nil,
+        }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        self.decl.name.get_value()
+    }
+
+    #[inline]
+    pub fn arity(&self) -> usize {
+        self.decl.params.len()
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.name())
+    }
+}
+
+/// A function implemented in Rust and exposed to Lox code.
+pub struct NativeFunction {
+    pub(crate) name: String,
+    pub(crate) arity: usize,
+    pub(crate) func: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new<N, F>(name: N, arity: usize, func: F) -> Self
+    where
+        N: Into<String>,
+        F: Fn(&[Value]) -> Result<Value, String> + 'static,
+    {
+        Self {
+            name: name.into(),
+            arity,
+            func: Box::new(func),
+        }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
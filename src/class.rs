@@ -0,0 +1,89 @@
+use std::{cell::RefCell, collections::HashMap, fmt, mem, rc::Rc};
+
+use crate::{
+    callable::Function,
+    value::{drop_values, Value},
+};
+
+pub struct Class {
+    pub(crate) name: String,
+    pub(crate) superclass: Option<Rc<Class>>,
+    pub(crate) methods: HashMap<String, Rc<Function>>,
+}
+
+impl Class {
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn find_method(&self, name: &str) -> Option<Rc<Function>> {
+        if let Some(method) = self.methods.get(name) {
+            return Some(Rc::clone(method));
+        }
+        self.superclass.as_ref()?.find_method(name)
+    }
+
+    /// The number of arguments expected when the class is called, taken
+    /// from its `init` method if it has one.
+    pub fn arity(&self) -> usize {
+        self.find_method("init").map_or(0, |init| init.arity())
+    }
+}
+
+impl fmt::Debug for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
+
+pub struct Instance {
+    pub(crate) class: Rc<Class>,
+    pub(crate) fields: HashMap<String, Value>,
+}
+
+impl Instance {
+    #[inline]
+    pub fn new(class: Rc<Class>) -> Self {
+        Self {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    pub fn class(&self) -> &Rc<Class> {
+        &self.class
+    }
+
+    /// Looks up a field first and falls back to a method bound to `this`.
+    pub fn get(this: &Rc<RefCell<Instance>>, name: &str) -> Option<Value> {
+        let instance = this.borrow();
+        if let Some(v) = instance.fields.get(name) {
+            return Some(v.clone());
+        }
+        let method = instance.class.find_method(name)?;
+        Some(Value::Function(Rc::new(
+            method.bind(Value::Instance(Rc::clone(this))),
+        )))
+    }
+
+    #[inline]
+    pub fn set<K: Into<String>>(&mut self, name: K, value: Value) {
+        self.fields.insert(name.into(), value);
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        if !self.fields.is_empty() {
+            drop_values(mem::take(&mut self.fields).into_values());
+        }
+    }
+}
+
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{} instance>", self.class.name)
+    }
+}
@@ -0,0 +1,327 @@
+use std::{borrow::Cow, fmt, fmt::Write, ops::Range};
+
+use crate::{
+    source::{SourceFile, SourceMap},
+    FileId,
+};
+
+/// A secondary span shown alongside the primary one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+/// A compile-time error with enough location information to be rendered
+/// either as the single-line `[line N] Error at X: message` format or as a
+/// source excerpt with the offending range underlined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    code: Option<&'static str>,
+    message: String,
+    line: usize,
+    span: Range<usize>,
+    file: FileId,
+    at: Option<String>,
+    label: Option<String>,
+    secondary: Vec<Label>,
+    notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error<M: Into<String>>(message: M, line: usize, span: Range<usize>) -> Self {
+        Self {
+            code: None,
+            message: message.into(),
+            line,
+            span,
+            file: FileId::default(),
+            at: None,
+            label: None,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    /// Sets the error code shown in the rich format, e.g. `E0101`.
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Sets the file `span` and secondary spans point into.
+    pub fn in_file(mut self, file: FileId) -> Self {
+        self.file = file;
+        self
+    }
+
+    /// Sets what the short format prints after `Error at`.
+    pub fn at<S: Into<String>>(mut self, at: S) -> Self {
+        self.at = Some(at.into());
+        self
+    }
+
+    /// Sets the text printed next to the primary underline.
+    pub fn with_label<S: Into<String>>(mut self, label: S) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_secondary<S: Into<String>>(mut self, span: Range<usize>, message: S) -> Self {
+        self.secondary.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note<S: Into<String>>(mut self, note: S) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    #[inline]
+    pub fn code(&self) -> Option<&'static str> {
+        self.code
+    }
+
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    #[inline]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    #[inline]
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    #[inline]
+    pub fn file(&self) -> FileId {
+        self.file
+    }
+
+    #[inline]
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
+}
+
+/// The codecrafters-compatible single-line format.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.at {
+            Some(at) => write!(f, "[line {}] Error at {}: {}", self.line, at, self.message),
+            None => write!(f, "[line {}] Error: {}", self.line, self.message),
+        }
+    }
+}
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Renders [`Diagnostic`]s against the source they were produced from.
+pub struct Renderer<'s> {
+    sources: Cow<'s, SourceMap>,
+    short: bool,
+    color: bool,
+}
+
+impl<'s> Renderer<'s> {
+    /// A renderer for diagnostics from a single file.
+    pub fn new(name: &str, source: &str) -> Self {
+        let mut sources = SourceMap::new();
+        sources.add(name, source);
+        Self::from_map(Cow::Owned(sources))
+    }
+
+    /// A renderer that shows each diagnostic in the file it points into.
+    pub fn from_map<S: Into<Cow<'s, SourceMap>>>(sources: S) -> Self {
+        Self {
+            sources: sources.into(),
+            short: false,
+            color: false,
+        }
+    }
+
+    /// Use the single-line `[line N] Error: ...` format instead of a
+    /// source excerpt.
+    pub fn short(mut self, short: bool) -> Self {
+        self.short = short;
+        self
+    }
+
+    /// Emit ANSI colour codes.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        if self.short {
+            return diagnostic.to_string();
+        }
+        let mut out = String::new();
+        self.render_into(&mut out, diagnostic)
+            .expect("writing to a String cannot fail");
+        out
+    }
+
+    fn paint(&self, style: &'static str) -> (&'static str, &'static str) {
+        if self.color {
+            (style, RESET)
+        } else {
+            ("", "")
+        }
+    }
+
+    fn render_into(&self, out: &mut String, d: &Diagnostic) -> fmt::Result {
+        let Some(file) = self.sources.get(d.file) else {
+            // Without its source, fall back to the single-line format.
+            return writeln!(out, "{d}");
+        };
+        let (red, red_end) = self.paint(RED);
+        let (blue, blue_end) = self.paint(BLUE);
+        let (bold, bold_end) = self.paint(BOLD);
+
+        let mut marks = vec![(d.span.clone(), '^', d.label.as_deref().unwrap_or(""))];
+        marks.extend(
+            d.secondary
+                .iter()
+                .map(|label| (label.span.clone(), '-', label.message.as_str())),
+        );
+        marks.sort_by_key(|(span, _, _)| span.start);
+        let mut lines = marks
+            .iter()
+            .map(|(span, _, _)| locate(file, span.start).0)
+            .collect::<Vec<_>>();
+        lines.sort_unstable();
+        lines.dedup();
+        let width = lines.last().map_or(1, |line| line.to_string().len());
+
+        let (line, column) = locate(file, d.span.start);
+        match d.code {
+            Some(code) => write!(out, "{red}error[{code}]{red_end}")?,
+            None => write!(out, "{red}error{red_end}")?,
+        }
+        writeln!(out, "{bold}: {}{bold_end}", d.message)?;
+        writeln!(
+            out,
+            "{:width$}{blue}-->{blue_end} {}:{}:{}",
+            "",
+            file.name(),
+            line,
+            column
+        )?;
+        writeln!(out, "{:width$} {blue}|{blue_end}", "")?;
+        for line in lines {
+            let text = file.line_text(line).unwrap_or("");
+            writeln!(out, "{blue}{line:>width$} |{blue_end} {text}")?;
+            for (span, mark, message) in &marks {
+                let (start_line, column) = locate(file, span.start);
+                if start_line != line {
+                    continue;
+                }
+                let len = file
+                    .text()
+                    .get(span.start..span.end.min(file.text().len()))
+                    .unwrap_or("")
+                    .chars()
+                    .take_while(|&c| c != '\n')
+                    .count()
+                    .max(1);
+                let color = if *mark == '^' { red } else { blue };
+                let color_end = if *mark == '^' { red_end } else { blue_end };
+                write!(
+                    out,
+                    "{:width$} {blue}|{blue_end} {:pad$}{color}{}",
+                    "",
+                    "",
+                    mark.to_string().repeat(len),
+                    pad = column - 1
+                )?;
+                if message.is_empty() {
+                    writeln!(out, "{color_end}")?;
+                } else {
+                    writeln!(out, " {message}{color_end}")?;
+                }
+            }
+        }
+        for note in &d.notes {
+            writeln!(
+                out,
+                "{:width$} {blue}={blue_end} {bold}note{bold_end}: {note}",
+                ""
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// One-based line and column (in characters) of a byte offset.
+fn locate(file: &SourceFile, offset: usize) -> (usize, usize) {
+    let position = file.position(offset);
+    let start = file
+        .lines()
+        .line_span(position.line)
+        .map_or(0, |span| span.start);
+    let before = &file.text()[start..start + position.column - 1];
+    (position.line, before.chars().count() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Diagnostic, Renderer};
+    use crate::SourceMap;
+
+    #[test]
+    fn test_render() {
+        let source = "var a = 1;\nprint a +\n  b;\n";
+        let diagnostic = Diagnostic::error("Expect expression.", 2, 19..20)
+            .at("';'")
+            .with_label("here")
+            .with_secondary(11..16, "in this statement")
+            .with_note("an operand is missing");
+        assert_eq!(
+            diagnostic.to_string(),
+            "[line 2] Error at ';': Expect expression."
+        );
+        assert_eq!(
+            Renderer::new("test.lox", source)
+                .short(true)
+                .render(&diagnostic),
+            "[line 2] Error at ';': Expect expression."
+        );
+        assert_eq!(
+            Renderer::new("test.lox", source).render(&diagnostic),
+            "\
+error: Expect expression.
+ --> test.lox:2:9
+  |
+2 | print a +
+  | ----- in this statement
+  |         ^ here
+  = note: an operand is missing
+"
+        );
+    }
+
+    #[test]
+    fn test_render_in_file() {
+        let mut sources = SourceMap::new();
+        sources.add("main.lox", "import \"lib.lox\";\n");
+        let lib = sources.add("lib.lox", "var a;\nvar a;\n");
+        let diagnostic = Diagnostic::error("Already declared.", 2, 11..12).in_file(lib);
+        let rendered = Renderer::from_map(&sources).render(&diagnostic);
+        assert_eq!(
+            rendered.lines().take(3).collect::<Vec<_>>(),
+            ["error: Already declared.", " --> lib.lox:2:5", "  |"]
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{ast::Slot, value::Value};
+
+/// A local scope. Variables are stored in declaration order so that the
+/// slots computed by the resolver can be used to access them directly.
+#[derive(Debug, Default)]
+pub struct Environment {
+    pub(crate) values: Vec<Value>,
+    pub(crate) enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    #[inline]
+    pub fn new(enclosing: Option<Rc<RefCell<Environment>>>) -> Self {
+        Self {
+            values: Vec::new(),
+            enclosing,
+        }
+    }
+
+    #[inline]
+    pub fn define(&mut self, value: Value) {
+        self.values.push(value);
+    }
+
+    pub fn get_at(&self, slot: Slot) -> Value {
+        if slot.depth == 0 {
+            return self.values[slot.index].clone();
+        }
+        self.ancestor(slot.depth).borrow().values[slot.index].clone()
+    }
+
+    pub fn assign_at(&mut self, slot: Slot, value: Value) {
+        if slot.depth == 0 {
+            self.values[slot.index] = value;
+        } else {
+            self.ancestor(slot.depth).borrow_mut().values[slot.index] = value;
+        }
+    }
+
+    fn ancestor(&self, depth: usize) -> Rc<RefCell<Environment>> {
+        let mut environment = Rc::clone(
+            self.enclosing
+                .as_ref()
+                .expect("resolved scope depth exceeds environment chain"),
+        );
+        for _ in 1..depth {
+            let enclosing = Rc::clone(
+                environment
+                    .borrow()
+                    .enclosing
+                    .as_ref()
+                    .expect("resolved scope depth exceeds environment chain"),
+            );
+            environment = enclosing;
+        }
+        environment
+    }
+}
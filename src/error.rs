@@ -1,24 +1,597 @@
-use std::fmt;
+use std::{fmt, ops::Range};
 
+use crate::{limits::Limit, token::TokenKind, Diagnostic, FileId, SourceMap, WithSpan};
+
+/// A character sequence the lexer could not turn into a token.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub enum LoxError {
+pub enum LexError {
     InvalidChar(usize, char),
     UnterminatedStr(usize),
+    /// A backslash in a string that does not start a known escape. Holds
+    /// the sequence as written, e.g. `\q` or `\u{D800}`.
+    InvalidEscape(usize, String),
+    /// A `/*` comment still open at the end of the source.
+    UnterminatedComment(usize),
+    /// A malformed number literal as written, and what is wrong with it.
+    InvalidNumber(usize, String, &'static str),
 }
 
-impl fmt::Display for LoxError {
+impl fmt::Display for LexError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Error: {}", self.line(), self.message())
+    }
+}
+
+impl LexError {
+    #[inline]
+    pub fn line(&self) -> usize {
         match self {
-            Self::InvalidChar(line, c) => {
-                write!(f, "[line {}] Error: Unexpected character: {}", line, c)
+            Self::InvalidChar(line, _)
+            | Self::UnterminatedStr(line)
+            | Self::InvalidEscape(line, _)
+            | Self::UnterminatedComment(line)
+            | Self::InvalidNumber(line, ..) => *line,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Self::InvalidChar(_, c) => format!("Unexpected character: {}", c),
+            Self::UnterminatedStr(_) => "Unterminated string.".to_string(),
+            Self::InvalidEscape(_, escape) => format!("Invalid escape sequence: {}", escape),
+            Self::UnterminatedComment(_) => "Unterminated block comment.".to_string(),
+            Self::InvalidNumber(_, number, reason) => {
+                format!("Invalid number literal '{}': {}.", number, reason)
             }
-            Self::UnterminatedStr(line) => write!(f, "[line {}] Error: Unterminated string.", line),
         }
     }
+
+    #[inline]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidChar(..) => "E0001",
+            Self::UnterminatedStr(_) => "E0002",
+            Self::InvalidEscape(..) => "E0003",
+            Self::UnterminatedComment(_) => "E0004",
+            Self::InvalidNumber(..) => "E0005",
+        }
+    }
+
+    /// Converts the error into a [`Diagnostic`] pointing at `span`.
+    pub fn diagnostic(&self, span: Range<usize>) -> Diagnostic {
+        Diagnostic::error(self.message(), self.line(), span).with_code(self.code())
+    }
 }
 
-impl From<(usize, char)> for LoxError {
+impl From<(usize, char)> for LexError {
     fn from((line, c): (usize, char)) -> Self {
         Self::InvalidChar(line, c)
     }
 }
+
+/// A token sequence the parser could not turn into a syntax tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxError {
+    /// `expected` was required but another token was found.
+    ExpectedToken {
+        expected: TokenKind,
+        found: TokenKind,
+        lexeme: String,
+    },
+    /// An expression was required but another token was found.
+    ExpectedExpression {
+        found: TokenKind,
+        lexeme: String,
+    },
+    /// A token that cannot continue an expression.
+    UnexpectedToken {
+        found: TokenKind,
+        lexeme: String,
+    },
+    /// The input ended where `expected`, or an expression if `None`, was
+    /// required.
+    UnexpectedEof {
+        expected: Option<TokenKind>,
+    },
+    /// The left-hand side of `=` spanning `target` cannot be assigned to.
+    InvalidAssignmentTarget {
+        target: Range<usize>,
+    },
+    TooManyParameters {
+        lexeme: String,
+    },
+    TooManyArguments {
+        lexeme: String,
+    },
+    /// An `import` without `as` whose file stem is not a valid identifier.
+    InvalidModuleName {
+        path: String,
+    },
+    /// Statements and expressions nested more than
+    /// [`MAX_NESTING`](crate::MAX_NESTING) deep.
+    TooMuchNesting {
+        lexeme: String,
+    },
+}
+
+impl SyntaxError {
+    pub fn message(&self) -> String {
+        match self {
+            Self::ExpectedToken { expected, .. }
+            | Self::UnexpectedEof {
+                expected: Some(expected),
+            } => format!("Expect {}.", expected),
+            Self::ExpectedExpression { .. } | Self::UnexpectedEof { expected: None } => {
+                "Expect expression.".to_string()
+            }
+            Self::UnexpectedToken { .. } => "Unexpected token.".to_string(),
+            Self::InvalidAssignmentTarget { .. } => "Invalid assignment target.".to_string(),
+            Self::TooManyParameters { .. } => "Can't have more than 255 parameters.".to_string(),
+            Self::TooManyArguments { .. } => "Can't have more than 255 arguments.".to_string(),
+            Self::InvalidModuleName { .. } => {
+                "Module file name is not an identifier; name it with 'as'.".to_string()
+            }
+            Self::TooMuchNesting { .. } => "Too much nesting.".to_string(),
+        }
+    }
+
+    #[inline]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ExpectedToken { .. } => "E0101",
+            Self::ExpectedExpression { .. } => "E0102",
+            Self::UnexpectedToken { .. } => "E0103",
+            Self::UnexpectedEof { .. } => "E0104",
+            Self::InvalidAssignmentTarget { .. } => "E0105",
+            Self::TooManyParameters { .. } => "E0106",
+            Self::TooManyArguments { .. } => "E0107",
+            Self::InvalidModuleName { .. } => "E0108",
+            Self::TooMuchNesting { .. } => "E0109",
+        }
+    }
+
+    /// The token kind the parser was looking for, if it wanted one in
+    /// particular.
+    pub fn expected(&self) -> Option<TokenKind> {
+        match self {
+            Self::ExpectedToken { expected, .. } => Some(*expected),
+            Self::UnexpectedEof { expected } => *expected,
+            _ => None,
+        }
+    }
+
+    /// The kind of the offending token.
+    pub fn found(&self) -> Option<TokenKind> {
+        match self {
+            Self::ExpectedToken { found, .. }
+            | Self::ExpectedExpression { found, .. }
+            | Self::UnexpectedToken { found, .. } => Some(*found),
+            Self::UnexpectedEof { .. } => Some(TokenKind::Eof),
+            Self::InvalidAssignmentTarget { .. } => Some(TokenKind::Equal),
+            Self::InvalidModuleName { .. } => Some(TokenKind::String),
+            Self::TooManyParameters { .. }
+            | Self::TooManyArguments { .. }
+            | Self::TooMuchNesting { .. } => None,
+        }
+    }
+
+    /// How the book's messages refer to the offending token.
+    fn at(&self) -> String {
+        match self {
+            Self::ExpectedToken { lexeme, .. }
+            | Self::ExpectedExpression { lexeme, .. }
+            | Self::UnexpectedToken { lexeme, .. }
+            | Self::TooManyParameters { lexeme }
+            | Self::TooManyArguments { lexeme }
+            | Self::TooMuchNesting { lexeme } => format!("'{}'", lexeme),
+            Self::UnexpectedEof { .. } => "end".to_string(),
+            Self::InvalidAssignmentTarget { .. } => "'='".to_string(),
+            Self::InvalidModuleName { path } => format!("'\"{}\"'", path),
+        }
+    }
+}
+
+/// A scope error found by the resolver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+    ReadInOwnInitializer {
+        name: String,
+    },
+    AlreadyDeclared {
+        name: String,
+    },
+    ReturnFromTopLevel,
+    ReturnValueFromInitializer,
+    ThisOutsideClass,
+    SuperOutsideClass,
+    SuperWithoutSuperclass,
+    InheritFromSelf {
+        name: String,
+    },
+    /// Statements and expressions nested more than
+    /// [`MAX_NESTING`](crate::MAX_NESTING) deep.
+    TooMuchNesting,
+}
+
+impl ResolveError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::ReadInOwnInitializer { .. } => {
+                "Can't read local variable in its own initializer."
+            }
+            Self::AlreadyDeclared { .. } => "Already a variable with this name in this scope.",
+            Self::ReturnFromTopLevel => "Can't return from top-level code.",
+            Self::ReturnValueFromInitializer => "Can't return a value from an initializer.",
+            Self::ThisOutsideClass => "Can't use 'this' outside of a class.",
+            Self::SuperOutsideClass => "Can't use 'super' outside of a class.",
+            Self::SuperWithoutSuperclass => "Can't use 'super' in a class with no superclass.",
+            Self::InheritFromSelf { .. } => "A class can't inherit from itself.",
+            Self::TooMuchNesting => "Too much nesting.",
+        }
+    }
+
+    #[inline]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ReadInOwnInitializer { .. } => "E0201",
+            Self::AlreadyDeclared { .. } => "E0202",
+            Self::ReturnFromTopLevel => "E0203",
+            Self::ReturnValueFromInitializer => "E0204",
+            Self::ThisOutsideClass => "E0205",
+            Self::SuperOutsideClass => "E0206",
+            Self::SuperWithoutSuperclass => "E0207",
+            Self::InheritFromSelf { .. } => "E0208",
+            Self::TooMuchNesting => "E0209",
+        }
+    }
+
+    /// The token the error is reported at, if it is about one.
+    fn at(&self) -> Option<&str> {
+        match self {
+            Self::ReadInOwnInitializer { name }
+            | Self::AlreadyDeclared { name }
+            | Self::InheritFromSelf { name } => Some(name),
+            Self::ReturnFromTopLevel | Self::ReturnValueFromInitializer => Some("return"),
+            Self::ThisOutsideClass => Some("this"),
+            Self::SuperOutsideClass | Self::SuperWithoutSuperclass => Some("super"),
+            Self::TooMuchNesting => None,
+        }
+    }
+}
+
+/// Any error produced while turning source text into a running program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoxError {
+    Lex(WithSpan<LexError>),
+    Syntax(WithSpan<SyntaxError>),
+    Resolve(WithSpan<ResolveError>),
+    Runtime(RuntimeError),
+    /// The program was stopped by one of the interpreter's
+    /// [`Limits`](crate::Limits); [`RuntimeError::limit`] says which.
+    Limit(RuntimeError),
+}
+
+impl LoxError {
+    /// A stable identifier for the kind of error, e.g. `E0101`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Lex(e) => e.get_value().code(),
+            Self::Syntax(e) => e.get_value().code(),
+            Self::Resolve(e) => e.get_value().code(),
+            Self::Runtime(_) => "E0301",
+            Self::Limit(_) => "E0302",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Self::Lex(e) => e.get_value().message(),
+            Self::Syntax(e) => e.get_value().message(),
+            Self::Resolve(e) => e.get_value().message().to_string(),
+            Self::Runtime(e) | Self::Limit(e) => e.message().to_string(),
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        match self {
+            Self::Lex(e) => e.get_line(),
+            Self::Syntax(e) => e.get_line(),
+            Self::Resolve(e) => e.get_line(),
+            Self::Runtime(e) | Self::Limit(e) => e.line(),
+        }
+    }
+
+    /// The file [`LoxError::span`] points into.
+    pub fn file(&self) -> FileId {
+        match self {
+            Self::Lex(e) => e.get_file(),
+            Self::Syntax(e) => e.get_file(),
+            Self::Resolve(e) => e.get_file(),
+            Self::Runtime(e) | Self::Limit(e) => e.file(),
+        }
+    }
+
+    /// Byte range of the offending source, when known.
+    pub fn span(&self) -> Option<Range<usize>> {
+        match self {
+            Self::Lex(e) => Some(e.get_span()),
+            Self::Syntax(e) => Some(e.get_span()),
+            Self::Resolve(e) => Some(e.get_span()),
+            Self::Runtime(e) | Self::Limit(e) => e.span(),
+        }
+    }
+
+    /// Converts the error into a [`Diagnostic`]. Runtime errors without a
+    /// span point at the start of the source.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let span = self.span().unwrap_or(0..0);
+        let diagnostic = Diagnostic::error(self.message(), self.line(), span)
+            .with_code(self.code())
+            .in_file(self.file());
+        match self {
+            Self::Lex(_) => diagnostic,
+            Self::Runtime(e) | Self::Limit(e) => e
+                .traceback()
+                .lines()
+                .fold(diagnostic, |diagnostic, line| diagnostic.with_note(line)),
+            Self::Syntax(e) => {
+                let diagnostic = diagnostic.at(e.get_value().at());
+                match e.get_value() {
+                    SyntaxError::ExpectedToken { expected, .. }
+                    | SyntaxError::UnexpectedEof {
+                        expected: Some(expected),
+                    } => diagnostic.with_label(format!("expected {}", expected)),
+                    SyntaxError::InvalidAssignmentTarget { target } => diagnostic
+                        .with_secondary(target.clone(), "cannot assign to this expression"),
+                    _ => diagnostic,
+                }
+            }
+            Self::Resolve(e) => match e.get_value().at() {
+                Some(at) => diagnostic.at(format!("'{at}'")),
+                None => diagnostic,
+            },
+        }
+    }
+}
+
+/// Lexical, syntax and resolution errors use the single-line
+/// `[line N] Error at X: message` format; runtime errors put the line
+/// underneath the message.
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Runtime(e) | Self::Limit(e) => write!(f, "{e}"),
+            _ => write!(f, "{}", self.diagnostic()),
+        }
+    }
+}
+
+impl std::error::Error for LoxError {}
+
+impl From<WithSpan<LexError>> for LoxError {
+    fn from(e: WithSpan<LexError>) -> Self {
+        Self::Lex(e)
+    }
+}
+
+impl From<WithSpan<SyntaxError>> for LoxError {
+    fn from(e: WithSpan<SyntaxError>) -> Self {
+        Self::Syntax(e)
+    }
+}
+
+impl From<WithSpan<ResolveError>> for LoxError {
+    fn from(e: WithSpan<ResolveError>) -> Self {
+        Self::Resolve(e)
+    }
+}
+
+impl From<RuntimeError> for LoxError {
+    fn from(e: RuntimeError) -> Self {
+        if e.limit.is_some() {
+            Self::Limit(e)
+        } else {
+            Self::Runtime(e)
+        }
+    }
+}
+
+/// A [`Value`](crate::Value) did not have the type a host function
+/// expected. Converts into the `String` error natives return, so `?` works
+/// inside a closure passed to
+/// [`Interpreter::register_native`](crate::Interpreter::register_native).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expected {} but got {}.", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+impl From<TypeError> for String {
+    fn from(e: TypeError) -> Self {
+        e.to_string()
+    }
+}
+
+/// A function call that was still running when a runtime error occurred.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    function: String,
+    call_site: WithSpan<()>,
+}
+
+impl Frame {
+    #[inline]
+    pub fn new<S: Into<String>>(function: S, call_site: WithSpan<()>) -> Self {
+        Self {
+            function: function.into(),
+            call_site,
+        }
+    }
+
+    /// Name of the function that was called.
+    #[inline]
+    pub fn function(&self) -> &str {
+        &self.function
+    }
+
+    /// The call expression that started this frame.
+    #[inline]
+    pub fn call_site(&self) -> &WithSpan<()> {
+        &self.call_site
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeError {
+    message: String,
+    line: usize,
+    span: Option<Range<usize>>,
+    file: Option<FileId>,
+    limit: Option<Limit>,
+    trace: Vec<Frame>,
+}
+
+impl RuntimeError {
+    #[inline]
+    pub fn new<M: Into<String>>(message: M, line: usize) -> Self {
+        Self {
+            message: message.into(),
+            line,
+            span: None,
+            file: None,
+            limit: None,
+            trace: Vec::new(),
+        }
+    }
+
+    /// The error raised when `limit` is exceeded at `line`.
+    pub fn exceeded(limit: Limit, line: usize) -> Self {
+        Self {
+            limit: Some(limit),
+            ..Self::new(limit.to_string(), line)
+        }
+    }
+
+    /// The limit that stopped the program, if that is what this error is.
+    #[inline]
+    pub fn limit(&self) -> Option<Limit> {
+        self.limit
+    }
+
+    /// Points the error at the expression that raised it.
+    #[inline]
+    pub fn with_span(mut self, span: Range<usize>) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    #[inline]
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.span.clone()
+    }
+
+    /// Points the error at `span`, unless it already points somewhere
+    /// more precise.
+    pub(crate) fn or_span(mut self, span: Range<usize>) -> Self {
+        self.span.get_or_insert(span);
+        self
+    }
+
+    /// Records the file the error was raised in, unless one is already
+    /// known.
+    pub(crate) fn in_file(mut self, file: FileId) -> Self {
+        self.file.get_or_insert(file);
+        self
+    }
+
+    /// The file [`RuntimeError::line`] and [`RuntimeError::span`] refer to.
+    #[inline]
+    pub fn file(&self) -> FileId {
+        self.file.unwrap_or_default()
+    }
+
+    /// Records that the error unwound through `frame`. Frames are added
+    /// innermost first.
+    pub(crate) fn with_frame(mut self, frame: Frame) -> Self {
+        self.trace.push(frame);
+        self
+    }
+
+    pub(crate) fn with_trace(mut self, trace: Vec<Frame>) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// The calls that were active when the error occurred, innermost
+    /// first. Empty if it happened at the top level.
+    #[inline]
+    pub fn trace(&self) -> &[Frame] {
+        &self.trace
+    }
+
+    /// Formats the trace the way clox does, one `[line N] in f()` per
+    /// frame followed by `[line N] in script`.
+    pub fn traceback(&self) -> String {
+        self.format_trace(|line, _, _| format!("line {line}"))
+    }
+
+    /// Like [`RuntimeError::traceback`], but with each frame located as
+    /// `path:line:column` in `sources`.
+    pub fn traceback_in(&self, sources: &SourceMap) -> String {
+        self.format_trace(|line, file, offset| {
+            let location = offset.and_then(|offset| sources.location(file, offset));
+            match (location, sources.get(file)) {
+                (Some(location), _) => location.to_string(),
+                (None, Some(source)) => format!("{}:{line}", source.name()),
+                (None, None) => format!("line {line}"),
+            }
+        })
+    }
+
+    /// Joins `[place] in function()` lines, innermost first, where
+    /// `place` formats a line, file and byte offset if known.
+    fn format_trace<F>(&self, place: F) -> String
+    where
+        F: Fn(usize, FileId, Option<usize>) -> String,
+    {
+        let mut at = place(self.line, self.file(), self.span.as_ref().map(|s| s.start));
+        let mut out = String::new();
+        for frame in &self.trace {
+            out.push_str(&format!("[{at}] in {}()\n", frame.function));
+            let site = &frame.call_site;
+            at = place(
+                site.get_line(),
+                site.get_file(),
+                Some(site.get_span().start),
+            );
+        }
+        out.push_str(&format!("[{at}] in script"));
+        out
+    }
+
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    #[inline]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n[line {}]", self.message, self.line)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
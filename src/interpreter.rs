@@ -0,0 +1,948 @@
+use std::{
+    borrow::Cow, cell::RefCell, collections::HashMap, fmt, fs, io, mem, path::PathBuf, rc::Rc,
+    time::Instant,
+};
+
+use crate::{
+    ast::{
+        Ast, BinaryOperator, ClassDecl, Expr, FunctionDecl, LogicalOperator, Slot, Stmt,
+        UnaryOperator,
+    },
+    builtins::{self, list_index},
+    callable::{Function, NativeFunction},
+    class::{Class, Instance},
+    environment::Environment,
+    error::{Frame, LoxError, RuntimeError},
+    limits::{grow_stack, InterruptHandle, Limit, Limits, MAX_NESTING},
+    module::{Globals, Loader, Module},
+    objects::Objects,
+    value::{List, Value},
+    vm::HeapStats,
+    FileId, Lexer, Parser, Resolver, SourceFile, SourceMap, WithSpan,
+};
+
+pub type RuntimeResult<T> = Result<T, RuntimeError>;
+
+/// How control leaves a statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Flow {
+    Next,
+    Return(Value),
+}
+
+/// How statements with the lifetime `'a` hand the functions they declare
+/// to closures, which outlive the statements.
+trait Detach<'a> {
+    fn function(decl: &Rc<FunctionDecl<'a>>) -> Rc<FunctionDecl<'static>>;
+}
+
+/// Statements owned by a function body or module, whose declarations can
+/// be shared.
+enum Owned {}
+
+impl Detach<'static> for Owned {
+    #[inline]
+    fn function(decl: &Rc<FunctionDecl<'static>>) -> Rc<FunctionDecl<'static>> {
+        Rc::clone(decl)
+    }
+}
+
+/// Statements borrowed from the program being interpreted. Only the
+/// functions they declare are copied out of the source.
+enum Borrowed {}
+
+impl<'a> Detach<'a> for Borrowed {
+    fn function(decl: &Rc<FunctionDecl<'a>>) -> Rc<FunctionDecl<'static>> {
+        Rc::new(FunctionDecl::clone(decl).into_owned())
+    }
+}
+
+/// Tree-walking interpreter.
+///
+/// Programs must have been passed through the [`Resolver`](crate::Resolver)
+/// before they are executed: locals are looked up by the slots it records,
+/// and anything left unresolved is treated as a global. [`Interpreter::eval_str`]
+/// does both steps for you.
+///
+/// `print` writes to `W`, which is stdout unless the interpreter was built
+/// with [`Interpreter::with_output`].
+///
+/// Untrusted programs can be bounded with [`Interpreter::with_limits`] and
+/// stopped from another thread through [`Interpreter::interrupt_handle`].
+///
+/// Values are freed by reference counting. Reference cycles, such as an
+/// instance holding one of its own bound methods or a ring of linked
+/// instances, are freed by a collector that runs whenever the program has
+/// allocated as much again as was alive after the last collection;
+/// [`Interpreter::heap_stats`] shows its progress.
+pub struct Interpreter<W: io::Write = io::Stdout> {
+    globals: Globals,
+    environment: Option<Rc<RefCell<Environment>>>,
+    modules: Loader,
+    /// Host functions added with [`Interpreter::register_native`], which
+    /// imported modules see as well.
+    natives: HashMap<String, Value>,
+    output: W,
+    limits: Limits,
+    steps: u64,
+    depth: usize,
+    /// Statements and expressions being executed in the current call.
+    nesting: usize,
+    deadline: Option<Instant>,
+    interrupt: InterruptHandle,
+    objects: Objects,
+    /// Text of every file run so far, which spans in errors refer to.
+    sources: SourceMap,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::with_output(io::stdout())
+    }
+}
+
+impl Interpreter {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<W: io::Write> fmt::Debug for Interpreter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interpreter")
+            .field("globals", &self.globals)
+            .field("environment", &self.environment)
+            .field("modules", &self.modules)
+            .field("limits", &self.limits)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<W: io::Write> Interpreter<W> {
+    pub fn with_output(output: W) -> Self {
+        let objects = Objects::default();
+        Self {
+            globals: Rc::new(RefCell::new(builtins::globals(objects.meter()))),
+            environment: None,
+            modules: Loader::default(),
+            natives: HashMap::new(),
+            output,
+            limits: Limits::default(),
+            steps: 0,
+            depth: 0,
+            nesting: 0,
+            deadline: None,
+            interrupt: InterruptHandle::default(),
+            objects,
+            sources: SourceMap::new(),
+        }
+    }
+
+    /// Uses `sources` as the interpreter's source map. Programs passed to
+    /// [`Interpreter::interpret`] should have been lexed from files in it,
+    /// with [`Lexer::with_file`].
+    pub fn with_sources(mut self, sources: SourceMap) -> Self {
+        self.sources = sources;
+        self
+    }
+
+    /// Every file the interpreter has run, including imported modules, for
+    /// resolving the spans in errors.
+    #[inline]
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    /// Bounds the work done by each call into the interpreter. A program
+    /// that exceeds a limit fails with [`LoxError::Limit`].
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    #[inline]
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    #[inline]
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Counts the strings, instances and lists created by programs that
+    /// are still alive. `freed_objects` includes those freed by reference counting
+    /// as well as by the collector.
+    #[inline]
+    pub fn heap_stats(&self) -> HeapStats {
+        self.objects.stats()
+    }
+
+    /// Enables or disables collecting garbage on every allocation.
+    #[inline]
+    pub fn set_gc_stress(&mut self, stress: bool) {
+        self.objects.set_stress(stress);
+    }
+
+    /// Frees the objects kept alive only by reference cycles, returning
+    /// the number of instances and lists freed.
+    #[inline]
+    pub fn collect_garbage(&mut self) -> usize {
+        self.objects.collect()
+    }
+
+    /// Returns a handle that stops the running program when interrupted.
+    /// It can be sent to another thread.
+    #[inline]
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// The writer `print` sends its output to.
+    #[inline]
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    #[inline]
+    pub fn output_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    #[inline]
+    pub fn into_output(self) -> W {
+        self.output
+    }
+
+    /// Parses, resolves and runs `source`. If it is a single expression
+    /// without a trailing `;`, its value is returned; otherwise the
+    /// statements are executed and the result is `nil`.
+    ///
+    /// The source is added to [`Interpreter::sources`] as `<eval>`.
+    pub fn eval_str(&mut self, source: &str) -> Result<Value, LoxError> {
+        self.start();
+        let file = self.sources.add("<eval>", source);
+        let tokens = Lexer::from(source).with_file(file).collect::<Vec<_>>();
+        let mut parser = Parser::<io::Sink>::new(&tokens, None);
+        if let Ok(expr) = parser.parse() {
+            if parser.is_eof() {
+                return Ok(self.evaluate(&expr).map_err(|e| e.in_file(file))?);
+            }
+        }
+        let mut ast = Parser::<io::Sink>::new(&tokens, None).parse_program()?;
+        Resolver::<io::Sink>::new(None).resolve(&mut ast)?;
+        self.interpret(&ast)?;
+        Ok(Value::Nil)
+    }
+
+    /// Calls the global function or class `name` with `args`.
+    pub fn call_function(&mut self, name: &str, args: &[Value]) -> Result<Value, LoxError> {
+        self.start();
+        let callee = self.lookup(name, None, 0)?;
+        Ok(self.call(callee, args.to_vec(), &WithSpan::new((), 0, 0..0))?)
+    }
+
+    #[inline]
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().get(name).cloned()
+    }
+
+    /// Defines or overwrites a global variable.
+    pub fn set_global<V: Into<Value>>(&mut self, name: &str, value: V) {
+        self.globals
+            .borrow_mut()
+            .insert(name.to_string(), value.into());
+    }
+
+    /// Exposes a Rust closure to Lox code as the global function `name`.
+    /// Errors returned by `func` become runtime errors at the call site.
+    ///
+    /// ```
+    /// use codecrafters_interpreter::{Interpreter, Value};
+    ///
+    /// let mut lox = Interpreter::with_output(Vec::new());
+    /// lox.register_native("hypot", 2, |args| {
+    ///     let (x, y) = (f64::try_from(&args[0])?, f64::try_from(&args[1])?);
+    ///     Ok(Value::from(x.hypot(y)))
+    /// });
+    /// lox.eval_str("print hypot(3, 4);").unwrap();
+    /// assert_eq!(lox.into_output(), b"5\n");
+    /// ```
+    pub fn register_native<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + 'static,
+    {
+        let native = Value::NativeFunction(Rc::new(NativeFunction::new(name, arity, func)));
+        self.natives.insert(name.to_string(), native.clone());
+        self.set_global(name, native);
+    }
+
+    /// Sets the file the program was read from. Relative imports are
+    /// resolved against its directory instead of the working directory,
+    /// and importing it again is reported as circular.
+    pub fn with_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        let path = path.into();
+        if let Ok(canonical) = path.canonicalize() {
+            self.modules.enter(canonical, path);
+        }
+        self
+    }
+
+    pub fn interpret(&mut self, ast: &Ast) -> RuntimeResult<()> {
+        self.start();
+        for stmt in ast {
+            self.execute_as::<Borrowed>(stmt)
+                .map_err(|e| e.in_file(stmt.get_file()))?;
+        }
+        Ok(())
+    }
+
+    /// Starts the step and time budgets afresh, and drops any interrupt
+    /// left over from an earlier run.
+    fn start(&mut self) {
+        self.interrupt.clear();
+        self.steps = 0;
+        self.deadline = self.limits.time().map(|time| Instant::now() + time);
+    }
+
+    /// Counts one step of execution and checks every limit but depth.
+    fn tick(&mut self, line: usize) -> RuntimeResult<()> {
+        self.steps += 1;
+        let exceeded = if self.limits.steps().is_some_and(|max| self.steps > max) {
+            Some(Limit::Steps)
+        } else if self.over_allocation() {
+            Some(Limit::Allocation)
+        } else if self.interrupt.take() {
+            Some(Limit::Interrupted)
+        } else if self.steps % 256 == 0 && self.deadline.is_some_and(|d| Instant::now() >= d) {
+            // Reading the clock on every step would dominate run time.
+            Some(Limit::Time)
+        } else {
+            None
+        };
+        match exceeded {
+            Some(limit) => Err(RuntimeError::exceeded(limit, line)),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the program holds more than the allocation limit. Garbage
+    /// is collected before giving up, as what was allocated may since have
+    /// been freed.
+    fn over_allocation(&mut self) -> bool {
+        let Some(max) = self.limits.allocation() else {
+            return false;
+        };
+        if self.objects.bytes() <= max {
+            return false;
+        }
+        self.objects.collect();
+        self.objects.bytes() > max
+    }
+
+    /// Records an allocation of `bytes` made for the program.
+    fn allocate(&mut self, bytes: usize, line: usize) -> RuntimeResult<()> {
+        self.objects.charge(bytes);
+        if self.objects.should_collect() {
+            self.objects.collect();
+        }
+        if self.over_allocation() {
+            return Err(RuntimeError::exceeded(Limit::Allocation, line));
+        }
+        Ok(())
+    }
+
+    pub fn execute(&mut self, stmt: &WithSpan<Stmt<'static>>) -> RuntimeResult<Flow> {
+        self.execute_as::<Owned>(stmt)
+    }
+
+    fn execute_as<'a, D: Detach<'a>>(&mut self, stmt: &WithSpan<Stmt<'a>>) -> RuntimeResult<Flow> {
+        self.nested(stmt, |this| this.execute_stmt::<D>(stmt))
+    }
+
+    /// Runs `run` on `node` one level deeper. Trees nested past
+    /// [`MAX_NESTING`] fail with [`Limit::Depth`], like calls nested past
+    /// the depth limit. Errors raised without a span point at `node`.
+    fn nested<N, T>(
+        &mut self,
+        node: &WithSpan<N>,
+        run: impl FnOnce(&mut Self) -> RuntimeResult<T>,
+    ) -> RuntimeResult<T> {
+        if self.nesting >= MAX_NESTING {
+            return Err(
+                RuntimeError::exceeded(Limit::Depth, node.get_line()).with_span(node.get_span())
+            );
+        }
+        self.nesting += 1;
+        let result = grow_stack(|| run(self));
+        self.nesting -= 1;
+        result.map_err(|e| e.or_span(node.get_span()))
+    }
+
+    fn execute_stmt<'a, D: Detach<'a>>(
+        &mut self,
+        stmt: &WithSpan<Stmt<'a>>,
+    ) -> RuntimeResult<Flow> {
+        self.tick(stmt.get_line())?;
+        match stmt.get_value() {
+            Stmt::Expression(expr) => {
+                self.evaluate(expr)?;
+            }
+            Stmt::Print(expr) => {
+                let value = self.evaluate(expr)?;
+                writeln!(self.output, "{value}")
+                    .map_err(|e| RuntimeError::new(e.to_string(), stmt.get_line()))?;
+            }
+            Stmt::Var(name, initializer) => {
+                let value = match initializer {
+                    Some(expr) => self.evaluate(expr)?,
+                    None => Value::Nil,
+                };
+                self.define(name.get_value(), value);
+            }
+            Stmt::Block(stmts) => {
+                let environment = Environment::new(self.environment.clone());
+                return self.execute_block_as::<D>(stmts, Rc::new(RefCell::new(environment)));
+            }
+            Stmt::If(condition, then_branch, else_branch) => {
+                if self.evaluate(condition)?.is_truthy() {
+                    return self.execute_as::<D>(then_branch);
+                } else if let Some(else_branch) = else_branch {
+                    return self.execute_as::<D>(else_branch);
+                }
+            }
+            Stmt::While(condition, body) => {
+                while self.evaluate(condition)?.is_truthy() {
+                    if let Flow::Return(v) = self.execute_as::<D>(body)? {
+                        return Ok(Flow::Return(v));
+                    }
+                }
+            }
+            Stmt::Function(decl) => {
+                if let Some(closure) = &self.environment {
+                    self.objects.track_closure(closure);
+                }
+                let function = Function {
+                    decl: D::function(decl),
+                    closure: self.environment.clone(),
+                    globals: Rc::clone(&self.globals),
+                    is_initializer: false,
+                };
+                self.define(decl.name.get_value(), Value::Function(Rc::new(function)));
+            }
+            Stmt::Return(value) => {
+                let value = match value {
+                    Some(expr) => self.evaluate(expr)?,
+                    None => Value::Nil,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Stmt::Class(decl) => self.class::<D>(decl)?,
+            Stmt::Import(path, name) => {
+                let module = self.import(path, name.get_value())?;
+                self.define(name.get_value(), Value::Module(module));
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Runs a class declaration. Kept out of [`Interpreter::execute`] so
+    /// its locals don't enlarge every recursive call's stack frame.
+    fn class<'a, D: Detach<'a>>(&mut self, decl: &ClassDecl<'a>) -> RuntimeResult<()> {
+        let superclass = match &decl.superclass {
+            Some(expr) => match self.evaluate(expr)? {
+                Value::Class(class) => Some(class),
+                _ => {
+                    return Err(RuntimeError::new(
+                        "Superclass must be a class.",
+                        expr.get_line(),
+                    ))
+                }
+            },
+            None => None,
+        };
+        let closure = match &superclass {
+            Some(superclass) => {
+                let mut environment = Environment::new(self.environment.clone());
+                environment.define(Value::Class(Rc::clone(superclass)));
+                Some(Rc::new(RefCell::new(environment)))
+            }
+            None => self.environment.clone(),
+        };
+        if let Some(closure) = &closure {
+            self.objects.track_closure(closure);
+        }
+        let methods = decl
+            .methods
+            .iter()
+            .map(|method| {
+                let function = Function {
+                    decl: D::function(method),
+                    closure: closure.clone(),
+                    globals: Rc::clone(&self.globals),
+                    is_initializer: method.name.get_value() == "init",
+                };
+                (method.name.get_value().to_string(), Rc::new(function))
+            })
+            .collect();
+        let name = decl.name.get_value();
+        let class = Class {
+            name: name.to_string(),
+            superclass,
+            methods,
+        };
+        self.define(name, Value::Class(Rc::new(class)));
+        Ok(())
+    }
+
+    /// Loads, runs and caches the module at `path`, which is executed with
+    /// globals of its own.
+    fn import(&mut self, path: &WithSpan<Cow<str>>, name: &str) -> RuntimeResult<Rc<Module>> {
+        let error = |message: String| {
+            RuntimeError::new(message, path.get_line()).with_span(path.get_span())
+        };
+        let Some(file) = self.modules.find(path.get_value()) else {
+            return Err(error(format!(
+                "Can't find module \"{}\".",
+                path.get_value()
+            )));
+        };
+        let read_error =
+            |e: io::Error| error(format!("Can't read module \"{}\": {e}.", file.display()));
+        let canonical = file.canonicalize().map_err(read_error)?;
+        if let Some(module) = self.modules.get(&canonical) {
+            return Ok(module);
+        }
+        if let Some(chain) = self.modules.cycle(&canonical, &file) {
+            return Err(error(format!("Circular import: {chain}.")));
+        }
+        let source = fs::read_to_string(&file).map_err(read_error)?;
+        let id = self.sources.add(file.display().to_string(), source);
+        let source = self.sources.get(id).map_or("", SourceFile::text);
+        let ast = compile(source, id)
+            .map_err(|e| error(format!("Error in module \"{}\": {e}", file.display())))?;
+
+        let mut globals = builtins::globals(self.objects.meter());
+        globals.extend(self.natives.clone());
+        let globals = Rc::new(RefCell::new(globals));
+        let previous_globals = mem::replace(&mut self.globals, Rc::clone(&globals));
+        let previous_environment = self.environment.take();
+        self.modules.enter(canonical.clone(), file.clone());
+        let result = ast.iter().try_for_each(|stmt| {
+            self.execute(stmt)
+                .map(drop)
+                .map_err(|e| e.in_file(stmt.get_file()))
+        });
+        self.modules.exit();
+        self.environment = previous_environment;
+        self.globals = previous_globals;
+        result?;
+
+        let module = Rc::new(Module::new(name, file, globals, &ast));
+        self.modules.insert(canonical, Rc::clone(&module));
+        Ok(module)
+    }
+
+    pub fn execute_block(
+        &mut self,
+        stmts: &[WithSpan<Stmt<'static>>],
+        environment: Rc<RefCell<Environment>>,
+    ) -> RuntimeResult<Flow> {
+        self.execute_block_as::<Owned>(stmts, environment)
+    }
+
+    fn execute_block_as<'a, D: Detach<'a>>(
+        &mut self,
+        stmts: &[WithSpan<Stmt<'a>>],
+        environment: Rc<RefCell<Environment>>,
+    ) -> RuntimeResult<Flow> {
+        let previous = self.environment.replace(environment);
+        let mut result = Ok(Flow::Next);
+        for stmt in stmts {
+            result = self.execute_as::<D>(stmt);
+            if !matches!(result, Ok(Flow::Next)) {
+                break;
+            }
+        }
+        self.environment = previous;
+        result
+    }
+
+    /// Declares a variable in the innermost scope, which is the global
+    /// table when no block or function is being executed.
+    fn define(&mut self, name: &str, value: Value) {
+        match &self.environment {
+            Some(environment) => environment.borrow_mut().define(value),
+            None => {
+                self.globals.borrow_mut().insert(name.to_string(), value);
+            }
+        }
+    }
+
+    fn lookup(&self, name: &str, slot: Option<Slot>, line: usize) -> RuntimeResult<Value> {
+        match (slot, &self.environment) {
+            (Some(slot), Some(environment)) => Ok(environment.borrow().get_at(slot)),
+            _ => self
+                .globals
+                .borrow()
+                .get(name)
+                .cloned()
+                .ok_or_else(|| RuntimeError::new(format!("Undefined variable '{name}'."), line)),
+        }
+    }
+
+    /// Calls `callee`. `site` is the call expression, recorded in the
+    /// [`Frame`] of any error the call fails with.
+    pub fn call(
+        &mut self,
+        callee: Value,
+        args: Vec<Value>,
+        site: &WithSpan<()>,
+    ) -> RuntimeResult<Value> {
+        let line = site.get_line();
+        let arity = match &callee {
+            Value::Function(f) => f.arity(),
+            Value::NativeFunction(f) => f.arity(),
+            Value::Class(c) => c.arity(),
+            _ => {
+                return Err(
+                    RuntimeError::new("Can only call functions and classes.", line)
+                        .with_span(site.get_span()),
+                )
+            }
+        };
+        if args.len() != arity {
+            return Err(RuntimeError::new(
+                format!("Expected {} arguments but got {}.", arity, args.len()),
+                line,
+            )
+            .with_span(site.get_span()));
+        }
+        if matches!(callee, Value::Function(_) | Value::Class(_))
+            && self.depth >= self.limits.depth()
+        {
+            return Err(RuntimeError::exceeded(Limit::Depth, line));
+        }
+        match callee {
+            Value::Function(f) => self.call_closure(&f, args, site),
+            Value::NativeFunction(f) => (f.func)(&args).map_err(|e| RuntimeError::new(e, line)),
+            Value::Class(class) => {
+                self.allocate(mem::size_of::<Instance>(), line)?;
+                let instance = Rc::new(RefCell::new(Instance::new(Rc::clone(&class))));
+                self.objects.track_instance(&instance);
+                let instance = Value::Instance(instance);
+                if let Some(init) = class.find_method("init") {
+                    self.call_closure(&init.bind(instance.clone()), args, site)?;
+                }
+                Ok(instance)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Evaluates `list[index]` up to the point of reading or writing it,
+    /// checking that the index is in bounds.
+    fn list_element(
+        &mut self,
+        list: &WithSpan<Expr>,
+        index: &WithSpan<Expr>,
+    ) -> RuntimeResult<(Rc<RefCell<List>>, usize)> {
+        let Value::List(items) = self.evaluate(list)? else {
+            return Err(
+                RuntimeError::new("Only lists can be indexed.", list.get_line())
+                    .with_span(list.get_span()),
+            );
+        };
+        let error = |message: String| {
+            RuntimeError::new(message, index.get_line()).with_span(index.get_span())
+        };
+        let Value::Number(n) = self.evaluate(index)? else {
+            return Err(error("List index must be a number.".to_string()));
+        };
+        let len = items.borrow().len();
+        let n = list_index(n, len, false).map_err(error)?;
+        Ok((items, n))
+    }
+
+    fn call_closure(
+        &mut self,
+        function: &Function,
+        args: Vec<Value>,
+        site: &WithSpan<()>,
+    ) -> RuntimeResult<Value> {
+        let mut environment = Environment::new(function.closure.clone());
+        for arg in args {
+            environment.define(arg);
+        }
+        let globals = mem::replace(&mut self.globals, Rc::clone(&function.globals));
+        self.depth += 1;
+        let nesting = mem::take(&mut self.nesting);
+        let flow = self.execute_block(&function.decl.body, Rc::new(RefCell::new(environment)));
+        self.nesting = nesting;
+        self.depth -= 1;
+        self.globals = globals;
+        let flow = flow.map_err(|e| {
+            e.in_file(function.decl.name.get_file())
+                .with_frame(Frame::new(
+                    function.decl.name.get_value().as_ref(),
+                    site.clone(),
+                ))
+        })?;
+        if function.is_initializer {
+            return Ok(function.this());
+        }
+        match flow {
+            Flow::Return(v) => Ok(v),
+            Flow::Next => Ok(Value::Nil),
+        }
+    }
+
+    fn assign(
+        &mut self,
+        name: &WithSpan<Cow<str>>,
+        value: &WithSpan<Expr>,
+        slot: Option<Slot>,
+    ) -> RuntimeResult<Value> {
+        let value = self.evaluate(value)?;
+        match (slot, &self.environment) {
+            (Some(slot), Some(environment)) => {
+                environment.borrow_mut().assign_at(slot, value.clone())
+            }
+            _ => match self.globals.borrow_mut().get_mut(name.get_value().as_ref()) {
+                Some(global) => *global = value.clone(),
+                None => {
+                    return Err(RuntimeError::new(
+                        format!("Undefined variable '{}'.", name.get_value()),
+                        name.get_line(),
+                    ))
+                }
+            },
+        }
+        Ok(value)
+    }
+
+    /// Looks up `super.method`, bound to the current `this`.
+    fn super_method(
+        &self,
+        method: &WithSpan<Cow<str>>,
+        slot: Option<Slot>,
+    ) -> RuntimeResult<Value> {
+        let line = method.get_line();
+        let Some(slot) = slot else {
+            return Err(RuntimeError::new(
+                "Can't use 'super' outside of a class.",
+                line,
+            ));
+        };
+        // `this` is always bound in the scope just inside the one
+        // that holds `super`.
+        let superclass = self.lookup("super", Some(slot), line)?;
+        let this = self.lookup("this", Some(Slot::new(slot.depth - 1, 0)), line)?;
+        let Value::Class(superclass) = superclass else {
+            return Err(RuntimeError::new("Superclass must be a class.", line));
+        };
+        superclass
+            .find_method(method.get_value())
+            .map(|m| Value::Function(Rc::new(m.bind(this))))
+            .ok_or_else(|| {
+                RuntimeError::new(
+                    format!("Undefined property '{}'.", method.get_value()),
+                    line,
+                )
+            })
+    }
+
+    /// Concatenates the parts of an interpolated string, each shown the way
+    /// `print` would show it.
+    fn interpolate(&mut self, parts: &[WithSpan<Expr>], line: usize) -> RuntimeResult<Value> {
+        use std::fmt::Write;
+
+        let mut s = String::new();
+        for part in parts {
+            let value = self.evaluate(part)?;
+            // Writing to a `String` cannot fail.
+            let _ = write!(s, "{value}");
+        }
+        self.string(&s, line)
+    }
+
+    /// Allocates a string the program has built.
+    fn string(&mut self, s: &str, line: usize) -> RuntimeResult<Value> {
+        self.allocate(s.len(), line)?;
+        let s = Rc::from(s);
+        self.objects.track_string(&s);
+        Ok(Value::String(s))
+    }
+
+    pub fn evaluate(&mut self, expr: &WithSpan<Expr>) -> RuntimeResult<Value> {
+        self.nested(expr, |this| this.evaluate_expr(expr))
+    }
+
+    fn evaluate_expr(&mut self, expr: &WithSpan<Expr>) -> RuntimeResult<Value> {
+        self.tick(expr.get_line())?;
+        match expr.get_value() {
+            Expr::Nil => Ok(Value::Nil),
+            Expr::Boolean(v) => Ok(Value::Boolean(*v)),
+            Expr::Number(v) => Ok(Value::Number(*v)),
+            Expr::String(v) => self.string(v, expr.get_line()),
+            Expr::Grouping(inner) => self.evaluate(inner),
+            Expr::Unary(operator, right) => {
+                let right = self.evaluate(right)?;
+                unary(operator, right)
+            }
+            Expr::Binary(left, operator, right) => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                let value = binary(left, operator, right)?;
+                if let Value::String(s) = &value {
+                    self.allocate(s.len(), expr.get_line())?;
+                    self.objects.track_string(s);
+                }
+                Ok(value)
+            }
+            Expr::Variable(name, slot) => self.lookup(name, *slot, expr.get_line()),
+            Expr::Assign(name, value, slot) => self.assign(name, value, *slot),
+            Expr::Logical(left, operator, right) => {
+                let left = self.evaluate(left)?;
+                match (operator.get_value(), left.is_truthy()) {
+                    (LogicalOperator::Or, true) | (LogicalOperator::And, false) => Ok(left),
+                    _ => self.evaluate(right),
+                }
+            }
+            Expr::Call(callee, args) => {
+                let callee = self.evaluate(callee)?;
+                let args = args
+                    .iter()
+                    .map(|arg| self.evaluate(arg))
+                    .collect::<RuntimeResult<Vec<_>>>()?;
+                let site = WithSpan::new((), expr.get_line(), expr.get_span());
+                self.call(callee, args, &site)
+            }
+            Expr::Get(object, name) => {
+                let object = self.evaluate(object)?;
+                get_property(object, name)
+            }
+            Expr::Set(object, name, value) => {
+                let Value::Instance(instance) = self.evaluate(object)? else {
+                    return Err(RuntimeError::new(
+                        "Only instances have fields.",
+                        name.get_line(),
+                    ));
+                };
+                let value = self.evaluate(value)?;
+                self.allocate(mem::size_of::<Value>(), name.get_line())?;
+                instance
+                    .borrow_mut()
+                    .set(name.get_value().as_ref(), value.clone());
+                Ok(value)
+            }
+            Expr::List(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| self.evaluate(item))
+                    .collect::<RuntimeResult<Vec<_>>>()?;
+                self.allocate(mem::size_of_val(items.as_slice()), expr.get_line())?;
+                let list = Rc::new(RefCell::new(List::from(items)));
+                self.objects.track_list(&list);
+                Ok(Value::List(list))
+            }
+            Expr::Interpolation(parts) => self.interpolate(parts, expr.get_line()),
+            Expr::Index(list, index) => {
+                let (list, index) = self.list_element(list, index)?;
+                let item = list.borrow()[index].clone();
+                Ok(item)
+            }
+            Expr::SetIndex(list, index, value) => {
+                let (list, index) = self.list_element(list, index)?;
+                let value = self.evaluate(value)?;
+                list.borrow_mut()[index] = value.clone();
+                Ok(value)
+            }
+            Expr::This(slot) => self.lookup("this", *slot, expr.get_line()),
+            Expr::Super(method, slot) => self.super_method(method, *slot),
+        }
+    }
+}
+
+fn unary(operator: &WithSpan<UnaryOperator>, right: Value) -> RuntimeResult<Value> {
+    match operator.get_value() {
+        UnaryOperator::Bang => Ok(Value::Boolean(!right.is_truthy())),
+        UnaryOperator::Minus => match right {
+            Value::Number(n) => Ok(Value::Number(-n)),
+            _ => Err(RuntimeError::new(
+                "Operand must be a number.",
+                operator.get_line(),
+            )),
+        },
+    }
+}
+
+fn binary(left: Value, operator: &WithSpan<BinaryOperator>, right: Value) -> RuntimeResult<Value> {
+    let line = operator.get_line();
+    let numbers = |left: &Value, right: &Value| match (left, right) {
+        (Value::Number(a), Value::Number(b)) => Ok((*a, *b)),
+        _ => Err(RuntimeError::new("Operands must be numbers.", line)),
+    };
+    match operator.get_value() {
+        BinaryOperator::Plus => match (&left, &right) {
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
+            (Value::String(a), Value::String(b)) => {
+                let mut s = String::with_capacity(a.len() + b.len());
+                s.push_str(a);
+                s.push_str(b);
+                Ok(Value::from(s))
+            }
+            _ => Err(RuntimeError::new(
+                "Operands must be two numbers or two strings.",
+                line,
+            )),
+        },
+        BinaryOperator::Minus => numbers(&left, &right).map(|(a, b)| Value::Number(a - b)),
+        BinaryOperator::Star => numbers(&left, &right).map(|(a, b)| Value::Number(a * b)),
+        BinaryOperator::Slash => numbers(&left, &right).map(|(a, b)| Value::Number(a / b)),
+        BinaryOperator::Greater => numbers(&left, &right).map(|(a, b)| Value::Boolean(a > b)),
+        BinaryOperator::GreaterEqual => numbers(&left, &right).map(|(a, b)| Value::Boolean(a >= b)),
+        BinaryOperator::Less => numbers(&left, &right).map(|(a, b)| Value::Boolean(a < b)),
+        BinaryOperator::LessEqual => numbers(&left, &right).map(|(a, b)| Value::Boolean(a <= b)),
+        BinaryOperator::EqualEqual => Ok(Value::Boolean(left == right)),
+        BinaryOperator::BangEqual => Ok(Value::Boolean(left != right)),
+    }
+}
+
+/// Parses and resolves a module's source, failing with its first error.
+fn compile(source: &str, file: FileId) -> Result<Vec<WithSpan<Stmt<'static>>>, LoxError> {
+    let tokens = Lexer::from(source).with_file(file).collect::<Vec<_>>();
+    let mut ast = Parser::<io::Sink>::new(&tokens, None).parse_program()?;
+    Resolver::<io::Sink>::new(None).resolve(&mut ast)?;
+    Ok(ast
+        .into_iter()
+        .map(|stmt| stmt.map(Stmt::into_owned))
+        .collect())
+}
+
+/// Reads the property `name` of an instance or module.
+fn get_property(object: Value, name: &WithSpan<Cow<str>>) -> RuntimeResult<Value> {
+    match object {
+        Value::Instance(instance) => Instance::get(&instance, name.get_value()).ok_or_else(|| {
+            RuntimeError::new(
+                format!("Undefined property '{}'.", name.get_value()),
+                name.get_line(),
+            )
+        }),
+        Value::Module(module) => module.get(name.get_value()).ok_or_else(|| {
+            RuntimeError::new(
+                format!(
+                    "Module '{}' has no member '{}'.",
+                    module.name(),
+                    name.get_value()
+                ),
+                name.get_line(),
+            )
+        }),
+        _ => Err(RuntimeError::new(
+            "Only instances have properties.",
+            name.get_line(),
+        )),
+    }
+}
@@ -1,12 +1,38 @@
-mod ast;
+pub mod ast;
+mod builtins;
+mod callable;
+mod class;
+mod diagnostic;
+mod environment;
 mod error;
+mod interpreter;
+mod limits;
+mod module;
+mod number;
+mod objects;
 mod parser;
 mod position;
+mod repl;
+mod resolver;
+mod source;
 mod token;
 mod tokenizer;
+mod value;
+pub mod vm;
 
-pub use error::LoxError;
+pub use callable::{Function, NativeFunction};
+pub use class::{Class, Instance};
+pub use diagnostic::{Diagnostic, Label, Renderer};
+pub use error::{Frame, LexError, LoxError, ResolveError, RuntimeError, SyntaxError, TypeError};
+pub use interpreter::Interpreter;
+pub use limits::{InterruptHandle, Limit, Limits, DEFAULT_MAX_DEPTH, MAX_NESTING};
+pub use module::Module;
 pub use parser::Parser;
-pub use position::WithSpan;
-pub use token::Token;
+pub use position::{FileId, WithSpan};
+pub use repl::Repl;
+pub use resolver::Resolver;
+pub use source::{LineIndex, Location, Position, SourceFile, SourceMap};
+pub use token::{Token, TokenKind};
 pub use tokenizer::Lexer;
+pub use value::{List, Value};
+pub use vm::HeapStats;
@@ -0,0 +1,157 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Call depth allowed when no other limit is set.
+pub const DEFAULT_MAX_DEPTH: usize = 1024;
+
+/// How deeply statements and expressions may nest. The parser, the
+/// resolver and the interpreter all reject deeper trees.
+pub const MAX_NESTING: usize = 1024;
+
+/// Stack left free before a recursive step, and the size of the segment
+/// allocated when less than that remains.
+const RED_ZONE: usize = 64 * 1024;
+const STACK_SEGMENT: usize = 1024 * 1024;
+
+/// Runs `f` on a fresh stack segment if the current one is nearly used up,
+/// so that deep recursion in Lox programs cannot overflow the Rust stack of
+/// whichever thread the interpreter runs on.
+#[inline]
+pub(crate) fn grow_stack<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(RED_ZONE, STACK_SEGMENT, f)
+}
+
+/// Bounds on the work a program may do before the interpreter stops it.
+/// Both backends accept them, through [`Interpreter::with_limits`](crate::Interpreter::with_limits)
+/// and [`Vm::with_limits`](crate::vm::Vm::with_limits).
+///
+/// Step and time budgets start afresh with each top-level call into the
+/// interpreter, such as [`Interpreter::eval_str`](crate::Interpreter::eval_str).
+/// The allocation limit applies to everything programs have left alive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    steps: Option<u64>,
+    depth: usize,
+    allocation: Option<usize>,
+    time: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            steps: None,
+            depth: DEFAULT_MAX_DEPTH,
+            allocation: None,
+            time: None,
+        }
+    }
+}
+
+impl Limits {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum number of statements and expressions evaluated, or of
+    /// instructions executed by the VM.
+    pub fn max_steps(mut self, steps: u64) -> Self {
+        self.steps = Some(steps);
+        self
+    }
+
+    /// Maximum number of nested function calls.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Maximum number of bytes held by live objects such as strings, lists
+    /// and instances.
+    /// Garbage is collected before a program is stopped, so a long loop
+    /// that only makes garbage keeps running.
+    pub fn max_allocation(mut self, bytes: usize) -> Self {
+        self.allocation = Some(bytes);
+        self
+    }
+
+    /// Maximum wall-clock running time.
+    pub fn timeout(mut self, time: Duration) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    #[inline]
+    pub fn steps(&self) -> Option<u64> {
+        self.steps
+    }
+
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    #[inline]
+    pub fn allocation(&self) -> Option<usize> {
+        self.allocation
+    }
+
+    #[inline]
+    pub fn time(&self) -> Option<Duration> {
+        self.time
+    }
+}
+
+/// Which limit stopped a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Steps,
+    Depth,
+    Allocation,
+    Time,
+    Interrupted,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Steps => "Step limit exceeded.",
+            Self::Depth => "Stack overflow.",
+            Self::Allocation => "Allocation limit exceeded.",
+            Self::Time => "Time limit exceeded.",
+            Self::Interrupted => "Interrupted.",
+        })
+    }
+}
+
+/// Stops a running interpreter from another thread. The program fails with
+/// [`Limit::Interrupted`] at its next step. An interrupt raised while
+/// nothing is running is dropped when the next run starts.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    #[inline]
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn clear(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    /// Clears a pending interrupt, returning whether there was one.
+    #[inline]
+    pub(crate) fn take(&self) -> bool {
+        // Checked on every step, so avoid the read-modify-write unless an
+        // interrupt is actually pending.
+        self.0.load(Ordering::Relaxed) && self.0.swap(false, Ordering::Relaxed)
+    }
+}
@@ -1,47 +1,170 @@
 use std::env;
 use std::fs;
-use std::io;
+use std::io::{self, IsTerminal};
 
+use codecrafters_interpreter::vm::{Vm, VmError};
+use codecrafters_interpreter::Interpreter;
 use codecrafters_interpreter::Lexer;
+use codecrafters_interpreter::LoxError;
 use codecrafters_interpreter::Parser;
+use codecrafters_interpreter::Renderer;
+use codecrafters_interpreter::Repl;
+use codecrafters_interpreter::Resolver;
+use codecrafters_interpreter::RuntimeError;
+use codecrafters_interpreter::SourceMap;
 use codecrafters_interpreter::Token;
 use codecrafters_interpreter::WithSpan;
 
+fn usage(program: &str) {
+    eprintln!(
+        "Usage: {} <tokenize|parse|evaluate|run> [--backend=tree|vm] [--diagnostics=short|rich] [--trivia] <filename>\n       {} [repl]",
+        program, program
+    );
+}
+
+fn report(renderer: &Renderer, errors: &[LoxError]) {
+    for error in errors {
+        eprintln!("{}", renderer.render(&error.diagnostic()).trim_end());
+    }
+}
+
+/// Short diagnostics keep the codecrafters `[line N]` format; otherwise
+/// the whole call stack is shown, as clox does, located in `sources`.
+fn report_runtime(error: &RuntimeError, short: bool, sources: &SourceMap) {
+    if short {
+        eprintln!("{error}");
+        return;
+    }
+    eprintln!("{}\n{}", error.message(), error.traceback_in(sources));
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        eprintln!("Usage: {} tokenize <filename>", args[0]);
+    if args.len() == 1 || args[1] == "repl" {
+        let stdin = io::stdin();
+        let prompts = stdin.is_terminal();
+        if let Err(e) = Repl::new().prompts(prompts).run(stdin.lock(), io::stdout()) {
+            eprintln!("{e}");
+            std::process::exit(74);
+        }
         return;
     }
 
     let command = &args[1];
-    let filename = &args[2];
+    let mut backend = "tree";
+    // Source excerpts are only shown to a person at a terminal unless asked
+    // for; piped output keeps the single-line format tools expect.
+    let mut short = !io::stderr().is_terminal();
+    // `tokenize` leaves out comments and whitespace unless asked for them.
+    let mut trivia = false;
+    let mut filename = None;
+    for arg in &args[2..] {
+        match arg.split_once('=') {
+            Some(("--backend", name @ ("tree" | "vm"))) => backend = name,
+            Some(("--diagnostics", "short")) => short = true,
+            Some(("--diagnostics", "rich")) => short = false,
+            None if arg == "--trivia" => trivia = true,
+            _ if arg.starts_with("--") || filename.is_some() => {
+                usage(&args[0]);
+                return;
+            }
+            _ => filename = Some(arg),
+        }
+    }
+    let Some(filename) = filename else {
+        usage(&args[0]);
+        return;
+    };
     let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
         eprintln!("Failed to read file {}", filename);
         String::new()
     });
+    let mut sources = SourceMap::new();
+    let file = sources.add(filename.as_str(), file_contents.as_str());
+    let renderer = Renderer::from_map(&sources)
+        .short(short)
+        .color(!short && io::stderr().is_terminal());
     let mut exit_code = 0;
     match command.as_str() {
         "tokenize" => {
-            let lx = Lexer::from(file_contents.as_str());
+            let mut lx = Lexer::from(file_contents.as_str()).with_file(file);
+            if trivia {
+                lx = lx.with_trivia();
+            }
             for token in lx {
-                let t = token.as_ref();
-                if t.is_ok() {
-                    println!("{}", t);
-                } else {
-                    exit_code = 65;
-                    eprintln!("{}", t);
+                match token.get_value() {
+                    Token::Error(e) => {
+                        exit_code = 65;
+                        let diagnostic = e.diagnostic(token.get_span());
+                        eprintln!("{}", renderer.render(&diagnostic).trim_end());
+                    }
+                    Token::DocComment(_) if !trivia => {}
+                    t => println!("{}", t),
                 }
             }
         }
         "parse" => {
-            let lx = Lexer::from(file_contents.as_str());
+            let lx = Lexer::from(file_contents.as_str()).with_file(file);
             let tokens = lx.collect::<Vec<WithSpan<Token>>>();
             let mut parser = Parser::<io::Sink>::new(&tokens, None);
             if let Ok(v) = parser.parse() {
                 println!("{}", v.get_value());
             } else {
                 exit_code = 65;
+                report(&renderer, parser.errors());
+            }
+        }
+        "evaluate" => {
+            let lx = Lexer::from(file_contents.as_str()).with_file(file);
+            let tokens = lx.collect::<Vec<WithSpan<Token>>>();
+            let mut parser = Parser::<io::Sink>::new(&tokens, None);
+            if let Ok(expr) = parser.parse() {
+                match Interpreter::new().evaluate(&expr) {
+                    Ok(v) => println!("{v}"),
+                    Err(e) => {
+                        exit_code = 70;
+                        eprintln!("{e}");
+                    }
+                }
+            } else {
+                exit_code = 65;
+                report(&renderer, parser.errors());
+            }
+        }
+        "run" => {
+            let lx = Lexer::from(file_contents.as_str()).with_file(file);
+            let tokens = lx.collect::<Vec<WithSpan<Token>>>();
+            let mut parser = Parser::<io::Sink>::new(&tokens, None);
+            let parsed = parser.parse_program();
+            report(&renderer, parser.errors());
+            let mut resolver = Resolver::<io::Sink>::new(None);
+            let resolved = parsed.and_then(|mut ast| {
+                let result = resolver.resolve(&mut ast);
+                report(&renderer, resolver.errors());
+                result.map(|_| ast)
+            });
+            match resolved {
+                Ok(ast) if backend == "vm" => match Vm::new().interpret(&ast) {
+                    Ok(()) => {}
+                    Err(e @ VmError::Compile(_)) => {
+                        exit_code = 65;
+                        eprintln!("{e}");
+                    }
+                    Err(VmError::Runtime(e)) => {
+                        exit_code = 70;
+                        report_runtime(&e, short, &sources);
+                    }
+                },
+                Ok(ast) => {
+                    let mut interpreter = Interpreter::new()
+                        .with_path(filename)
+                        .with_sources(sources.clone());
+                    if let Err(e) = interpreter.interpret(&ast) {
+                        exit_code = 70;
+                        report_runtime(&e, short, interpreter.sources());
+                    }
+                }
+                Err(_) => exit_code = 65,
             }
         }
         _ => {
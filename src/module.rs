@@ -0,0 +1,150 @@
+//! Files loaded by `import`.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    env, fmt,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{ast::Stmt, value::Value, WithSpan};
+
+/// The global variables of one file. Functions keep a handle to the table
+/// of the file that declared them, so they see their own module's globals
+/// wherever they are called from.
+pub(crate) type Globals = Rc<RefCell<HashMap<String, Value>>>;
+
+/// A loaded file. Its top-level declarations are read as properties of the
+/// module value, e.g. `math.sqrt`.
+#[derive(Debug)]
+pub struct Module {
+    name: String,
+    path: PathBuf,
+    globals: Globals,
+    exports: HashSet<String>,
+}
+
+impl Module {
+    pub(crate) fn new(name: &str, path: PathBuf, globals: Globals, ast: &[WithSpan<Stmt>]) -> Self {
+        let exports = ast
+            .iter()
+            .filter_map(|stmt| match stmt.get_value() {
+                Stmt::Var(name, _) | Stmt::Import(_, name) => Some(name.get_value().to_string()),
+                Stmt::Function(decl) => Some(decl.name.get_value().to_string()),
+                Stmt::Class(decl) => Some(decl.name.get_value().to_string()),
+                _ => None,
+            })
+            .collect();
+        Self {
+            name: name.to_string(),
+            path,
+            globals,
+            exports,
+        }
+    }
+
+    /// The name the module was first imported as.
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the current value of a top-level declaration.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        if !self.exports.contains(name) {
+            return None;
+        }
+        self.globals.borrow().get(name).cloned()
+    }
+}
+
+/// Finds module files, caches loaded modules and tracks the chain of
+/// files currently being executed so circular imports can be reported.
+#[derive(Debug)]
+pub(crate) struct Loader {
+    search_path: Vec<PathBuf>,
+    cache: HashMap<PathBuf, Rc<Module>>,
+    /// Canonical and as-found paths of the files being executed, outermost
+    /// first.
+    loading: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Default for Loader {
+    fn default() -> Self {
+        let search_path = env::var_os("LOXPATH")
+            .map(|paths| env::split_paths(&paths).collect())
+            .unwrap_or_default();
+        Self {
+            search_path,
+            cache: HashMap::new(),
+            loading: Vec::new(),
+        }
+    }
+}
+
+impl Loader {
+    /// Resolves `path` against the directory of the importing file, then
+    /// against each directory in `LOXPATH`.
+    pub(crate) fn find(&self, path: &str) -> Option<PathBuf> {
+        let path = Path::new(path);
+        if path.is_absolute() {
+            return path.is_file().then(|| path.to_path_buf());
+        }
+        let base = self
+            .loading
+            .last()
+            .and_then(|(_, file)| file.parent())
+            .map_or_else(PathBuf::new, Path::to_path_buf);
+        std::iter::once(&base)
+            .chain(&self.search_path)
+            .map(|dir| dir.join(path))
+            .find(|file| file.is_file())
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, canonical: &Path) -> Option<Rc<Module>> {
+        self.cache.get(canonical).cloned()
+    }
+
+    #[inline]
+    pub(crate) fn insert(&mut self, canonical: PathBuf, module: Rc<Module>) {
+        self.cache.insert(canonical, module);
+    }
+
+    /// If `canonical` is already being executed, returns the import chain
+    /// that leads back to it, e.g. `main.lox -> a.lox -> main.lox`.
+    pub(crate) fn cycle(&self, canonical: &Path, file: &Path) -> Option<String> {
+        if !self.loading.iter().any(|(c, _)| c == canonical) {
+            return None;
+        }
+        let chain = self
+            .loading
+            .iter()
+            .map(|(_, file)| file.as_path())
+            .chain([file])
+            .map(|file| file.display().to_string())
+            .collect::<Vec<_>>();
+        Some(chain.join(" -> "))
+    }
+
+    /// Marks `file` as being executed until the matching [`Loader::exit`].
+    pub(crate) fn enter(&mut self, canonical: PathBuf, file: PathBuf) {
+        self.loading.push((canonical, file));
+    }
+
+    pub(crate) fn exit(&mut self) {
+        self.loading.pop();
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<module {}>", self.name)
+    }
+}
@@ -0,0 +1,102 @@
+//! The one place numbers are turned into text, so that `tokenize`,
+//! `parse`, `print` and string conversion all agree.
+
+/// Magnitudes outside `[MIN_PLAIN, MAX_PLAIN)` are written with an
+/// exponent. Inside the range, integer digits past the precision of an
+/// `f64` are written as zeros.
+const MIN_PLAIN: f64 = 1e-7;
+const MAX_PLAIN: f64 = 1e21;
+
+/// Formats a number the way `print` shows it: the shortest text that reads
+/// back as the same value, such as `3`, `0.1`, `-0`, `1e21`, `inf` or
+/// `nan`.
+pub(crate) fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        return "nan".to_string();
+    }
+    if n.is_infinite() {
+        return if n > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let magnitude = n.abs();
+    if magnitude != 0.0 && !(MIN_PLAIN..MAX_PLAIN).contains(&magnitude) {
+        format!("{n:e}")
+    } else {
+        format!("{n}")
+    }
+}
+
+/// Formats a number the way the tokenizer and AST printer show literals:
+/// as [`format_number`], but integers keep a `.0` to mark them as numbers.
+pub(crate) fn format_literal(n: f64) -> String {
+    let mut s = format_number(n);
+    if n.is_finite() && !s.contains(['.', 'e']) {
+        s.push_str(".0");
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integers() {
+        assert_eq!(format_number(0.0), "0");
+        assert_eq!(format_number(42.0), "42");
+        assert_eq!(format_number(-7.0), "-7");
+        assert_eq!(format_number(9007199254740992.0), "9007199254740992");
+        // Above i64::MAX, where a cast would saturate; only the significant
+        // digits are kept.
+        assert_eq!(format_number(2f64.powi(64)), "18446744073709552000");
+        assert_eq!(format_number(1e20), "100000000000000000000");
+        assert_eq!(format_literal(42.0), "42.0");
+        assert_eq!(format_literal(1e20), "100000000000000000000.0");
+    }
+
+    #[test]
+    fn test_fractions() {
+        assert_eq!(format_number(0.1), "0.1");
+        assert_eq!(format_number(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(format_number(-2.5), "-2.5");
+        assert_eq!(format_number(1e-7), "0.0000001");
+        assert_eq!(format_literal(1234.1234), "1234.1234");
+    }
+
+    #[test]
+    fn test_exponents() {
+        assert_eq!(format_number(1e21), "1e21");
+        assert_eq!(format_number(-1.5e300), "-1.5e300");
+        assert_eq!(format_number(1e-9), "1e-9");
+        assert_eq!(format_number(f64::MAX), "1.7976931348623157e308");
+        assert_eq!(format_number(f64::MIN_POSITIVE), "2.2250738585072014e-308");
+        assert_eq!(format_number(5e-324), "5e-324");
+        assert_eq!(format_literal(1e21), "1e21");
+    }
+
+    #[test]
+    fn test_special_values() {
+        assert_eq!(format_number(-0.0), "-0");
+        assert_eq!(format_literal(-0.0), "-0.0");
+        assert_eq!(format_number(f64::INFINITY), "inf");
+        assert_eq!(format_number(f64::NEG_INFINITY), "-inf");
+        assert_eq!(format_number(f64::NAN), "nan");
+        assert_eq!(format_literal(f64::INFINITY), "inf");
+        assert_eq!(format_literal(f64::NAN), "nan");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let values = [
+            0.1,
+            1.0 / 3.0,
+            123456.789,
+            1e-8,
+            6.02214076e23,
+            2f64.powi(53) + 2.0,
+        ];
+        for n in values {
+            assert_eq!(format_number(n).parse::<f64>(), Ok(n));
+            assert_eq!(format_literal(n).parse::<f64>(), Ok(n));
+        }
+    }
+}
@@ -0,0 +1,364 @@
+//! Bookkeeping for the objects the tree-walking interpreter creates, and
+//! the collector that frees the reference cycles among them.
+
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap},
+    mem,
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    builtins::{self, Meter},
+    callable::Function,
+    class::{Class, Instance},
+    environment::Environment,
+    value::{drop_values, List, Value},
+    vm::HeapStats,
+};
+
+/// Objects tracked before freed ones are first swept from [`Objects`].
+const FIRST_SWEEP: usize = 1024;
+
+/// Bytes that may be allocated before the first collection runs.
+const FIRST_COLLECTION: usize = 1024 * 1024;
+
+/// Weak references to the objects programs have created, for
+/// [`Interpreter::heap_stats`](crate::Interpreter::heap_stats) and for
+/// collecting the ones that only keep each other alive.
+///
+/// Reference counting frees everything but cycles. [`Objects::collect`]
+/// finds those by trial deletion: every object reachable from a tracked one
+/// has the references held by the others subtracted from its count, so
+/// what remains comes from outside them, such as the globals, the running
+/// environments or the host. Objects that can't be reached from one with
+/// such a reference are garbage, and clearing them breaks their cycles.
+#[derive(Debug, Default)]
+pub(crate) struct Objects {
+    instances: Vec<Weak<RefCell<Instance>>>,
+    lists: Vec<Weak<RefCell<List>>>,
+    strings: Vec<Weak<str>>,
+    /// Environments captured by closures. Only these can be part of a
+    /// cycle, through the functions stored in them.
+    closures: Vec<Weak<RefCell<Environment>>>,
+    /// Freed strings, instances and lists already swept from the vectors
+    /// above.
+    freed: usize,
+    /// Number of tracked objects at which the next sweep runs.
+    next_sweep: usize,
+    /// Live bytes measured by the last collection plus bytes allocated
+    /// since, shared with the list natives that grow lists.
+    bytes: Meter,
+    /// Bytes at which the next collection runs.
+    next_collection: usize,
+    collections: usize,
+    stress: bool,
+}
+
+impl Objects {
+    pub(crate) fn track_instance(&mut self, instance: &Rc<RefCell<Instance>>) {
+        self.instances.push(Rc::downgrade(instance));
+        self.sweep_if_due();
+    }
+
+    pub(crate) fn track_list(&mut self, list: &Rc<RefCell<List>>) {
+        self.lists.push(Rc::downgrade(list));
+        self.sweep_if_due();
+    }
+
+    pub(crate) fn track_string(&mut self, string: &Rc<str>) {
+        self.strings.push(Rc::downgrade(string));
+        self.sweep_if_due();
+    }
+
+    pub(crate) fn track_closure(&mut self, environment: &Rc<RefCell<Environment>>) {
+        self.closures.push(Rc::downgrade(environment));
+        self.sweep_if_due();
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.instances.len() + self.lists.len() + self.strings.len()
+    }
+
+    /// Forgets freed objects once twice as many are tracked as survived the
+    /// last sweep, so tracking takes amortized constant time.
+    fn sweep_if_due(&mut self) {
+        if self.len() + self.closures.len() >= self.next_sweep.max(FIRST_SWEEP) {
+            self.sweep();
+        }
+    }
+
+    fn sweep(&mut self) {
+        let before = self.len();
+        self.instances
+            .retain(|instance| instance.strong_count() > 0);
+        self.lists.retain(|list| list.strong_count() > 0);
+        self.strings.retain(|string| string.strong_count() > 0);
+        self.closures
+            .retain(|environment| environment.strong_count() > 0);
+        self.freed += before - self.len();
+        self.next_sweep = (self.len() + self.closures.len()) * 2;
+    }
+
+    #[inline]
+    pub(crate) fn meter(&self) -> &Meter {
+        &self.bytes
+    }
+
+    /// Bytes the program's objects hold, counting garbage allocated since
+    /// the last collection.
+    #[inline]
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes.get()
+    }
+
+    /// Records an allocation of `bytes` made for the program.
+    #[inline]
+    pub(crate) fn charge(&mut self, bytes: usize) {
+        builtins::charge(&self.bytes, bytes);
+    }
+
+    #[inline]
+    pub(crate) fn set_stress(&mut self, stress: bool) {
+        self.stress = stress;
+    }
+
+    #[inline]
+    pub(crate) fn should_collect(&self) -> bool {
+        self.stress || self.bytes() > self.next_collection.max(FIRST_COLLECTION)
+    }
+
+    /// Frees every object kept alive only by cycles, returning the number
+    /// of instances and lists freed.
+    pub(crate) fn collect(&mut self) -> usize {
+        let tracked = self
+            .instances
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(Node::Instance)
+            .chain(self.lists.iter().filter_map(Weak::upgrade).map(Node::List))
+            .chain(
+                self.closures
+                    .iter()
+                    .filter_map(Weak::upgrade)
+                    .map(Node::Environment),
+            );
+        let mut graph = Graph::default();
+        for node in tracked {
+            graph.add(node);
+        }
+        graph.discover();
+        let live = graph.mark();
+
+        let mut values = Vec::new();
+        let mut freed = 0;
+        for (node, _) in graph.nodes.iter().zip(live).filter(|(_, live)| !live) {
+            freed += usize::from(matches!(node, Node::Instance(_) | Node::List(_)));
+            node.clear(&mut values);
+        }
+        drop(graph);
+        drop_values(values);
+
+        self.sweep();
+        self.collections += 1;
+        self.bytes.set(self.stats().live_bytes);
+        self.next_collection = (self.bytes() * 2).max(FIRST_COLLECTION);
+        freed
+    }
+
+    pub(crate) fn stats(&self) -> HeapStats {
+        let instances = self
+            .instances
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|instance| {
+                mem::size_of::<Instance>()
+                    + instance.try_borrow().map_or(0, |instance| {
+                        instance.fields.capacity() * mem::size_of::<(String, Value)>()
+                    })
+            });
+        let lists = self.lists.iter().filter_map(Weak::upgrade).map(|list| {
+            mem::size_of::<List>()
+                + list
+                    .try_borrow()
+                    .map_or(0, |list| list.capacity() * mem::size_of::<Value>())
+        });
+        let strings = self
+            .strings
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|string| string.len());
+        let (live_objects, live_bytes) = instances
+            .chain(lists)
+            .chain(strings)
+            .fold((0, 0), |(count, bytes), size| (count + 1, bytes + size));
+        HeapStats {
+            live_objects,
+            live_bytes,
+            collections: self.collections,
+            freed_objects: self.freed + self.len() - live_objects,
+            next_collection: self.next_collection.max(FIRST_COLLECTION),
+        }
+    }
+}
+
+/// An object that can hold references to other objects.
+enum Node {
+    Environment(Rc<RefCell<Environment>>),
+    Function(Rc<Function>),
+    Class(Rc<Class>),
+    Instance(Rc<RefCell<Instance>>),
+    List(Rc<RefCell<List>>),
+}
+
+impl Node {
+    /// Native functions and modules are left out: whatever they refer to
+    /// counts as referenced from outside, which keeps it alive.
+    fn of(value: &Value) -> Option<Node> {
+        match value {
+            Value::Function(function) => Some(Node::Function(Rc::clone(function))),
+            Value::Class(class) => Some(Node::Class(Rc::clone(class))),
+            Value::Instance(instance) => Some(Node::Instance(Rc::clone(instance))),
+            Value::List(list) => Some(Node::List(Rc::clone(list))),
+            _ => None,
+        }
+    }
+
+    fn address(&self) -> *const () {
+        match self {
+            Node::Environment(environment) => Rc::as_ptr(environment).cast(),
+            Node::Function(function) => Rc::as_ptr(function).cast(),
+            Node::Class(class) => Rc::as_ptr(class).cast(),
+            Node::Instance(instance) => Rc::as_ptr(instance).cast(),
+            Node::List(list) => Rc::as_ptr(list).cast(),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Environment(environment) => Rc::strong_count(environment),
+            Node::Function(function) => Rc::strong_count(function),
+            Node::Class(class) => Rc::strong_count(class),
+            Node::Instance(instance) => Rc::strong_count(instance),
+            Node::List(list) => Rc::strong_count(list),
+        }
+    }
+
+    /// Pushes the objects this one refers to, once per reference. Returns
+    /// false if the object is borrowed mutably and can't be read.
+    fn children(&self, children: &mut Vec<Node>) -> bool {
+        match self {
+            Node::Environment(environment) => {
+                let Ok(environment) = environment.try_borrow() else {
+                    return false;
+                };
+                children.extend(environment.values.iter().filter_map(Node::of));
+                children.extend(environment.enclosing.clone().map(Node::Environment));
+            }
+            Node::Function(function) => {
+                children.extend(function.closure.clone().map(Node::Environment));
+            }
+            Node::Class(class) => {
+                children.extend(class.superclass.clone().map(Node::Class));
+                children.extend(class.methods.values().cloned().map(Node::Function));
+            }
+            Node::Instance(instance) => {
+                let Ok(instance) = instance.try_borrow() else {
+                    return false;
+                };
+                children.push(Node::Class(Rc::clone(&instance.class)));
+                children.extend(instance.fields.values().filter_map(Node::of));
+            }
+            Node::List(list) => {
+                let Ok(list) = list.try_borrow() else {
+                    return false;
+                };
+                children.extend(list.iter().filter_map(Node::of));
+            }
+        }
+        true
+    }
+
+    /// Moves the values a garbage object holds into `values`. Functions
+    /// and classes can't be changed, and are freed along with the objects
+    /// that refer to them.
+    fn clear(&self, values: &mut Vec<Value>) {
+        match self {
+            Node::Environment(environment) => {
+                if let Ok(mut environment) = environment.try_borrow_mut() {
+                    values.append(&mut environment.values);
+                }
+            }
+            Node::Instance(instance) => {
+                if let Ok(mut instance) = instance.try_borrow_mut() {
+                    values.extend(mem::take(&mut instance.fields).into_values());
+                }
+            }
+            Node::List(list) => {
+                if let Ok(mut list) = list.try_borrow_mut() {
+                    values.append(&mut list);
+                }
+            }
+            Node::Function(_) | Node::Class(_) => {}
+        }
+    }
+}
+
+/// The objects reachable from the tracked ones, each held once.
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Node>,
+    index: HashMap<*const (), usize>,
+    /// The nodes each node refers to, or `None` if it couldn't be read.
+    edges: Vec<Option<Vec<usize>>>,
+}
+
+impl Graph {
+    fn add(&mut self, node: Node) -> usize {
+        match self.index.entry(node.address()) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                entry.insert(self.nodes.len());
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn discover(&mut self) {
+        let mut children = Vec::new();
+        let mut i = 0;
+        while i < self.nodes.len() {
+            let edges = self.nodes[i]
+                .children(&mut children)
+                .then(|| children.drain(..).map(|child| self.add(child)).collect());
+            self.edges.push(edges);
+            i += 1;
+        }
+    }
+
+    /// Finds the nodes referenced from outside the graph, or that couldn't
+    /// be read, and everything reachable from them.
+    fn mark(&self) -> Vec<bool> {
+        // Each node is held once by the graph itself.
+        let mut outside: Vec<usize> = self
+            .nodes
+            .iter()
+            .map(|node| node.strong_count() - 1)
+            .collect();
+        for &target in self.edges.iter().flatten().flatten() {
+            outside[target] -= 1;
+        }
+        let mut stack = (0..self.nodes.len())
+            .filter(|&i| outside[i] > 0 || self.edges[i].is_none())
+            .collect::<Vec<_>>();
+        let mut live = vec![false; self.nodes.len()];
+        while let Some(i) = stack.pop() {
+            if !mem::replace(&mut live[i], true) {
+                stack.extend(self.edges[i].iter().flatten());
+            }
+        }
+        live
+    }
+}
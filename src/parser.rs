@@ -1,20 +1,27 @@
-use std::{
-    borrow::Cow,
-    fmt,
-    io::{self, ErrorKind},
-    marker::PhantomData,
-};
+use std::{borrow::Cow, io, marker::PhantomData, ops::Range, path::Path, rc::Rc};
 
 use crate::{
-    ast::{BinaryOperator, Expr, UnaryOperator},
+    ast::{
+        Ast, BinaryOperator, ClassDecl, Expr, FunctionDecl, LogicalOperator, Stmt, UnaryOperator,
+    },
+    error::{LoxError, SyntaxError},
+    limits::{grow_stack, MAX_NESTING},
     token::TokenKind,
-    Token, WithSpan,
+    FileId, Token, WithSpan,
 };
 
+pub type ParseResult<T> = Result<T, LoxError>;
+
 pub struct Parser<'a, W: io::Write> {
     tokens: &'a [WithSpan<Token<'a>>],
+    /// The file the tokens were read from, recorded on every node.
+    file: FileId,
     cursor: usize,
+    /// How many statements and expressions enclose the one being parsed.
+    depth: usize,
     diagnostics: Option<W>,
+    lex_errors_reported: bool,
+    errors: Vec<LoxError>,
     _marker: PhantomData<&'a W>,
 }
 
@@ -22,37 +29,134 @@ impl<'a, W: io::Write> Parser<'a, W> {
     pub fn new(tokens: &'a [WithSpan<Token<'a>>], diagnostics: Option<W>) -> Self {
         Self {
             tokens,
+            file: tokens
+                .first()
+                .map_or_else(FileId::default, WithSpan::get_file),
             cursor: 0,
+            depth: 0,
             diagnostics,
+            lex_errors_reported: false,
+            errors: Vec::new(),
             _marker: PhantomData,
         }
     }
 
     pub fn is_eof(&self) -> bool {
-        if let Some(v) = self.tokens.get(self.cursor) {
+        if let Some(v) = self.tokens.get(self.position()) {
             v.get_value().is_eof()
         } else {
-            false
+            true
+        }
+    }
+
+    /// Index of the next token to parse. Tokens the lexer could not scan
+    /// are skipped here; they are reported before parsing starts. So are
+    /// whitespace and comments, doc comments included.
+    fn position(&self) -> usize {
+        let mut position = self.cursor;
+        while self
+            .tokens
+            .get(position)
+            .is_some_and(|t| t.get_value().is_skipped())
+        {
+            position += 1;
         }
+        position
+    }
+
+    fn previous(&self) -> Option<&'a Token<'a>> {
+        self.tokens[..self.cursor]
+            .iter()
+            .rev()
+            .map(|t| t.get_value())
+            .find(|t| !t.is_skipped())
     }
 
-    pub fn error<E: fmt::Display>(&mut self, msg: E) -> io::Result<()> {
+    /// Records `error`, writing it to the diagnostics sink, and hands it
+    /// back so the caller can bail out with it.
+    pub fn error(&mut self, error: LoxError) -> LoxError {
         if let Some(f) = self.diagnostics.as_mut() {
-            write!(f, "{}", msg)?;
+            // Diagnostics are best effort; a failing sink must not hide
+            // the error from the caller.
+            let _ = writeln!(f, "{}", error);
         }
+        self.errors.push(error.clone());
+        error
+    }
+
+    fn syntax_error(&mut self, error: SyntaxError, token: &WithSpan<Token>) -> LoxError {
+        self.error(self.node(error, token.get_line(), token.get_span()).into())
+    }
+
+    /// Reports that `token` is not what the parser wanted: `expected`, or
+    /// the start of an expression if `None`.
+    fn unexpected(&mut self, token: &WithSpan<Token>, expected: Option<TokenKind>) -> LoxError {
+        let found = TokenKind::from(token.get_value());
+        let lexeme = token.get_value().lexeme().into_owned();
+        let error = match expected {
+            _ if found == TokenKind::Eof => SyntaxError::UnexpectedEof { expected },
+            Some(expected) => SyntaxError::ExpectedToken {
+                expected,
+                found,
+                lexeme,
+            },
+            None => SyntaxError::ExpectedExpression { found, lexeme },
+        };
+        self.syntax_error(error, token)
+    }
+
+    fn unexpected_token(&mut self, token: &WithSpan<Token>) -> LoxError {
+        let error = SyntaxError::UnexpectedToken {
+            found: TokenKind::from(token.get_value()),
+            lexeme: token.get_value().lexeme().into_owned(),
+        };
+        self.syntax_error(error, token)
+    }
+
+    /// Goes one level deeper, failing with [`SyntaxError::TooMuchNesting`]
+    /// past [`MAX_NESTING`].
+    fn deepen(&mut self) -> ParseResult<()> {
+        if self.depth >= MAX_NESTING {
+            let token = self.peek_token();
+            let lexeme = token.get_value().lexeme().into_owned();
+            return Err(self.syntax_error(SyntaxError::TooMuchNesting { lexeme }, &token));
+        }
+        self.depth += 1;
         Ok(())
     }
 
+    /// Runs `parse` one level deeper, coming back up whether it succeeds
+    /// or not.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        let depth = self.depth;
+        self.deepen()?;
+        let result = grow_stack(|| parse(self));
+        self.depth = depth;
+        result
+    }
+
+    /// Wraps a node parsed from this parser's tokens with its location.
+    #[inline]
+    fn node<T>(&self, value: T, line: usize, span: Range<usize>) -> WithSpan<T> {
+        WithSpan::new(value, line, span).file(self.file)
+    }
+
+    /// Every error reported so far, in source order.
+    #[inline]
+    pub fn errors(&self) -> &[LoxError] {
+        &self.errors
+    }
+
     #[inline]
     pub fn peek(&self) -> TokenKind {
-        if let Some(v) = self.tokens.get(self.cursor) {
+        if let Some(v) = self.tokens.get(self.position()) {
             v.get_value().into()
         } else {
             TokenKind::Eof
         }
     }
     pub fn peek_token(&self) -> Cow<'a, WithSpan<Token<'a>>> {
-        if let Some(token) = self.tokens.get(self.cursor) {
+        if let Some(token) = self.tokens.get(self.position()) {
             Cow::Borrowed(token)
         } else {
             Cow::Owned(WithSpan::empty(Token::Eof))
@@ -63,35 +167,29 @@ impl<'a, W: io::Write> Parser<'a, W> {
         self.peek() == match_token
     }
 
-    pub fn advance(&mut self) -> io::Result<&'a WithSpan<Token<'a>>> {
-        if let Some(v) = self.tokens.get(self.cursor) {
-            self.cursor += 1;
+    pub fn advance(&mut self) -> ParseResult<&'a WithSpan<Token<'a>>> {
+        let position = self.position();
+        if let Some(v) = self.tokens.get(position) {
+            self.cursor = position + 1;
             Ok(v)
         } else {
-            Err(io::Error::new(ErrorKind::NotFound, "Token Not Found."))
+            Err(self.unexpected(&WithSpan::empty(Token::Eof), None))
         }
     }
 
-    pub fn expect(&mut self, expected: TokenKind) -> io::Result<&'a WithSpan<Token<'a>>> {
-        let token = self.advance()?;
-        if TokenKind::from(token.get_value()) == expected {
-            Ok(token)
-        } else {
-            self.error(format!(
-                "[line {}] Error at {}: Expect expression.",
-                token.get_line(),
-                token.get_value()
-            ))?;
-            Err(io::Error::new(
-                ErrorKind::InvalidInput,
-                "Expected Token Not Found.",
-            ))
+    /// Consumes the next token if it is `expected`, otherwise reports an
+    /// error at it and leaves it in place.
+    pub fn expect(&mut self, expected: TokenKind) -> ParseResult<&'a WithSpan<Token<'a>>> {
+        if self.check(expected) {
+            return self.advance();
         }
+        let token = self.peek_token();
+        Err(self.unexpected(&token, Some(expected)))
     }
 
-    pub fn optionally(&mut self, expected: TokenKind) -> io::Result<bool> {
+    pub fn optionally(&mut self, expected: TokenKind) -> ParseResult<bool> {
         let token = self.peek();
-        if TokenKind::from(token) == expected {
+        if token == expected {
             self.expect(expected)?;
             Ok(true)
         } else {
@@ -99,23 +197,397 @@ impl<'a, W: io::Write> Parser<'a, W> {
         }
     }
 
-    pub fn parse(&mut self) -> io::Result<WithSpan<Expr<'a>>> {
-        self.parse_expr(Precedence::None)
+    /// Parses a single expression. Fails if any error was reported.
+    pub fn parse(&mut self) -> ParseResult<WithSpan<Expr<'a>>> {
+        self.report_lex_errors();
+        let expr = self.parse_expr(Precedence::None)?;
+        self.finish(expr)
     }
 
-    fn parse_expr(&mut self, precedence: Precedence) -> io::Result<WithSpan<Expr<'a>>> {
-        let mut expr = self.parse_prefix()?;
+    /// Parses a whole program. Fails if any error was reported; use
+    /// [`Parser::parse_program_partial`] to get the declarations that did
+    /// parse.
+    pub fn parse_program(&mut self) -> ParseResult<Ast<'a>> {
+        let ast = self.parse_program_partial()?;
+        self.finish(ast)
+    }
+
+    /// Parses a whole program, recovering from syntax errors at the next
+    /// statement boundary so that every error is reported in one pass.
+    /// Declarations containing errors are left out of the returned `Ast`;
+    /// the errors themselves are available from [`Parser::errors`].
+    pub fn parse_program_partial(&mut self) -> ParseResult<Ast<'a>> {
+        self.report_lex_errors();
+        let mut ast = Vec::new();
         while !self.is_eof() {
-            let next_precedence = Precedence::from(self.peek());
-            if precedence >= next_precedence {
-                break;
+            let start = self.cursor;
+            match self.parse_declaration() {
+                Ok(stmt) => ast.push(stmt),
+                Err(_) => {
+                    if self.cursor == start {
+                        self.advance()?;
+                    }
+                    self.synchronize()?;
+                }
             }
-            expr = self.parse_infix(expr)?;
         }
-        Ok(expr)
+        Ok(ast)
     }
 
-    fn parse_prefix(&mut self) -> io::Result<WithSpan<Expr<'a>>> {
+    /// Fails with the first error reported, if any.
+    fn finish<T>(&self, value: T) -> ParseResult<T> {
+        match self.errors.first() {
+            Some(error) => Err(error.clone()),
+            None => Ok(value),
+        }
+    }
+
+    fn report_lex_errors(&mut self) {
+        if std::mem::replace(&mut self.lex_errors_reported, true) {
+            return;
+        }
+        for token in self.tokens {
+            if let Token::Error(e) = token.get_value() {
+                let error = self.node(e.clone(), token.get_line(), token.get_span());
+                self.error(error.into());
+            }
+        }
+    }
+
+    /// Skips tokens until the start of the next statement.
+    fn synchronize(&mut self) -> ParseResult<()> {
+        while !self.is_eof() {
+            if matches!(self.previous(), Some(Token::Semicolon)) {
+                return Ok(());
+            }
+            match self.peek() {
+                TokenKind::Class
+                | TokenKind::Fun
+                | TokenKind::Var
+                | TokenKind::For
+                | TokenKind::If
+                | TokenKind::While
+                | TokenKind::Print
+                | TokenKind::Return
+                | TokenKind::Import => return Ok(()),
+                _ => {
+                    self.advance()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_declaration(&mut self) -> ParseResult<WithSpan<Stmt<'a>>> {
+        match self.peek() {
+            TokenKind::Var => self.parse_var_declaration(),
+            TokenKind::Class => self.parse_class(),
+            TokenKind::Import => self.parse_import(),
+            TokenKind::Fun => {
+                let fun = self.expect(TokenKind::Fun)?;
+                let decl = self.parse_function()?;
+                let range = fun.get_span().start..decl.get_span().end;
+                Ok(self.node(Stmt::Function(Rc::new(decl._value)), fun.get_line(), range))
+            }
+            _ => self.parse_statement(),
+        }
+    }
+
+    fn parse_var_declaration(&mut self) -> ParseResult<WithSpan<Stmt<'a>>> {
+        let var = self.expect(TokenKind::Var)?;
+        let name = self.expect_identifier()?;
+        let initializer = if self.optionally(TokenKind::Equal)? {
+            Some(Box::new(self.parse_expr(Precedence::None)?))
+        } else {
+            None
+        };
+        let semicolon = self.expect(TokenKind::Semicolon)?;
+        Ok(self.node(
+            Stmt::Var(name, initializer),
+            var.get_line(),
+            var.get_span().start..semicolon.get_span().end,
+        ))
+    }
+
+    /// `import "path";` or `import "path" as name;`. `as` is only special
+    /// in this position, so it remains usable as an identifier elsewhere.
+    fn parse_import(&mut self) -> ParseResult<WithSpan<Stmt<'a>>> {
+        let import = self.expect(TokenKind::Import)?;
+        let tc = self.expect(TokenKind::String)?;
+        let Token::String(ref path, _) = tc.get_value() else {
+            return Err(self.unexpected(tc, Some(TokenKind::String)));
+        };
+        let name = if matches!(self.peek_token().get_value(), Token::Identifier(id) if id == "as") {
+            self.advance()?;
+            self.expect_identifier()?
+        } else {
+            let stem = Path::new(path.as_ref())
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .filter(|stem| is_identifier(stem));
+            let Some(stem) = stem else {
+                let path = path.to_string();
+                return Err(self.syntax_error(SyntaxError::InvalidModuleName { path }, tc));
+            };
+            self.node(Cow::Owned(stem.to_string()), tc.get_line(), tc.get_span())
+        };
+        let semicolon = self.expect(TokenKind::Semicolon)?;
+        let path = self.node(Cow::Borrowed(path.as_ref()), tc.get_line(), tc.get_span());
+        Ok(self.node(
+            Stmt::Import(path, name),
+            import.get_line(),
+            import.get_span().start..semicolon.get_span().end,
+        ))
+    }
+
+    fn parse_class(&mut self) -> ParseResult<WithSpan<Stmt<'a>>> {
+        let class = self.expect(TokenKind::Class)?;
+        let name = self.expect_identifier()?;
+        let superclass = if self.optionally(TokenKind::Less)? {
+            Some(
+                self.expect_identifier()?
+                    .map(|name| Expr::Variable(name, None)),
+            )
+        } else {
+            None
+        };
+        self.expect(TokenKind::LeftBrace)?;
+        let mut methods = Vec::new();
+        while !self.check(TokenKind::RightBrace) && !self.check(TokenKind::Eof) {
+            methods.push(Rc::new(self.parse_function()?._value));
+        }
+        let right_brace = self.expect(TokenKind::RightBrace)?;
+        Ok(self.node(
+            Stmt::Class(ClassDecl {
+                name,
+                superclass,
+                methods,
+            }),
+            class.get_line(),
+            class.get_span().start..right_brace.get_span().end,
+        ))
+    }
+
+    fn parse_function(&mut self) -> ParseResult<WithSpan<FunctionDecl<'a>>> {
+        self.nested(Self::parse_function_decl)
+    }
+
+    fn parse_function_decl(&mut self) -> ParseResult<WithSpan<FunctionDecl<'a>>> {
+        let name = self.expect_identifier()?;
+        self.expect(TokenKind::LeftParen)?;
+        let mut params = Vec::new();
+        if !self.check(TokenKind::RightParen) {
+            loop {
+                if params.len() >= 255 {
+                    let token = self.peek_token();
+                    let lexeme = token.get_value().lexeme().into_owned();
+                    return Err(
+                        self.syntax_error(SyntaxError::TooManyParameters { lexeme }, &token)
+                    );
+                }
+                params.push(self.expect_identifier()?);
+                if !self.optionally(TokenKind::Comma)? {
+                    break;
+                }
+            }
+        }
+        self.expect(TokenKind::RightParen)?;
+        let body = self.parse_block()?;
+        let line = name.get_line();
+        let range = name.get_span().start..body.get_span().end;
+        Ok(self.node(
+            FunctionDecl {
+                name,
+                params,
+                body: body._value,
+            },
+            line,
+            range,
+        ))
+    }
+
+    fn parse_statement(&mut self) -> ParseResult<WithSpan<Stmt<'a>>> {
+        self.nested(|this| match this.peek() {
+            TokenKind::Print => this.parse_print(),
+            TokenKind::If => this.parse_if(),
+            TokenKind::While => this.parse_while(),
+            TokenKind::For => this.parse_for(),
+            TokenKind::Return => this.parse_return(),
+            TokenKind::LeftBrace => {
+                let block = this.parse_block()?;
+                let line = block.get_line();
+                let range = block.get_span();
+                Ok(this.node(Stmt::Block(block._value), line, range))
+            }
+            _ => this.parse_expression_statement(),
+        })
+    }
+
+    fn parse_print(&mut self) -> ParseResult<WithSpan<Stmt<'a>>> {
+        let print = self.expect(TokenKind::Print)?;
+        let expr = self.parse_expr(Precedence::None)?;
+        let semicolon = self.expect(TokenKind::Semicolon)?;
+        Ok(self.node(
+            Stmt::Print(Box::new(expr)),
+            print.get_line(),
+            print.get_span().start..semicolon.get_span().end,
+        ))
+    }
+
+    fn parse_return(&mut self) -> ParseResult<WithSpan<Stmt<'a>>> {
+        let return_token = self.expect(TokenKind::Return)?;
+        let value = if self.check(TokenKind::Semicolon) {
+            None
+        } else {
+            Some(Box::new(self.parse_expr(Precedence::None)?))
+        };
+        let semicolon = self.expect(TokenKind::Semicolon)?;
+        Ok(self.node(
+            Stmt::Return(value),
+            return_token.get_line(),
+            return_token.get_span().start..semicolon.get_span().end,
+        ))
+    }
+
+    fn parse_if(&mut self) -> ParseResult<WithSpan<Stmt<'a>>> {
+        let if_token = self.expect(TokenKind::If)?;
+        self.expect(TokenKind::LeftParen)?;
+        let condition = self.parse_expr(Precedence::None)?;
+        self.expect(TokenKind::RightParen)?;
+        let then_branch = self.parse_statement()?;
+        let else_branch = if self.optionally(TokenKind::Else)? {
+            Some(Box::new(self.parse_statement()?))
+        } else {
+            None
+        };
+        let end = else_branch
+            .as_ref()
+            .map_or_else(|| then_branch.get_span().end, |v| v.get_span().end);
+        Ok(self.node(
+            Stmt::If(Box::new(condition), Box::new(then_branch), else_branch),
+            if_token.get_line(),
+            if_token.get_span().start..end,
+        ))
+    }
+
+    fn parse_while(&mut self) -> ParseResult<WithSpan<Stmt<'a>>> {
+        let while_token = self.expect(TokenKind::While)?;
+        self.expect(TokenKind::LeftParen)?;
+        let condition = self.parse_expr(Precedence::None)?;
+        self.expect(TokenKind::RightParen)?;
+        let body = self.parse_statement()?;
+        let range = while_token.get_span().start..body.get_span().end;
+        Ok(self.node(
+            Stmt::While(Box::new(condition), Box::new(body)),
+            while_token.get_line(),
+            range,
+        ))
+    }
+
+    /// `for` loops are desugared into a `while` loop wrapped in a block:
+    /// `{ initializer; while (condition) { body; increment; } }`
+    fn parse_for(&mut self) -> ParseResult<WithSpan<Stmt<'a>>> {
+        let for_token = self.expect(TokenKind::For)?;
+        let line = for_token.get_line();
+        self.expect(TokenKind::LeftParen)?;
+        let initializer = match self.peek() {
+            TokenKind::Semicolon => {
+                self.expect(TokenKind::Semicolon)?;
+                None
+            }
+            TokenKind::Var => Some(self.parse_var_declaration()?),
+            _ => Some(self.parse_expression_statement()?),
+        };
+        let condition = if self.check(TokenKind::Semicolon) {
+            None
+        } else {
+            Some(self.parse_expr(Precedence::None)?)
+        };
+        let semicolon = self.expect(TokenKind::Semicolon)?;
+        let increment = if self.check(TokenKind::RightParen) {
+            None
+        } else {
+            Some(self.parse_expr(Precedence::None)?)
+        };
+        self.expect(TokenKind::RightParen)?;
+        let mut body = self.parse_statement()?;
+        let range = for_token.get_span().start..body.get_span().end;
+
+        if let Some(increment) = increment {
+            let (inc_line, inc_range) = (increment.get_line(), increment.get_span());
+            let body_line = body.get_line();
+            body = self.node(
+                Stmt::Block(vec![
+                    body,
+                    self.node(Stmt::Expression(Box::new(increment)), inc_line, inc_range),
+                ]),
+                body_line,
+                range.clone(),
+            );
+        }
+        let condition =
+            condition.unwrap_or_else(|| self.node(Expr::Boolean(true), line, semicolon.get_span()));
+        let mut stmt = self.node(
+            Stmt::While(Box::new(condition), Box::new(body)),
+            line,
+            range.clone(),
+        );
+        if let Some(initializer) = initializer {
+            stmt = self.node(Stmt::Block(vec![initializer, stmt]), line, range);
+        }
+        Ok(stmt)
+    }
+
+    fn parse_block(&mut self) -> ParseResult<WithSpan<Vec<WithSpan<Stmt<'a>>>>> {
+        let left_brace = self.expect(TokenKind::LeftBrace)?;
+        let mut stmts = Vec::new();
+        while !self.check(TokenKind::RightBrace) && !self.check(TokenKind::Eof) {
+            stmts.push(self.parse_declaration()?);
+        }
+        let right_brace = self.expect(TokenKind::RightBrace)?;
+        Ok(self.node(
+            stmts,
+            left_brace.get_line(),
+            left_brace.get_span().start..right_brace.get_span().end,
+        ))
+    }
+
+    fn parse_expression_statement(&mut self) -> ParseResult<WithSpan<Stmt<'a>>> {
+        let expr = self.parse_expr(Precedence::None)?;
+        let semicolon = self.expect(TokenKind::Semicolon)?;
+        let line = expr.get_line();
+        let range = expr.get_span().start..semicolon.get_span().end;
+        Ok(self.node(Stmt::Expression(Box::new(expr)), line, range))
+    }
+
+    fn expect_identifier(&mut self) -> ParseResult<WithSpan<Cow<'a, str>>> {
+        let tc = self.expect(TokenKind::Identifier)?;
+        match tc.get_value() {
+            Token::Identifier(ref name) => {
+                Ok(self.node(Cow::Borrowed(name.as_ref()), tc.get_line(), tc.get_span()))
+            }
+            _ => Err(self.unexpected(tc, Some(TokenKind::Identifier))),
+        }
+    }
+
+    fn parse_expr(&mut self, precedence: Precedence) -> ParseResult<WithSpan<Expr<'a>>> {
+        self.nested(|this| {
+            let mut expr = this.parse_prefix()?;
+            while !this.is_eof() {
+                let next_precedence = Precedence::from(this.peek());
+                if precedence >= next_precedence {
+                    break;
+                }
+                // Each operator wraps the expression so far, so a chain
+                // such as `a.b.c` nests as deeply as parentheses do.
+                this.deepen()?;
+                expr = this.parse_infix(expr)?;
+            }
+            Ok(expr)
+        })
+    }
+
+    fn parse_prefix(&mut self) -> ParseResult<WithSpan<Expr<'a>>> {
         match self.peek() {
             TokenKind::Number
             | TokenKind::Nil
@@ -127,15 +599,16 @@ impl<'a, W: io::Write> Parser<'a, W> {
             | TokenKind::String => self.parse_primary(),
             TokenKind::Bang | TokenKind::Minus => self.parse_unary(),
             TokenKind::LeftParen => self.parse_grouping(),
-            TokenKind::LeftBracket => todo!(),
+            TokenKind::LeftBracket => self.parse_list(),
+            TokenKind::StringFragment => self.parse_interpolation(),
             _ => {
-                self.error(format!("Unexpected: TODO"))?;
-                Err(io::Error::new(ErrorKind::InvalidInput, "Invalid Input."))
+                let token = self.peek_token();
+                Err(self.unexpected(&token, None))
             }
         }
     }
 
-    fn parse_infix(&mut self, left: WithSpan<Expr<'a>>) -> io::Result<WithSpan<Expr<'a>>> {
+    fn parse_infix(&mut self, left: WithSpan<Expr<'a>>) -> ParseResult<WithSpan<Expr<'a>>> {
         match self.peek() {
             TokenKind::BangEqual
             | TokenKind::EqualEqual
@@ -147,58 +620,196 @@ impl<'a, W: io::Write> Parser<'a, W> {
             | TokenKind::Minus
             | TokenKind::Star
             | TokenKind::Slash => self.parse_binary(left),
-            TokenKind::Or | TokenKind::And => todo!(),
-            TokenKind::Equal => todo!(),
-            TokenKind::LeftParen => self.parse_grouping(),
-            TokenKind::LeftBracket => todo!(),
-            TokenKind::Dot => todo!(),
-            _ => todo!(),
+            TokenKind::Or | TokenKind::And => self.parse_logical(left),
+            TokenKind::Equal => self.parse_assign(left),
+            TokenKind::LeftParen => self.parse_call(left),
+            TokenKind::Dot => self.parse_get(left),
+            TokenKind::LeftBracket => self.parse_index(left),
+            _ => {
+                let token = self.peek_token();
+                Err(self.unexpected_token(&token))
+            }
         }
     }
 
-    fn parse_primary(&mut self) -> io::Result<WithSpan<Expr<'a>>> {
+    fn parse_primary(&mut self) -> ParseResult<WithSpan<Expr<'a>>> {
         let tc = self.advance()?;
         match tc.get_value() {
-            Token::Nil => Ok(WithSpan::new(Expr::Nil, tc.get_line(), tc.get_span())),
-            Token::This => Ok(WithSpan::new(Expr::This, tc.get_line(), tc.get_span())),
-            Token::Number(n, _) => Ok(WithSpan::new(
-                Expr::Number(*n),
+            Token::Nil => Ok(self.node(Expr::Nil, tc.get_line(), tc.get_span())),
+            Token::This => Ok(self.node(Expr::This(None), tc.get_line(), tc.get_span())),
+            Token::Number(n, _) => Ok(self.node(Expr::Number(*n), tc.get_line(), tc.get_span())),
+            Token::True => Ok(self.node(Expr::Boolean(true), tc.get_line(), tc.get_span())),
+            Token::False => Ok(self.node(Expr::Boolean(false), tc.get_line(), tc.get_span())),
+            Token::String(ref s, _) => Ok(self.node(
+                Expr::String(Cow::Borrowed(s.as_ref())),
                 tc.get_line(),
                 tc.get_span(),
             )),
-            Token::True => Ok(WithSpan::new(
-                Expr::Boolean(true),
+            Token::Identifier(ref name) => Ok(self.node(
+                Expr::Variable(Cow::Borrowed(name.as_ref()), None),
                 tc.get_line(),
                 tc.get_span(),
             )),
-            Token::False => Ok(WithSpan::new(
-                Expr::Boolean(false),
-                tc.get_line(),
-                tc.get_span(),
+            Token::Super => {
+                self.expect(TokenKind::Dot)?;
+                let method = self.expect_identifier()?;
+                let range = tc.get_span().start..method.get_span().end;
+                Ok(self.node(Expr::Super(method, None), tc.get_line(), range))
+            }
+            _ => Err(self.unexpected(tc, None)),
+        }
+    }
+
+    fn parse_assign(&mut self, left: WithSpan<Expr<'a>>) -> ParseResult<WithSpan<Expr<'a>>> {
+        let equal = self.expect(TokenKind::Equal)?;
+        let value = self.parse_expr(Precedence::None)?;
+        let line = left.get_line();
+        let range = left.get_span().start..value.get_span().end;
+        match left._value {
+            Expr::Variable(name, _) => Ok(self.node(
+                Expr::Assign(
+                    self.node(name, left._line, left._span),
+                    Box::new(value),
+                    None,
+                ),
+                line,
+                range,
             )),
-            Token::String(ref s) => Ok(WithSpan::new(
-                Expr::String(Cow::Borrowed(s.as_ref())),
-                tc.get_line(),
-                tc.get_span(),
+            Expr::Get(object, name) => {
+                Ok(self.node(Expr::Set(object, name, Box::new(value)), line, range))
+            }
+            Expr::Index(list, index) => {
+                Ok(self.node(Expr::SetIndex(list, index, Box::new(value)), line, range))
+            }
+            _ => Err(self.syntax_error(
+                SyntaxError::InvalidAssignmentTarget { target: left._span },
+                equal,
             )),
-            _ => Err(io::Error::new(ErrorKind::InvalidInput, "Unexpected token")),
         }
     }
 
-    fn parse_binary(&mut self, left: WithSpan<Expr<'a>>) -> io::Result<WithSpan<Expr<'a>>> {
+    fn parse_binary(&mut self, left: WithSpan<Expr<'a>>) -> ParseResult<WithSpan<Expr<'a>>> {
         let precedence = Precedence::from(self.peek());
         let operator = self.parse_binary_op()?;
         let right = self.parse_expr(precedence)?;
         let line_no = left.get_line();
         let range = left.get_span().start..right.get_span().end;
-        Ok(WithSpan::new(
+        Ok(self.node(
             Expr::Binary(Box::new(left), operator, Box::new(right)),
             line_no,
             range,
         ))
     }
 
-    fn parse_binary_op(&mut self) -> io::Result<WithSpan<BinaryOperator>> {
+    fn parse_call(&mut self, callee: WithSpan<Expr<'a>>) -> ParseResult<WithSpan<Expr<'a>>> {
+        self.expect(TokenKind::LeftParen)?;
+        let mut args = Vec::new();
+        if !self.check(TokenKind::RightParen) {
+            loop {
+                if args.len() >= 255 {
+                    let token = self.peek_token();
+                    let lexeme = token.get_value().lexeme().into_owned();
+                    return Err(self.syntax_error(SyntaxError::TooManyArguments { lexeme }, &token));
+                }
+                args.push(self.parse_expr(Precedence::None)?);
+                if !self.optionally(TokenKind::Comma)? {
+                    break;
+                }
+            }
+        }
+        let right_paren = self.expect(TokenKind::RightParen)?;
+        let range = callee.get_span().start..right_paren.get_span().end;
+        Ok(self.node(
+            Expr::Call(Box::new(callee), args),
+            right_paren.get_line(),
+            range,
+        ))
+    }
+
+    fn parse_get(&mut self, object: WithSpan<Expr<'a>>) -> ParseResult<WithSpan<Expr<'a>>> {
+        self.expect(TokenKind::Dot)?;
+        let name = self.expect_identifier()?;
+        let line = name.get_line();
+        let range = object.get_span().start..name.get_span().end;
+        Ok(self.node(Expr::Get(Box::new(object), name), line, range))
+    }
+
+    /// `[a, b, c]`, with an optional trailing comma.
+    fn parse_list(&mut self) -> ParseResult<WithSpan<Expr<'a>>> {
+        let left_bracket = self.expect(TokenKind::LeftBracket)?;
+        let mut items = Vec::new();
+        while !self.check(TokenKind::RightBracket) {
+            items.push(self.parse_expr(Precedence::None)?);
+            if !self.optionally(TokenKind::Comma)? {
+                break;
+            }
+        }
+        let right_bracket = self.expect(TokenKind::RightBracket)?;
+        let range = left_bracket.get_span().start..right_bracket.get_span().end;
+        Ok(self.node(Expr::List(items), left_bracket.get_line(), range))
+    }
+
+    /// `"a ${b} c"`: string fragments alternating with interpolated
+    /// expressions, starting and ending with a fragment.
+    fn parse_interpolation(&mut self) -> ParseResult<WithSpan<Expr<'a>>> {
+        let first = self.expect(TokenKind::StringFragment)?;
+        let mut parts = Vec::new();
+        self.push_fragment(&mut parts, first);
+        let mut end = first.get_span().end;
+        while self.optionally(TokenKind::InterpolationStart)? {
+            parts.push(self.parse_expr(Precedence::None)?);
+            self.expect(TokenKind::InterpolationEnd)?;
+            let fragment = self.expect(TokenKind::StringFragment)?;
+            self.push_fragment(&mut parts, fragment);
+            end = fragment.get_span().end;
+        }
+        let range = first.get_span().start..end;
+        Ok(self.node(Expr::Interpolation(parts), first.get_line(), range))
+    }
+
+    fn push_fragment(&self, parts: &mut Vec<WithSpan<Expr<'a>>>, token: &'a WithSpan<Token<'a>>) {
+        if let Token::StringFragment(s, _) = token.get_value() {
+            if !s.is_empty() {
+                parts.push(self.node(
+                    Expr::String(Cow::Borrowed(s.as_ref())),
+                    token.get_line(),
+                    token.get_span(),
+                ));
+            }
+        }
+    }
+
+    fn parse_index(&mut self, list: WithSpan<Expr<'a>>) -> ParseResult<WithSpan<Expr<'a>>> {
+        let left_bracket = self.expect(TokenKind::LeftBracket)?;
+        let index = self.parse_expr(Precedence::None)?;
+        let right_bracket = self.expect(TokenKind::RightBracket)?;
+        let range = list.get_span().start..right_bracket.get_span().end;
+        Ok(self.node(
+            Expr::Index(Box::new(list), Box::new(index)),
+            left_bracket.get_line(),
+            range,
+        ))
+    }
+
+    fn parse_logical(&mut self, left: WithSpan<Expr<'a>>) -> ParseResult<WithSpan<Expr<'a>>> {
+        let precedence = Precedence::from(self.peek());
+        let tc = self.advance()?;
+        let operator = match tc.get_value() {
+            Token::And => LogicalOperator::And,
+            _ => LogicalOperator::Or,
+        };
+        let operator = self.node(operator, tc.get_line(), tc.get_span());
+        let right = self.parse_expr(precedence)?;
+        let line_no = left.get_line();
+        let range = left.get_span().start..right.get_span().end;
+        Ok(self.node(
+            Expr::Logical(Box::new(left), operator, Box::new(right)),
+            line_no,
+            range,
+        ))
+    }
+
+    fn parse_binary_op(&mut self) -> ParseResult<WithSpan<BinaryOperator>> {
         let tc = self.advance()?;
         let operator = match tc.get_value() {
             Token::Plus => BinaryOperator::Plus,
@@ -211,67 +822,45 @@ impl<'a, W: io::Write> Parser<'a, W> {
             Token::LessEqual => BinaryOperator::LessEqual,
             Token::Greater => BinaryOperator::Greater,
             Token::GreaterEqual => BinaryOperator::GreaterEqual,
-            _ => {
-                self.error(format!(
-                    " {} | Unexpected binary operator got: {:?}",
-                    tc.get_line(),
-                    tc.get_span()
-                ))?;
-                return Err(io::Error::new(
-                    ErrorKind::InvalidInput,
-                    "Unexpected binary operator",
-                ));
-            }
+            _ => return Err(self.unexpected_token(tc)),
         };
-        Ok(WithSpan::new(operator, tc.get_line(), tc.get_span()))
+        Ok(self.node(operator, tc.get_line(), tc.get_span()))
     }
-    fn parse_grouping(&mut self) -> io::Result<WithSpan<Expr<'a>>> {
+    fn parse_grouping(&mut self) -> ParseResult<WithSpan<Expr<'a>>> {
         let left_paren = self.expect(TokenKind::LeftParen)?;
         let expr = self.parse_expr(Precedence::None)?;
         let right_paren = self.expect(TokenKind::RightParen)?;
         let range = left_paren.get_span().start..right_paren.get_span().end;
         let line = left_paren.get_line();
-        Ok(WithSpan::new(Expr::Grouping(Box::new(expr)), line, range))
+        Ok(self.node(Expr::Grouping(Box::new(expr)), line, range))
     }
-    fn parse_unary(&mut self) -> io::Result<WithSpan<Expr<'a>>> {
+    fn parse_unary(&mut self) -> ParseResult<WithSpan<Expr<'a>>> {
         let operator = self.parse_unary_op()?;
         let right = self.parse_expr(Precedence::Unary)?;
         let line = operator.get_line();
         let range = operator.get_span().start..right.get_span().end;
-        Ok(WithSpan::new(
-            Expr::Unary(operator, Box::new(right)),
-            line,
-            range,
-        ))
+        Ok(self.node(Expr::Unary(operator, Box::new(right)), line, range))
     }
-    fn parse_unary_op(&mut self) -> io::Result<WithSpan<UnaryOperator>> {
+    fn parse_unary_op(&mut self) -> ParseResult<WithSpan<UnaryOperator>> {
         let tc = self.advance()?;
         match tc.get_value() {
-            Token::Minus => Ok(WithSpan::new(
-                UnaryOperator::Minus,
-                tc.get_line(),
-                tc.get_span(),
-            )),
-            Token::Bang => Ok(WithSpan::new(
-                UnaryOperator::Bang,
-                tc.get_line(),
-                tc.get_span(),
-            )),
-            _ => {
-                self.error(format!(
-                    " {} | Unexpected unary operator got: {}",
-                    tc.get_line(),
-                    tc.get_value()
-                ))?;
-                Err(io::Error::new(
-                    ErrorKind::InvalidInput,
-                    "unexpected unary operator",
-                ))
-            }
+            Token::Minus => Ok(self.node(UnaryOperator::Minus, tc.get_line(), tc.get_span())),
+            Token::Bang => Ok(self.node(UnaryOperator::Bang, tc.get_line(), tc.get_span())),
+            _ => Err(self.unexpected_token(tc)),
         }
     }
 }
 
+/// Whether `name` would lex as a single identifier.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name.parse::<Token>().is_err()
+}
+
 #[derive(PartialEq, PartialOrd, Copy, Clone)]
 #[repr(u8)]
 enum Precedence {
@@ -286,10 +875,9 @@ enum Precedence {
     Unary,      // ! -
     Call,       // ()
     List,       // []
-    Primary,
 }
 
-impl<'a> From<TokenKind> for Precedence {
+impl From<TokenKind> for Precedence {
     fn from(token: TokenKind) -> Precedence {
         match token {
             TokenKind::Equal => Precedence::Assign,
@@ -302,7 +890,6 @@ impl<'a> From<TokenKind> for Precedence {
             | TokenKind::GreaterEqual => Precedence::Comparison,
             TokenKind::Plus | TokenKind::Minus => Precedence::Term,
             TokenKind::Star | TokenKind::Slash => Precedence::Factor,
-            TokenKind::Bang => Precedence::Unary, // Minus is already specified, but I think this is only for infix ops
             TokenKind::LeftParen => Precedence::Call,
             TokenKind::Dot => Precedence::Call,
             TokenKind::LeftBracket => Precedence::List,
@@ -315,7 +902,13 @@ impl<'a> From<TokenKind> for Precedence {
 mod tests {
     use std::io;
 
-    use crate::{Lexer, Token, WithSpan};
+    use crate::{
+        ast::{
+            visit::{self, Visitor},
+            Expr, Stmt,
+        },
+        FileId, Lexer, LoxError, SourceMap, SyntaxError, Token, TokenKind, WithSpan,
+    };
 
     use super::Parser;
 
@@ -329,4 +922,134 @@ mod tests {
             "(+ 2.0 3.7)"
         );
     }
+
+    #[test]
+    fn test_parse_interpolation() {
+        let source = r#""a ${b + 1}${"${c}"} d""#;
+        let tk = Lexer::from(source).collect::<Vec<WithSpan<Token>>>();
+        let mut parser = Parser::<io::Sink>::new(tk.as_slice(), None);
+        let expr = parser.parse().unwrap();
+        assert_eq!(
+            expr.get_value().to_string(),
+            "(interpolate a  (+ b 1.0) (interpolate c)  d)"
+        );
+        assert_eq!(expr.get_span(), 0..source.len());
+    }
+
+    #[test]
+    fn test_parse_program() {
+        let lx = Lexer::from("var a = 1; { a = a + 2; print a; }");
+        let tk = lx.collect::<Vec<WithSpan<Token>>>();
+        let mut parser = Parser::<io::Sink>::new(tk.as_slice(), None);
+        let ast = parser.parse_program().unwrap();
+        let printed = ast
+            .iter()
+            .map(|stmt| stmt.get_value().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            printed,
+            ["(var a 1.0)", "(block (= a (+ a 2.0)) (print a))"]
+        );
+
+        let tk = Lexer::from("/// The answer.\nvar a = 42; /// Trailing.")
+            .collect::<Vec<WithSpan<Token>>>();
+        let ast = Parser::<io::Sink>::new(&tk, None).parse_program().unwrap();
+        assert_eq!(ast[0].get_value().to_string(), "(var a 42.0)");
+    }
+
+    #[test]
+    fn test_parse_file_ids() {
+        struct Files(Vec<FileId>);
+
+        impl<'a> Visitor<'a> for Files {
+            fn visit_stmt(&mut self, stmt: &WithSpan<Stmt<'a>>) {
+                self.0.push(stmt.get_file());
+                visit::walk_stmt(self, stmt);
+            }
+
+            fn visit_expr(&mut self, expr: &WithSpan<Expr<'a>>) {
+                self.0.push(expr.get_file());
+                visit::walk_expr(self, expr);
+            }
+        }
+
+        let mut sources = SourceMap::new();
+        sources.add("first.lox", "");
+        let source = "for (var i = 0; i < 3; i = i + 1) print i;";
+        let file = sources.add("second.lox", source);
+        let tk = Lexer::from(source).with_file(file).collect::<Vec<_>>();
+        let ast = Parser::<io::Sink>::new(&tk, None).parse_program().unwrap();
+        let mut files = Files(Vec::new());
+        for stmt in &ast {
+            files.visit_stmt(stmt);
+        }
+        assert_eq!(files.0.len(), 15);
+        assert!(files.0.iter().all(|&id| id == file), "{:?}", files.0);
+    }
+
+    #[test]
+    fn test_parse_program_partial() {
+        let lx = Lexer::from("var a = 1;\nprint a +;\nvar = 2;\nprint a;\nprint b");
+        let tk = lx.collect::<Vec<WithSpan<Token>>>();
+        let mut parser = Parser::new(tk.as_slice(), Some(Vec::new()));
+        let ast = parser.parse_program_partial().unwrap();
+        let printed = ast
+            .iter()
+            .map(|stmt| stmt.get_value().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(printed, ["(var a 1.0)", "(print a)"]);
+        let messages = parser
+            .errors()
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                "[line 2] Error at ';': Expect expression.",
+                "[line 3] Error at '=': Expect identifier.",
+                "[line 5] Error at end: Expect ';'.",
+            ]
+        );
+        assert!(Parser::<io::Sink>::new(tk.as_slice(), None)
+            .parse_program()
+            .is_err());
+    }
+
+    fn syntax_error(source: &str) -> SyntaxError {
+        let tk = Lexer::from(source).collect::<Vec<WithSpan<Token>>>();
+        match Parser::<io::Sink>::new(tk.as_slice(), None).parse_program() {
+            Err(LoxError::Syntax(e)) => e.get_value().clone(),
+            other => panic!("expected a syntax error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert_eq!(
+            syntax_error("print (1 + 2;"),
+            SyntaxError::ExpectedToken {
+                expected: TokenKind::RightParen,
+                found: TokenKind::Semicolon,
+                lexeme: ";".to_string(),
+            }
+        );
+        assert_eq!(
+            syntax_error("print (1 + 2"),
+            SyntaxError::UnexpectedEof {
+                expected: Some(TokenKind::RightParen)
+            }
+        );
+        assert_eq!(
+            syntax_error("print 1 +"),
+            SyntaxError::UnexpectedEof { expected: None }
+        );
+        assert_eq!(
+            syntax_error("1 + 2 = 3;"),
+            SyntaxError::InvalidAssignmentTarget { target: 0..5 }
+        );
+        let error = syntax_error("print );");
+        assert_eq!(error.code(), "E0102");
+        assert_eq!(error.found(), Some(TokenKind::RightParen));
+    }
 }
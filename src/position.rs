@@ -1,10 +1,30 @@
-use std::ops::Range;
+use std::{fmt, ops::Range};
 
-#[derive(Debug, Clone, PartialEq)]
+/// Identifies a file in a [`SourceMap`](crate::SourceMap). Spans are byte
+/// ranges into the file they carry the id of; the default id is the first
+/// file added to a map.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileId(pub(crate) u32);
+
+impl FileId {
+    #[inline]
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for FileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct WithSpan<T> {
     pub(crate) _value: T,
     pub(crate) _line: usize,
     pub(crate) _span: Range<usize>,
+    pub(crate) _file: FileId,
 }
 
 impl<T> WithSpan<T> {
@@ -14,6 +34,7 @@ impl<T> WithSpan<T> {
             _value: value,
             _line: line,
             _span: span,
+            _file: FileId::default(),
         }
     }
 
@@ -46,11 +67,40 @@ impl<T> WithSpan<T> {
         }
     }
 
+    /// Moves the span into another file.
+    #[inline]
+    pub fn file(self, file: FileId) -> Self {
+        Self {
+            _file: file,
+            ..self
+        }
+    }
+
+    #[inline]
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> WithSpan<U> {
+        WithSpan {
+            _value: f(self._value),
+            _line: self._line,
+            _span: self._span,
+            _file: self._file,
+        }
+    }
+
     #[inline]
     pub fn get_value(&self) -> &T {
         &self._value
     }
 
+    #[inline]
+    pub fn get_value_mut(&mut self) -> &mut T {
+        &mut self._value
+    }
+
+    #[inline]
+    pub fn into_value(self) -> T {
+        self._value
+    }
+
     #[inline]
     pub fn get_line(&self) -> usize {
         self._line
@@ -60,6 +110,11 @@ impl<T> WithSpan<T> {
     pub fn get_span(&self) -> Range<usize> {
         self._span.clone()
     }
+
+    #[inline]
+    pub fn get_file(&self) -> FileId {
+        self._file
+    }
 }
 
 impl<T> AsRef<T> for WithSpan<T> {
@@ -76,6 +131,7 @@ impl<T> From<(T, usize, Range<usize>)> for WithSpan<T> {
             _value: value,
             _line: line,
             _span: span,
+            _file: FileId::default(),
         }
     }
 }
@@ -0,0 +1,251 @@
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    time::Instant,
+};
+
+use crate::{
+    ast::{Ast, Expr},
+    error::LexError,
+    Interpreter, Lexer, Parser, Resolver, Token, WithSpan,
+};
+
+const HELP: &str = "\
+:tokens <code>  print the tokens of <code>
+:ast <code>     print the syntax tree of <code>
+:time <code>    run <code> and print how long it took
+:load <file>    run a file in the current session
+:help           show this message
+:quit           leave the REPL";
+
+/// Interactive read-eval-print loop. Each call to [`Repl::run`] is a
+/// session over a single [`Interpreter`], so globals defined on one line
+/// remain visible on the next.
+///
+/// Input is buffered until it forms a complete program: unclosed
+/// brackets, unterminated strings and statements without a trailing `;`
+/// ask for a continuation line, and an empty continuation line submits
+/// whatever has been typed so far. Bare expressions are evaluated and
+/// their value is printed.
+pub struct Repl {
+    prompts: bool,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self { prompts: true }
+    }
+}
+
+impl Repl {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether to write `> ` and `. ` prompts before reading a line.
+    #[inline]
+    pub fn prompts(mut self, prompts: bool) -> Self {
+        self.prompts = prompts;
+        self
+    }
+
+    /// Reads lines from `input` until end of input or `:quit`. Prompts,
+    /// `print` statements, values of bare expressions and meta-command
+    /// output go to `output`; errors go to stderr.
+    pub fn run<R: BufRead, W: Write>(&self, mut input: R, output: W) -> io::Result<()> {
+        let mut session = Session {
+            interpreter: Interpreter::with_output(output),
+        };
+        let mut buffer = String::new();
+        loop {
+            if self.prompts {
+                let output = session.output();
+                write!(output, "{}", if buffer.is_empty() { "> " } else { ". " })?;
+                output.flush()?;
+            }
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                if self.prompts {
+                    writeln!(session.output())?;
+                }
+                if !buffer.trim().is_empty() {
+                    session.eval_input(&buffer)?;
+                }
+                return Ok(());
+            }
+            let blank = line.trim().is_empty();
+            if buffer.is_empty() && blank {
+                continue;
+            }
+            if !blank {
+                buffer.push_str(&line);
+                if needs_more(&buffer) {
+                    continue;
+                }
+            }
+            let source = std::mem::take(&mut buffer);
+            if !session.eval_input(&source)? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// The interpreter of one [`Repl::run`], whose output is the session's.
+struct Session<W: Write> {
+    interpreter: Interpreter<W>,
+}
+
+impl<W: Write> Session<W> {
+    #[inline]
+    fn output(&mut self) -> &mut W {
+        self.interpreter.output_mut()
+    }
+
+    /// Handles one complete submission. Returns `false` when the session
+    /// should end.
+    fn eval_input(&mut self, source: &str) -> io::Result<bool> {
+        let trimmed = source.trim();
+        let Some(meta) = trimmed.strip_prefix(':') else {
+            self.eval(source)?;
+            return Ok(true);
+        };
+        let output = self.output();
+        let (command, arg) = meta
+            .split_once(char::is_whitespace)
+            .map_or((meta, ""), |(command, arg)| (command, arg.trim()));
+        match command {
+            "tokens" => {
+                for token in Lexer::from(arg) {
+                    writeln!(output, "{}", token.get_value())?;
+                }
+            }
+            "ast" => {
+                let tokens = tokenize(arg);
+                if let Some(expr) = parse_expression(&tokens) {
+                    writeln!(output, "{}", expr.get_value())?;
+                } else if let Ok(ast) = Parser::new(&tokens, Some(io::stderr())).parse_program() {
+                    for stmt in &ast {
+                        writeln!(output, "{}", stmt.get_value())?;
+                    }
+                }
+            }
+            "time" => {
+                let start = Instant::now();
+                self.eval(arg)?;
+                writeln!(self.output(), "[{:.3?}]", start.elapsed())?;
+            }
+            "load" => match fs::read_to_string(arg) {
+                Ok(contents) => self.eval(&contents)?,
+                Err(_) => eprintln!("Failed to read file {}", arg),
+            },
+            "help" => writeln!(output, "{HELP}")?,
+            "quit" | "exit" => return Ok(false),
+            _ => eprintln!("Unknown command: :{}", command),
+        }
+        Ok(true)
+    }
+
+    /// Runs `source` in the session, printing the value of a bare
+    /// expression.
+    fn eval(&mut self, source: &str) -> io::Result<()> {
+        let tokens = tokenize(source);
+        if let Some(expr) = parse_expression(&tokens) {
+            match self.interpreter.evaluate(&expr) {
+                Ok(value) => writeln!(self.output(), "{value}")?,
+                Err(e) => eprintln!("{e}"),
+            }
+            return Ok(());
+        }
+        let Some(ast) = parse_program(&tokens) else {
+            return Ok(());
+        };
+        if let Err(e) = self.interpreter.interpret(&ast) {
+            eprintln!("{e}");
+        }
+        self.output().flush()
+    }
+}
+
+fn tokenize(source: &str) -> Vec<WithSpan<Token<'_>>> {
+    Lexer::from(source).collect()
+}
+
+/// Parses the whole input as a single expression without a trailing `;`.
+fn parse_expression<'a>(tokens: &'a [WithSpan<Token<'a>>]) -> Option<WithSpan<Expr<'a>>> {
+    let mut parser = Parser::<io::Sink>::new(tokens, None);
+    parser.parse().ok().filter(|_| parser.is_eof())
+}
+
+/// Parses and resolves a program, reporting errors on stderr.
+fn parse_program<'a>(tokens: &'a [WithSpan<Token<'a>>]) -> Option<Ast<'a>> {
+    let mut ast = Parser::new(tokens, Some(io::stderr()))
+        .parse_program()
+        .ok()?;
+    Resolver::new(Some(io::stderr())).resolve(&mut ast).ok()?;
+    Some(ast)
+}
+
+/// Whether `buffer` is the start of a submission that cannot be complete
+/// yet.
+fn needs_more(buffer: &str) -> bool {
+    let source = buffer.trim_start();
+    match source.strip_prefix(':') {
+        Some(meta) => match meta.split_once(char::is_whitespace) {
+            Some(("ast" | "time", arg)) => is_incomplete(arg),
+            _ => false,
+        },
+        None => is_incomplete(source),
+    }
+}
+
+fn is_incomplete(source: &str) -> bool {
+    let tokens = tokenize(source);
+    let mut depth = 0i32;
+    for token in &tokens {
+        match token.get_value() {
+            Token::LeftParen | Token::LeftBrace | Token::LeftBracket => depth += 1,
+            Token::RightParen | Token::RightBrace | Token::RightBracket => depth -= 1,
+            Token::Error(LexError::UnterminatedStr(_) | LexError::UnterminatedComment(_)) => {
+                return true
+            }
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return true;
+    }
+    if parse_expression(&tokens).is_some()
+        || Parser::<io::Sink>::new(&tokens, None)
+            .parse_program()
+            .is_ok()
+    {
+        return false;
+    }
+    let last = tokens
+        .iter()
+        .rev()
+        .map(|t| t.get_value())
+        .find(|t| !t.is_eof() && !t.is_skipped());
+    !matches!(last, None | Some(Token::Semicolon | Token::RightBrace))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::needs_more;
+
+    #[test]
+    fn test_needs_more() {
+        assert!(!needs_more("1 + 2"));
+        assert!(!needs_more("print 1;"));
+        assert!(!needs_more("fun f() { return 1; }"));
+        assert!(!needs_more(":tokens {"));
+        assert!(!needs_more("print 1 +;"));
+        assert!(needs_more("var a = 1"));
+        assert!(needs_more("fun f() {\n"));
+        assert!(needs_more("print (1 +\n"));
+        assert!(needs_more("print \"abc\n"));
+        assert!(needs_more(":time for (var i = 0; i < 3; i = i + 1) {"));
+    }
+}
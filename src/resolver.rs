@@ -0,0 +1,346 @@
+use std::{io, ops::Range, rc::Rc};
+
+use crate::{
+    ast::{Ast, Expr, FunctionDecl, Slot, Stmt},
+    error::{LoxError, ResolveError},
+    limits::{grow_stack, MAX_NESTING},
+    FileId, WithSpan,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
+/// A variable declared in a local scope and whether its initializer has
+/// finished running.
+struct Local {
+    name: String,
+    defined: bool,
+}
+
+/// Static pass that binds every local variable use to the [`Slot`] it
+/// will occupy at runtime and reports scope errors before execution.
+pub struct Resolver<W: io::Write> {
+    scopes: Vec<Vec<Local>>,
+    function: FunctionKind,
+    class: ClassKind,
+    /// How many statements and expressions enclose the one being resolved.
+    depth: usize,
+    diagnostics: Option<W>,
+    errors: Vec<LoxError>,
+    /// File of the statement being resolved, for error locations.
+    file: FileId,
+}
+
+impl<W: io::Write> Resolver<W> {
+    pub fn new(diagnostics: Option<W>) -> Self {
+        Self {
+            scopes: Vec::new(),
+            function: FunctionKind::None,
+            class: ClassKind::None,
+            depth: 0,
+            diagnostics,
+            errors: Vec::new(),
+            file: FileId::default(),
+        }
+    }
+
+    /// Resolves the whole program in place, reporting every error found.
+    /// Fails with the first one.
+    pub fn resolve(&mut self, ast: &mut Ast) -> Result<(), LoxError> {
+        for stmt in ast.iter_mut() {
+            self.file = stmt.get_file();
+            self.resolve_stmt(stmt);
+        }
+        match self.errors.first() {
+            Some(error) => Err(error.clone()),
+            None => Ok(()),
+        }
+    }
+
+    /// Every error reported so far, in source order.
+    #[inline]
+    pub fn errors(&self) -> &[LoxError] {
+        &self.errors
+    }
+
+    fn error(&mut self, error: ResolveError, line: usize, span: Range<usize>) {
+        let error = LoxError::from(WithSpan::new(error, line, span).file(self.file));
+        if let Some(f) = self.diagnostics.as_mut() {
+            // Diagnostics are best effort, as in the parser.
+            let _ = writeln!(f, "{}", error);
+        }
+        self.errors.push(error);
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &str, line: usize, span: Range<usize>) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        if scope.iter().any(|local| local.name == name) {
+            let name = name.to_string();
+            return self.error(ResolveError::AlreadyDeclared { name }, line, span);
+        }
+        scope.push(Local {
+            name: name.to_string(),
+            defined: false,
+        });
+    }
+
+    fn define(&mut self, name: &str) {
+        if let Some(local) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.iter_mut().rev().find(|local| local.name == name))
+        {
+            local.defined = true;
+        }
+    }
+
+    fn resolve_local(&self, name: &str) -> Option<Slot> {
+        self.scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, scope)| {
+                scope
+                    .iter()
+                    .position(|local| local.name == name)
+                    .map(|index| Slot::new(depth, index))
+            })
+    }
+
+    fn resolve_block(&mut self, stmts: &mut [WithSpan<Stmt>]) {
+        for stmt in stmts {
+            self.resolve_stmt(stmt);
+        }
+    }
+
+    /// Runs `resolve` on a node one level deeper, reporting
+    /// [`ResolveError::TooMuchNesting`] instead past [`MAX_NESTING`].
+    fn nested<T>(
+        &mut self,
+        node: &mut WithSpan<T>,
+        resolve: impl FnOnce(&mut Self, &mut WithSpan<T>),
+    ) {
+        if self.depth >= MAX_NESTING {
+            return self.error(
+                ResolveError::TooMuchNesting,
+                node.get_line(),
+                node.get_span(),
+            );
+        }
+        self.depth += 1;
+        grow_stack(|| resolve(self, node));
+        self.depth -= 1;
+    }
+
+    fn resolve_stmt(&mut self, stmt: &mut WithSpan<Stmt>) {
+        self.nested(stmt, Self::resolve_stmt_inner);
+    }
+
+    fn resolve_stmt_inner(&mut self, stmt: &mut WithSpan<Stmt>) {
+        let line = stmt.get_line();
+        let span = stmt.get_span();
+        match &mut stmt._value {
+            Stmt::Expression(expr) | Stmt::Print(expr) => self.resolve_expr(expr),
+            Stmt::Import(_, name) => {
+                self.declare(name.get_value(), name.get_line(), name.get_span());
+                self.define(name.get_value());
+            }
+            Stmt::Var(name, initializer) => {
+                self.declare(name.get_value(), name.get_line(), name.get_span());
+                if let Some(initializer) = initializer {
+                    self.resolve_expr(initializer);
+                }
+                self.define(name.get_value());
+            }
+            Stmt::Block(stmts) => {
+                self.begin_scope();
+                self.resolve_block(stmts);
+                self.end_scope();
+            }
+            Stmt::If(condition, then_branch, else_branch) => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_stmt(else_branch);
+                }
+            }
+            Stmt::While(condition, body) => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(body);
+            }
+            Stmt::Function(decl) => {
+                let decl = Rc::make_mut(decl);
+                self.declare(
+                    decl.name.get_value(),
+                    decl.name.get_line(),
+                    decl.name.get_span(),
+                );
+                self.define(decl.name.get_value());
+                self.resolve_function(decl, FunctionKind::Function);
+            }
+            Stmt::Return(value) => {
+                if self.function == FunctionKind::None {
+                    self.error(ResolveError::ReturnFromTopLevel, line, span.clone());
+                }
+                if let Some(value) = value {
+                    if self.function == FunctionKind::Initializer {
+                        self.error(ResolveError::ReturnValueFromInitializer, line, span);
+                    }
+                    self.resolve_expr(value);
+                }
+            }
+            Stmt::Class(decl) => {
+                let enclosing = self.class;
+                self.class = ClassKind::Class;
+                self.declare(
+                    decl.name.get_value(),
+                    decl.name.get_line(),
+                    decl.name.get_span(),
+                );
+                self.define(decl.name.get_value());
+
+                if let Some(superclass) = decl.superclass.as_mut() {
+                    if let Expr::Variable(name, _) = superclass.get_value() {
+                        if name == decl.name.get_value() {
+                            let error = ResolveError::InheritFromSelf {
+                                name: name.to_string(),
+                            };
+                            self.error(error, superclass.get_line(), superclass.get_span());
+                        }
+                    }
+                    self.class = ClassKind::Subclass;
+                    self.resolve_expr(superclass);
+                    self.begin_scope();
+                    self.declare("super", line, span.clone());
+                    self.define("super");
+                }
+
+                self.begin_scope();
+                self.declare("this", line, span);
+                self.define("this");
+                for method in decl.methods.iter_mut() {
+                    let method = Rc::make_mut(method);
+                    let kind = if method.name.get_value() == "init" {
+                        FunctionKind::Initializer
+                    } else {
+                        FunctionKind::Method
+                    };
+                    self.resolve_function(method, kind);
+                }
+                self.end_scope();
+
+                if decl.superclass.is_some() {
+                    self.end_scope();
+                }
+                self.class = enclosing;
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, decl: &mut FunctionDecl, kind: FunctionKind) {
+        let enclosing = self.function;
+        self.function = kind;
+        self.begin_scope();
+        for param in &decl.params {
+            self.declare(param.get_value(), param.get_line(), param.get_span());
+            self.define(param.get_value());
+        }
+        self.resolve_block(&mut decl.body);
+        self.end_scope();
+        self.function = enclosing;
+    }
+
+    fn resolve_expr(&mut self, expr: &mut WithSpan<Expr>) {
+        self.nested(expr, Self::resolve_expr_inner);
+    }
+
+    fn resolve_expr_inner(&mut self, expr: &mut WithSpan<Expr>) {
+        let line = expr.get_line();
+        let span = expr.get_span();
+        match &mut expr._value {
+            Expr::Number(_) | Expr::Boolean(_) | Expr::Nil | Expr::String(_) => {}
+            Expr::Binary(left, _, right) | Expr::Logical(left, _, right) => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            Expr::Grouping(inner) | Expr::Unary(_, inner) => self.resolve_expr(inner),
+            Expr::Variable(name, slot) => {
+                let in_initializer = self.scopes.last().is_some_and(|scope| {
+                    scope
+                        .iter()
+                        .any(|local| local.name == name.as_ref() && !local.defined)
+                });
+                if in_initializer {
+                    let error = ResolveError::ReadInOwnInitializer {
+                        name: name.to_string(),
+                    };
+                    self.error(error, line, span);
+                }
+                *slot = self.resolve_local(name);
+            }
+            Expr::Assign(name, value, slot) => {
+                self.resolve_expr(value);
+                *slot = self.resolve_local(name.get_value());
+            }
+            Expr::Call(callee, args) => {
+                self.resolve_expr(callee);
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+            }
+            Expr::Get(object, _) => self.resolve_expr(object),
+            Expr::List(items) | Expr::Interpolation(items) => {
+                for item in items {
+                    self.resolve_expr(item);
+                }
+            }
+            Expr::Index(list, index) => {
+                self.resolve_expr(list);
+                self.resolve_expr(index);
+            }
+            Expr::SetIndex(list, index, value) => {
+                self.resolve_expr(list);
+                self.resolve_expr(index);
+                self.resolve_expr(value);
+            }
+            Expr::Set(object, _, value) => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            }
+            Expr::This(slot) => {
+                if self.class == ClassKind::None {
+                    self.error(ResolveError::ThisOutsideClass, line, span);
+                } else {
+                    *slot = self.resolve_local("this");
+                }
+            }
+            Expr::Super(_, slot) => match self.class {
+                ClassKind::None => self.error(ResolveError::SuperOutsideClass, line, span),
+                ClassKind::Class => self.error(ResolveError::SuperWithoutSuperclass, line, span),
+                ClassKind::Subclass => *slot = self.resolve_local("super"),
+            },
+        }
+    }
+}
@@ -0,0 +1,264 @@
+//! Line and column positions for byte offsets into source text.
+
+use std::{fmt, ops::Range};
+
+use crate::{FileId, WithSpan};
+
+/// The start offset of every line in a text, so the line holding any byte
+/// offset can be found by binary search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            starts,
+            len: text.len(),
+        }
+    }
+
+    #[inline]
+    pub fn line_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// One-based line containing `offset`. A newline belongs to the line
+    /// it ends, and offsets past the end are on the last line.
+    #[inline]
+    pub fn line(&self, offset: usize) -> usize {
+        self.starts.partition_point(|&start| start <= offset)
+    }
+
+    /// Byte range of one-based `line`, excluding its line break.
+    pub fn line_span(&self, line: usize) -> Option<Range<usize>> {
+        let start = *self.starts.get(line.checked_sub(1)?)?;
+        let end = self.starts.get(line).map_or(self.len, |next| next - 1);
+        Some(start..end)
+    }
+}
+
+/// A line and column, all one-based. `column` counts UTF-8 bytes and
+/// `utf16_column` UTF-16 code units, as editors and LSP clients expect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+    pub utf16_column: usize,
+}
+
+/// A named source text with its [`LineIndex`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    name: String,
+    text: String,
+    lines: LineIndex,
+}
+
+impl SourceFile {
+    pub fn new<N: Into<String>, T: Into<String>>(name: N, text: T) -> Self {
+        let text = text.into();
+        Self {
+            name: name.into(),
+            lines: LineIndex::new(&text),
+            text,
+        }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    #[inline]
+    pub fn lines(&self) -> &LineIndex {
+        &self.lines
+    }
+
+    /// Position of the byte at `offset`. Offsets inside a multi-byte
+    /// character map to that character, and offsets past the end to the
+    /// end of the text.
+    pub fn position(&self, offset: usize) -> Position {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.lines.line(offset);
+        let start = self.lines.starts[line - 1];
+        let prefix = &self.text[start..offset];
+        Position {
+            line,
+            column: prefix.len() + 1,
+            utf16_column: prefix.encode_utf16().count() + 1,
+        }
+    }
+
+    /// Text of one-based `line`, without its line break.
+    pub fn line_text(&self, line: usize) -> Option<&str> {
+        let span = self.lines.line_span(line)?;
+        Some(self.text[span].trim_end_matches('\r'))
+    }
+}
+
+/// Owns the text of every file loaded into a program and hands out the
+/// [`FileId`]s that spans refer to them by.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file and returns its id. Ids are assigned in order, so the
+    /// first file gets [`FileId::default`].
+    pub fn add<N: Into<String>, T: Into<String>>(&mut self, name: N, text: T) -> FileId {
+        let id = FileId(self.files.len() as u32);
+        self.files.push(SourceFile::new(name, text));
+        id
+    }
+
+    #[inline]
+    pub fn get(&self, file: FileId) -> Option<&SourceFile> {
+        self.files.get(file.index())
+    }
+
+    /// Every file with its id, in the order they were added.
+    pub fn files(&self) -> impl Iterator<Item = (FileId, &SourceFile)> {
+        self.files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| (FileId(i as u32), file))
+    }
+
+    /// Where `offset` falls in `file`, if the map has that file.
+    pub fn location(&self, file: FileId, offset: usize) -> Option<Location<'_>> {
+        let source = self.get(file)?;
+        Some(Location {
+            name: source.name(),
+            position: source.position(offset),
+        })
+    }
+
+    /// Where `span` starts.
+    #[inline]
+    pub fn locate<T>(&self, span: &WithSpan<T>) -> Option<Location<'_>> {
+        self.location(span.get_file(), span.get_span().start)
+    }
+}
+
+impl<'a> From<&'a SourceMap> for std::borrow::Cow<'a, SourceMap> {
+    #[inline]
+    fn from(sources: &'a SourceMap) -> Self {
+        Self::Borrowed(sources)
+    }
+}
+
+/// A [`Position`] in a named file, displayed as `name:line:column`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location<'a> {
+    pub name: &'a str,
+    pub position: Position,
+}
+
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.name, self.position.line, self.position.column
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LineIndex, Position, SourceFile, SourceMap};
+    use crate::{Lexer, Token, WithSpan};
+
+    #[test]
+    fn test_positions() {
+        let file = SourceFile::new("test.lox", "a\né😀x\r\n\nz");
+        let at = |offset| {
+            let p = file.position(offset);
+            (p.line, p.column, p.utf16_column)
+        };
+        assert_eq!(at(0), (1, 1, 1));
+        assert_eq!(at(1), (1, 2, 2));
+        assert_eq!(at(2), (2, 1, 1));
+        // `é` is two bytes but one UTF-16 unit, `😀` four bytes and two.
+        assert_eq!(at(4), (2, 3, 2));
+        assert_eq!(at(5), (2, 3, 2));
+        assert_eq!(at(8), (2, 7, 4));
+        assert_eq!(at(11), (3, 1, 1));
+        assert_eq!(at(12), (4, 1, 1));
+        assert_eq!(at(13), (4, 2, 2));
+        assert_eq!(at(100), (4, 2, 2));
+        assert_eq!(
+            file.position(8),
+            Position {
+                line: 2,
+                column: 7,
+                utf16_column: 4
+            }
+        );
+
+        assert_eq!(file.line_text(2), Some("é😀x"));
+        assert_eq!(file.line_text(3), Some(""));
+        assert_eq!(file.line_text(4), Some("z"));
+        assert_eq!(file.line_text(5), None);
+        assert_eq!(file.line_text(0), None);
+        assert_eq!(file.lines().line_count(), 4);
+    }
+
+    #[test]
+    fn test_source_map() {
+        let mut sources = SourceMap::new();
+        let main = sources.add("main.lox", "print 1;\n");
+        let lib = sources.add("lib.lox", "\n  var x;");
+        assert_eq!(main, Default::default());
+        assert_ne!(main, lib);
+        assert_eq!(sources.get(lib).unwrap().name(), "lib.lox");
+
+        let text = sources.get(lib).unwrap().text();
+        let tokens = Lexer::from(text).with_file(lib).collect::<Vec<_>>();
+        assert!(matches!(tokens[1].get_value(), Token::Identifier(_)));
+        assert_eq!(tokens[1].get_file(), lib);
+        let location = sources.locate(&tokens[1]).unwrap();
+        assert_eq!(location.to_string(), "lib.lox:2:7");
+        assert_eq!(
+            sources
+                .locate(&WithSpan::new((), 1, 6..7))
+                .unwrap()
+                .to_string(),
+            "main.lox:1:7"
+        );
+        assert_eq!(sources.files().count(), 2);
+    }
+
+    #[test]
+    fn test_line_index() {
+        let index = LineIndex::new("ab\n\ncd\n");
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.line(2), 1);
+        assert_eq!(index.line(3), 2);
+        assert_eq!(index.line(4), 3);
+        assert_eq!(index.line(7), 4);
+        assert_eq!(index.line_span(1), Some(0..2));
+        assert_eq!(index.line_span(2), Some(3..3));
+        assert_eq!(index.line_span(4), Some(7..7));
+    }
+}
@@ -1,6 +1,6 @@
 use std::{borrow::Cow, fmt, str::FromStr};
 
-use crate::LoxError;
+use crate::{number::format_literal, LexError};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token<'a> {
@@ -50,10 +50,26 @@ pub enum Token<'a> {
 
     // Literals.
     Identifier(Cow<'a, str>),
-    String(Cow<'a, str>),
+    /// The decoded value and the literal as written, with its quotes.
+    String(Cow<'a, str>, Cow<'a, str>),
     Number(f64, Cow<'a, str>),
 
-    Error(LoxError),
+    // String interpolation: `"a ${b} c"` is a fragment, `${`, the tokens of
+    // `b`, `}` and another fragment. Like a string, a fragment holds its
+    // decoded value and its source text, which includes the quote at
+    // either end of the literal.
+    StringFragment(Cow<'a, str>, Cow<'a, str>),
+    InterpolationStart,
+    InterpolationEnd,
+
+    // Trivia, only emitted by `Lexer::with_trivia`, and doc comments.
+    Whitespace(Cow<'a, str>),
+    Newline,
+    Comment(Cow<'a, str>),
+    /// A `///` comment, holding the text after the slashes.
+    DocComment(Cow<'a, str>),
+
+    Error(LexError),
 
     Eof,
 }
@@ -82,14 +98,15 @@ impl fmt::Display for Token<'_> {
             Self::BangEqual => f.write_str("BANG_EQUAL != null"),
             Self::LessEqual => f.write_str("LESS_EQUAL <= null"),
             Self::GreaterEqual => f.write_str("GREATER_EQUAL >= null"),
-            Self::String(s) => write!(f, "STRING \"{s}\" {s}"),
-            Self::Number(g, v) => {
-                if g.fract() == 0.0 {
-                    write!(f, "NUMBER {} {}.0", v, *g as i64)
-                } else {
-                    write!(f, "NUMBER {v} {g}")
-                }
-            }
+            Self::String(s, lexeme) => write!(f, "STRING {lexeme} {s}"),
+            Self::StringFragment(s, lexeme) => write!(f, "STRING_FRAGMENT {lexeme} {s}"),
+            Self::InterpolationStart => f.write_str("INTERPOLATION_START ${ null"),
+            Self::InterpolationEnd => f.write_str("INTERPOLATION_END } null"),
+            Self::Number(g, v) => write!(f, "NUMBER {v} {}", format_literal(*g)),
+            Self::Whitespace(s) => write!(f, "WHITESPACE \"{}\" null", s.escape_debug()),
+            Self::Newline => f.write_str("NEWLINE \"\\n\" null"),
+            Self::Comment(s) => write!(f, "COMMENT {s} null"),
+            Self::DocComment(s) => write!(f, "DOC_COMMENT {} {s}", self.lexeme()),
             Self::Error(e) => write!(f, "{e}"),
             Self::And => f.write_str("AND and null"),
             Self::Class => f.write_str("CLASS class null"),
@@ -115,6 +132,63 @@ impl fmt::Display for Token<'_> {
 }
 
 impl Token<'_> {
+    /// The source text the token was scanned from. Empty for `Eof` and
+    /// lexer errors.
+    pub fn lexeme(&self) -> Cow<'_, str> {
+        let s = match self {
+            Self::LeftParen => "(",
+            Self::RightParen => ")",
+            Self::LeftBrace => "{",
+            Self::RightBrace => "}",
+            Self::LeftBracket => "[",
+            Self::RightBracket => "]",
+            Self::Star => "*",
+            Self::Dot => ".",
+            Self::Comma => ",",
+            Self::Plus => "+",
+            Self::Minus => "-",
+            Self::Semicolon => ";",
+            Self::Equal => "=",
+            Self::Bang => "!",
+            Self::Slash => "/",
+            Self::Less => "<",
+            Self::Greater => ">",
+            Self::EqualEqual => "==",
+            Self::BangEqual => "!=",
+            Self::LessEqual => "<=",
+            Self::GreaterEqual => ">=",
+            Self::And => "and",
+            Self::Class => "class",
+            Self::Else => "else",
+            Self::False => "false",
+            Self::For => "for",
+            Self::Fun => "fun",
+            Self::If => "if",
+            Self::Nil => "nil",
+            Self::Or => "or",
+            Self::Print => "print",
+            Self::Return => "return",
+            Self::Super => "super",
+            Self::This => "this",
+            Self::True => "true",
+            Self::Var => "var",
+            Self::While => "while",
+            Self::Import => "import",
+            Self::InterpolationStart => "${",
+            Self::InterpolationEnd => "}",
+            Self::DocComment(s) => return Cow::Owned(format!("///{s}")),
+            Self::Newline => "\n",
+            Self::Number(_, v)
+            | Self::String(_, v)
+            | Self::StringFragment(_, v)
+            | Self::Identifier(v)
+            | Self::Whitespace(v)
+            | Self::Comment(v) => v.as_ref(),
+            Self::Error(_) | Self::Eof => "",
+        };
+        Cow::Borrowed(s)
+    }
+
     #[inline]
     pub fn is_err(&self) -> bool {
         matches!(self, Self::Error(_))
@@ -125,6 +199,23 @@ impl Token<'_> {
         !matches!(self, Self::Error(_))
     }
 
+    /// Whether the token is whitespace or a plain comment, which only
+    /// [`Lexer::with_trivia`](crate::Lexer::with_trivia) emits. Doc
+    /// comments are not trivia: they are always emitted, though `tokenize`
+    /// only shows them with `--trivia`.
+    #[inline]
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, Self::Whitespace(_) | Self::Newline | Self::Comment(_))
+    }
+
+    /// Whether the parser passes over the token. Besides trivia, that is
+    /// doc comments, which have no place in the grammar, and lexer errors,
+    /// which are reported separately.
+    #[inline]
+    pub fn is_skipped(&self) -> bool {
+        self.is_trivia() || matches!(self, Self::DocComment(_) | Self::Error(_))
+    }
+
     #[inline]
     pub fn is_eof(&self) -> bool {
         *self == Self::Eof
@@ -157,7 +248,7 @@ impl FromStr for Token<'_> {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum TokenKind {
     // Single-character tokens.
     LeftParen,
@@ -188,6 +279,9 @@ pub enum TokenKind {
     Identifier,
     String,
     Number,
+    StringFragment,
+    InterpolationStart,
+    InterpolationEnd,
 
     // Keywords.
     And,
@@ -208,6 +302,12 @@ pub enum TokenKind {
     While,
     Import,
 
+    // Trivia.
+    Whitespace,
+    Newline,
+    Comment,
+    DocComment,
+
     // Other.
     Error,
     Eof,
@@ -238,8 +338,11 @@ impl From<&Token<'_>> for TokenKind {
             Token::Less => TokenKind::Less,
             Token::LessEqual => TokenKind::LessEqual,
             Token::Identifier(_) => TokenKind::Identifier,
-            Token::String(_) => TokenKind::String,
+            Token::String(..) => TokenKind::String,
             Token::Number(_, _) => TokenKind::Number,
+            Token::StringFragment(..) => TokenKind::StringFragment,
+            Token::InterpolationStart => TokenKind::InterpolationStart,
+            Token::InterpolationEnd => TokenKind::InterpolationEnd,
             Token::And => TokenKind::And,
             Token::Class => TokenKind::Class,
             Token::Else => TokenKind::Else,
@@ -257,6 +360,10 @@ impl From<&Token<'_>> for TokenKind {
             Token::Var => TokenKind::Var,
             Token::While => TokenKind::While,
             Token::Import => TokenKind::Import,
+            Token::Whitespace(_) => TokenKind::Whitespace,
+            Token::Newline => TokenKind::Newline,
+            Token::Comment(_) => TokenKind::Comment,
+            Token::DocComment(_) => TokenKind::DocComment,
             Token::Error(_) => TokenKind::Error,
             Token::Eof => TokenKind::Eof,
         }
@@ -268,7 +375,7 @@ impl fmt::Display for TokenKind {
             f,
             "{}",
             match self {
-                TokenKind::LeftParen => "')'",
+                TokenKind::LeftParen => "'('",
                 TokenKind::RightParen => "')'",
                 TokenKind::LeftBrace => "'{'",
                 TokenKind::RightBrace => "'}'",
@@ -292,6 +399,9 @@ impl fmt::Display for TokenKind {
                 TokenKind::Identifier => "identifier",
                 TokenKind::String => "string",
                 TokenKind::Number => "number",
+                TokenKind::StringFragment => "string",
+                TokenKind::InterpolationStart => "'${'",
+                TokenKind::InterpolationEnd => "'}'",
                 TokenKind::And => "'and'",
                 TokenKind::Class => "'class'",
                 TokenKind::Else => "'else'",
@@ -309,6 +419,10 @@ impl fmt::Display for TokenKind {
                 TokenKind::Var => "'var'",
                 TokenKind::While => "'while'",
                 TokenKind::Import => "'import'",
+                TokenKind::Whitespace => "whitespace",
+                TokenKind::Newline => "newline",
+                TokenKind::Comment => "comment",
+                TokenKind::DocComment => "doc comment",
                 TokenKind::Eof => "<EOF>",
                 TokenKind::Error => "<Unknown>",
             }
@@ -1,7 +1,7 @@
 use std::str::FromStr;
-use std::{borrow::Cow, ops::Range};
+use std::{borrow::Cow, mem};
 
-use crate::{LoxError, Token, WithSpan};
+use crate::{FileId, LexError, Token, WithSpan};
 
 #[derive(Debug)]
 struct Scanner<'a> {
@@ -40,6 +40,17 @@ impl<'a> Scanner<'a> {
         }
         false
     }
+    /// The character starting at the current position, if the bytes
+    /// there are valid UTF-8.
+    fn peek_char(&self) -> Option<char> {
+        let end = (self.pos + 4).min(self.val.len());
+        let bytes = self.val.get(self.pos..end)?;
+        let valid = match std::str::from_utf8(bytes) {
+            Ok(s) => s,
+            Err(e) => std::str::from_utf8(&bytes[..e.valid_up_to()]).ok()?,
+        };
+        valid.chars().next()
+    }
     fn consume_while<F: Fn(u8) -> bool>(&mut self, f: F) -> &'a [u8] {
         let start = self.pos;
         while let Some(u) = self.peek() {
@@ -73,6 +84,18 @@ impl<'a> From<&'a [u8]> for Scanner<'a> {
 pub struct Lexer<'a> {
     sc: Scanner<'a>,
     line: usize,
+    file: FileId,
+    /// An error found inside the token just scanned, emitted after it.
+    queued: Option<WithSpan<Token<'a>>>,
+    /// Whether whitespace and comments are emitted as tokens.
+    trivia: bool,
+    /// Brace depth inside each `${...}` being scanned, innermost last.
+    interpolations: Vec<usize>,
+    /// Set after a string fragment that stops at `${`.
+    open_interpolation: bool,
+    /// Set after the `}` that closes an interpolation, to scan the rest of
+    /// its string.
+    resume_string: bool,
     end: bool,
 }
 
@@ -80,7 +103,13 @@ impl<'a> From<&'a [u8]> for Lexer<'a> {
     fn from(value: &'a [u8]) -> Self {
         Self {
             sc: Scanner::from(value),
-            line: 0,
+            line: 1,
+            file: FileId::default(),
+            queued: None,
+            trivia: false,
+            interpolations: Vec::new(),
+            open_interpolation: false,
+            resume_string: false,
             end: false,
         }
     }
@@ -89,66 +118,91 @@ impl<'a> From<&'a [u8]> for Lexer<'a> {
 impl<'a> From<&'a str> for Lexer<'a> {
     fn from(value: &'a str) -> Self {
         Self {
-            sc: Scanner::from(value.as_bytes()),
+            sc: Scanner::new(value),
             line: 1,
+            file: FileId::default(),
+            queued: None,
+            trivia: false,
+            interpolations: Vec::new(),
+            open_interpolation: false,
+            resume_string: false,
             end: false,
         }
     }
 }
 
 impl<'a> Lexer<'a> {
+    /// Marks the tokens as coming from `file` of a
+    /// [`SourceMap`](crate::SourceMap).
+    pub fn with_file(mut self, file: FileId) -> Self {
+        self.file = file;
+        self
+    }
+
+    /// Also emits [`Token::Whitespace`], [`Token::Newline`] and
+    /// [`Token::Comment`] tokens, so that the spans of the tokens cover the
+    /// whole source.
+    pub fn with_trivia(mut self) -> Self {
+        self.trivia = true;
+        self
+    }
+
     #[inline(always)]
     pub fn get_line(&self) -> usize {
         self.line
     }
     fn match_token(&mut self, v: u8) -> Option<Token<'a>> {
         match v {
-            s if (s as char).is_ascii_whitespace() => None,
+            b'\n' => self.trivia.then_some(Token::Newline),
+            s if (s as char).is_ascii_whitespace() => {
+                let start = self.sc.pos - 1;
+                self.sc
+                    .consume_while(|u| u != b'\n' && (u as char).is_ascii_whitespace());
+                self.trivia.then(|| Token::Whitespace(self.text(start)))
+            }
             b'=' => Some(self.either(b'=', Token::EqualEqual, Token::Equal)),
             b'!' => Some(self.either(b'=', Token::BangEqual, Token::Bang)),
             b'>' => Some(self.either(b'=', Token::GreaterEqual, Token::Greater)),
             b'<' => Some(self.either(b'=', Token::LessEqual, Token::Less)),
             b'/' => {
+                let start = self.sc.pos - 1;
                 if self.sc.consume_if(|u| u == b'/') {
                     self.sc.consume_while(|u| u != b'\n');
-                    None
+                    self.line_comment(start)
+                } else if self.sc.consume_if(|u| u == b'*') {
+                    self.block_comment(start)
                 } else {
                     Some(Token::Slash)
                 }
             }
-            b'"' => {
-                let st = self.sc.consume_while(|u| u != b'"');
-                if self.sc.next().is_some() {
-                    Some(Token::String(unsafe {
-                        Cow::Borrowed(std::str::from_utf8_unchecked(st))
-                    }))
-                } else {
-                    Some(Token::Error(LoxError::UnterminatedStr(self.line)))
-                }
+            b'"' => Some(self.string(false)),
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => Some(self.identifier(self.sc.pos - 1)),
+            b'0'..=b'9' => Some(self.number(v)),
+            b'(' => Some(Token::LeftParen),
+            b')' => Some(Token::RightParen),
+            b'$' if mem::take(&mut self.open_interpolation) => {
+                self.sc.advance();
+                self.interpolations.push(0);
+                Some(Token::InterpolationStart)
             }
-            b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
-                let rng = self.identifier();
-                let start = rng.start.checked_sub(1).unwrap_or(rng.start);
-                let st = unsafe { std::str::from_utf8_unchecked(&self.sc.val[start..rng.end]) };
-                if let Ok(token) = Token::from_str(st) {
-                    Some(token)
-                } else {
-                    Some(Token::Identifier(Cow::Borrowed(st)))
+            b'{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
                 }
+                Some(Token::LeftBrace)
             }
-            b'0'..=b'9' => {
-                let rng = self.number();
-                let start = rng.start.checked_sub(1).unwrap_or(rng.start);
-                let st = unsafe { std::str::from_utf8_unchecked(&self.sc.val[start..rng.end]) };
-                Some(Token::Number(
-                    f64::from_str(st).unwrap_or_default(),
-                    Cow::Borrowed(st),
-                ))
-            }
-            b'(' => Some(Token::LeftParen),
-            b')' => Some(Token::RightParen),
-            b'{' => Some(Token::LeftBrace),
-            b'}' => Some(Token::RightBrace),
+            b'}' => match self.interpolations.last_mut() {
+                Some(0) => {
+                    self.interpolations.pop();
+                    self.resume_string = true;
+                    Some(Token::InterpolationEnd)
+                }
+                Some(depth) => {
+                    *depth -= 1;
+                    Some(Token::RightBrace)
+                }
+                None => Some(Token::RightBrace),
+            },
             b'[' => Some(Token::LeftBracket),
             b']' => Some(Token::RightBracket),
             b',' => Some(Token::Comma),
@@ -157,8 +211,184 @@ impl<'a> Lexer<'a> {
             b';' => Some(Token::Semicolon),
             b'*' => Some(Token::Star),
             b'.' => Some(Token::Dot),
-            e => Some(Token::Error(LoxError::InvalidChar(self.line, e as char))),
+            e => {
+                let start = self.sc.pos - 1;
+                let c = self.rest_of_char(e);
+                if is_identifier_start(c) {
+                    return Some(self.identifier(start));
+                }
+                Some(Token::Error(LexError::InvalidChar(self.line, c)))
+            }
+        }
+    }
+    /// The source from `start` up to the current position.
+    fn text(&self, start: usize) -> Cow<'a, str> {
+        String::from_utf8_lossy(&self.sc.val[start..self.sc.pos])
+    }
+    /// A `//` comment starting at `start`, already consumed up to the end
+    /// of its line. Three slashes, but not four, make a doc comment, which
+    /// is always emitted.
+    fn line_comment(&self, start: usize) -> Option<Token<'a>> {
+        let text = self.text(start);
+        let doc = match &text {
+            Cow::Borrowed(s) => s.strip_prefix("///").map(Cow::Borrowed),
+            Cow::Owned(s) => s.strip_prefix("///").map(|s| Cow::Owned(s.to_owned())),
+        };
+        match doc {
+            Some(doc) if !doc.starts_with('/') => Some(Token::DocComment(doc)),
+            _ => self.trivia.then_some(Token::Comment(text)),
+        }
+    }
+    /// A `/* ... */` comment after its opening delimiter. Block comments
+    /// nest, so `/* a /* b */ c */` is a single comment.
+    fn block_comment(&mut self, start: usize) -> Option<Token<'a>> {
+        let line = self.line;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.sc.next() {
+                None => return Some(Token::Error(LexError::UnterminatedComment(line))),
+                Some(b'*') if self.sc.consume_if(|u| u == b'/') => depth -= 1,
+                Some(b'/') if self.sc.consume_if(|u| u == b'*') => depth += 1,
+                Some(b'\n') => self.line += 1,
+                Some(_) => {}
+            }
+        }
+        self.trivia.then(|| Token::Comment(self.text(start)))
+    }
+    /// Consumes the rest of the UTF-8 character whose first byte was
+    /// `first`. A byte that does not start a valid character is taken on
+    /// its own as U+FFFD.
+    fn rest_of_char(&mut self, first: u8) -> char {
+        if first.is_ascii() {
+            return first as char;
+        }
+        self.sc.pos -= 1;
+        match self.sc.peek_char() {
+            Some(c) => {
+                self.sc.pos += c.len_utf8();
+                c
+            }
+            None => {
+                self.sc.advance();
+                char::REPLACEMENT_CHARACTER
+            }
+        }
+    }
+    /// Scans a string literal after its opening quote. Literals without
+    /// escapes borrow the source; the rest are decoded into an owned
+    /// string. Invalid escapes are kept as written, and the first one is
+    /// reported by an error token that follows the literal.
+    ///
+    /// A literal containing `${` is split into [`Token::StringFragment`]s
+    /// around the interpolated expressions; `continued` is set when
+    /// scanning the part after one of them.
+    fn string(&mut self, continued: bool) -> Token<'a> {
+        // The token keeps the line it starts on; later tokens must still
+        // count the newlines inside it.
+        let line = self.line;
+        // A continued part starts after the `}`; otherwise the opening
+        // quote has been consumed.
+        let start = if continued {
+            self.sc.pos
+        } else {
+            self.sc.pos - 1
+        };
+        let mut decoded: Option<String> = None;
+        let mut chunk = self.sc.pos;
+        let mut invalid = None;
+        loop {
+            match self.sc.peek() {
+                None => return Token::Error(LexError::UnterminatedStr(line)),
+                Some(b'"') => break,
+                Some(b'$') if self.sc.val.get(self.sc.pos + 1) == Some(&b'{') => {
+                    self.open_interpolation = true;
+                    break;
+                }
+                Some(b'\\') => {
+                    let start = self.sc.pos;
+                    let escape_line = self.line;
+                    let raw = &self.sc.val[chunk..start];
+                    self.sc.advance();
+                    let escape = self.escape();
+                    let s = decoded.get_or_insert_with(String::new);
+                    match escape {
+                        Some(c) => {
+                            s.push_str(&String::from_utf8_lossy(raw));
+                            s.push(c);
+                        }
+                        None => {
+                            s.push_str(&String::from_utf8_lossy(&self.sc.val[chunk..self.sc.pos]));
+                            invalid.get_or_insert((escape_line, start..self.sc.pos));
+                        }
+                    }
+                    chunk = self.sc.pos;
+                }
+                Some(b'\n') => {
+                    self.line += 1;
+                    self.sc.advance();
+                }
+                Some(_) => self.sc.advance(),
+            }
+        }
+        let rest = String::from_utf8_lossy(&self.sc.val[chunk..self.sc.pos]);
+        let fragment = continued || self.open_interpolation;
+        if !self.open_interpolation {
+            self.sc.advance();
+        }
+        if let Some((line, span)) = invalid {
+            let escape = String::from_utf8_lossy(&self.sc.val[span.clone()]).into_owned();
+            let error = Token::Error(LexError::InvalidEscape(line, escape));
+            self.queued = Some(WithSpan::new(error, line, span).file(self.file));
         }
+        let text = match decoded {
+            Some(mut s) => {
+                s.push_str(&rest);
+                Cow::Owned(s)
+            }
+            None => rest,
+        };
+        let lexeme = String::from_utf8_lossy(&self.sc.val[start..self.sc.pos]);
+        if fragment {
+            Token::StringFragment(text, lexeme)
+        } else {
+            Token::String(text, lexeme)
+        }
+    }
+    /// Decodes the escape after a backslash, consuming it even if it is
+    /// invalid.
+    fn escape(&mut self) -> Option<char> {
+        let c = match self.sc.next()? {
+            b'n' => '\n',
+            b't' => '\t',
+            b'r' => '\r',
+            b'"' => '"',
+            b'\\' => '\\',
+            b'$' => '$',
+            b'u' => return self.unicode_escape(),
+            b'\n' => {
+                self.line += 1;
+                return None;
+            }
+            other => {
+                self.rest_of_char(other);
+                return None;
+            }
+        };
+        Some(c)
+    }
+    /// `\u{...}`: one to six hex digits naming a Unicode scalar value.
+    fn unicode_escape(&mut self) -> Option<char> {
+        if !self.sc.consume_if(|u| u == b'{') {
+            return None;
+        }
+        let digits = self.sc.consume_while(|u| u.is_ascii_hexdigit());
+        if !self.sc.consume_if(|u| u == b'}') || digits.is_empty() || digits.len() > 6 {
+            return None;
+        }
+        let digits = std::str::from_utf8(digits).ok()?;
+        u32::from_str_radix(digits, 16)
+            .ok()
+            .and_then(char::from_u32)
     }
     fn either(&mut self, to_match: u8, matched: Token<'a>, unmatched: Token<'a>) -> Token<'a> {
         if self.sc.consume_if(|v| v == to_match) {
@@ -167,55 +397,168 @@ impl<'a> Lexer<'a> {
             unmatched
         }
     }
-    fn number(&mut self) -> Range<usize> {
-        let start = self.sc.pos;
-        self.sc.consume_while(|u| matches!(u, b'0'..=b'9'));
-        if let Some(v) = self.sc.peek() {
-            if v == b'.' {
-                if self.sc.consume_if_next(|v| matches!(v, b'0'..=b'9')) {
-                    self.sc.consume_while(|u| matches!(u, b'0'..=b'9'));
-                }
+    /// Scans a number literal whose first digit was `first`: decimal with
+    /// an optional fraction and exponent, or an integer after a `0x`, `0b`
+    /// or `0o` prefix. Digits may be separated by `_`. The token keeps the
+    /// literal as written.
+    fn number(&mut self, first: u8) -> Token<'a> {
+        let start = self.sc.pos - 1;
+        let radix = match (first, self.sc.peek()) {
+            (b'0', Some(b'x' | b'X')) => Some(16),
+            (b'0', Some(b'b' | b'B')) => Some(2),
+            (b'0', Some(b'o' | b'O')) => Some(8),
+            _ => None,
+        };
+        let value = match radix {
+            Some(radix) => {
+                self.sc.advance();
+                // Take letters too, so that `0x1G` is one bad literal rather
+                // than a number followed by an identifier.
+                let digits = self
+                    .sc
+                    .consume_while(|u| u.is_ascii_alphanumeric() || u == b'_');
+                integer(digits, radix)
+            }
+            None => self.decimal(start),
+        };
+        let lexeme = self.text(start);
+        match value {
+            Ok(value) => Token::Number(value, lexeme),
+            Err(reason) => Token::Error(LexError::InvalidNumber(
+                self.line,
+                lexeme.into_owned(),
+                reason,
+            )),
+        }
+    }
+    fn decimal(&mut self, start: usize) -> Result<f64, &'static str> {
+        let digits = |u: u8| u.is_ascii_digit() || u == b'_';
+        self.sc.consume_while(digits);
+        if self.sc.peek() == Some(b'.') && self.sc.consume_if_next(|v| v.is_ascii_digit()) {
+            self.sc.consume_while(digits);
+        }
+        if self.sc.consume_if(|u| u == b'e' || u == b'E') {
+            self.sc.consume_if(|u| u == b'+' || u == b'-');
+            if self.sc.consume_while(digits).is_empty() {
+                return Err("expected digits in the exponent");
             }
         }
-        start..self.sc.pos
+        let text = &self.sc.val[start..self.sc.pos];
+        if !separated(text, |u| u.is_ascii_digit()) {
+            return Err(SEPARATOR);
+        }
+        let text = text
+            .iter()
+            .filter(|&&u| u != b'_')
+            .map(|&u| u as char)
+            .collect::<String>();
+        f64::from_str(&text).map_err(|_| "malformed number")
     }
-    fn identifier(&mut self) -> Range<usize> {
-        let start = self.sc.pos;
-        self.sc
-            .consume_while(|u| matches!(u, b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_'));
-        start..self.sc.pos
+    /// Scans the rest of a keyword or identifier whose first character
+    /// started at `start`.
+    fn identifier(&mut self, start: usize) -> Token<'a> {
+        loop {
+            self.sc
+                .consume_while(|u| matches!(u, b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_'));
+            match self.sc.peek_char() {
+                Some(c) if !c.is_ascii() && is_identifier_continue(c) => {
+                    self.sc.pos += c.len_utf8();
+                }
+                _ => break,
+            }
+        }
+        // Only ASCII and whole characters decoded from valid UTF-8 were
+        // consumed.
+        let st = unsafe { std::str::from_utf8_unchecked(&self.sc.val[start..self.sc.pos]) };
+        Token::from_str(st).unwrap_or(Token::Identifier(Cow::Borrowed(st)))
     }
 }
 
 impl<'a> Iterator for Lexer<'a> {
     type Item = WithSpan<Token<'a>>;
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(token) = self.queued.take() {
+            return Some(token);
+        }
         loop {
             let initial_pos = self.sc.pos;
-            let nxt_char = self.sc.next();
-            if nxt_char.is_none() {
-                if self.end {
-                    return None;
-                } else {
+            // A newline belongs to the line it ends.
+            let line = self.line;
+            let token = if mem::take(&mut self.resume_string) {
+                Some(self.string(true))
+            } else {
+                let Some(nxt_char) = self.sc.next() else {
+                    if self.end {
+                        return None;
+                    }
                     self.end = true;
-                    let end_pos = self.sc.pos.checked_sub(1).unwrap_or(0);
-                    return Some(WithSpan::new(Token::Eof, self.line, end_pos..end_pos));
+                    let end_pos = self.sc.pos.saturating_sub(1);
+                    return Some(
+                        WithSpan::new(Token::Eof, self.line, end_pos..end_pos).file(self.file),
+                    );
+                };
+                if nxt_char == b'\n' {
+                    self.line += 1;
                 }
-            }
-            let nxt_char = nxt_char.unwrap();
-            if nxt_char == b'\n' {
-                self.line += 1;
-            }
-            let token = self.match_token(nxt_char);
-            if token.is_none() {
+                self.match_token(nxt_char)
+            };
+            let Some(token) = token else {
                 continue;
-            }
-            let end_pos = self.sc.pos;
-            return Some(WithSpan::new(
-                token.unwrap(),
-                self.line,
-                initial_pos..end_pos,
-            ));
+            };
+            let span = initial_pos..self.sc.pos;
+            return Some(WithSpan::new(token, line, span).file(self.file));
         }
     }
 }
+
+/// Whether a non-ASCII character may start an identifier: never, unless
+/// the `unicode-identifiers` feature is enabled, which accepts Unicode
+/// `XID_Start`.
+#[inline]
+fn is_identifier_start(c: char) -> bool {
+    #[cfg(feature = "unicode-identifiers")]
+    return unicode_xid::UnicodeXID::is_xid_start(c);
+    #[cfg(not(feature = "unicode-identifiers"))]
+    return c.is_ascii_alphabetic() || c == '_';
+}
+
+/// Whether a non-ASCII character may continue an identifier, see
+/// [`is_identifier_start`].
+#[inline]
+fn is_identifier_continue(c: char) -> bool {
+    #[cfg(feature = "unicode-identifiers")]
+    return unicode_xid::UnicodeXID::is_xid_continue(c);
+    #[cfg(not(feature = "unicode-identifiers"))]
+    return c.is_ascii_alphanumeric() || c == '_';
+}
+
+const SEPARATOR: &str = "'_' must separate two digits";
+
+/// The value of the digits of an integer literal after its base prefix.
+fn integer(digits: &[u8], radix: u32) -> Result<f64, &'static str> {
+    if digits.is_empty() {
+        return Err("expected digits after the prefix");
+    }
+    if !separated(digits, |u| u.is_ascii_alphanumeric()) {
+        return Err(SEPARATOR);
+    }
+    // Accumulated as a float so that long literals lose precision the way
+    // decimal ones do instead of overflowing.
+    digits
+        .iter()
+        .filter(|&&u| u != b'_')
+        .try_fold(0.0, |value, &u| {
+            let digit = (u as char)
+                .to_digit(radix)
+                .ok_or("invalid digit for the base")?;
+            Ok(value * radix as f64 + digit as f64)
+        })
+}
+
+/// Whether every `_` in `text` sits between two digits.
+fn separated(text: &[u8], is_digit: impl Fn(u8) -> bool) -> bool {
+    text.iter().enumerate().all(|(i, &u)| {
+        u != b'_'
+            || (i > 0 && is_digit(text[i - 1]) && text.get(i + 1).is_some_and(|&u| is_digit(u)))
+    })
+}
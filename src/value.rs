@@ -0,0 +1,281 @@
+use std::{
+    cell::RefCell,
+    fmt::{self, Write},
+    mem,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
+
+use crate::{
+    callable::{Function, NativeFunction},
+    class::{Class, Instance},
+    error::TypeError,
+    module::Module,
+    number::format_number,
+};
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(Rc<str>),
+    Function(Rc<Function>),
+    NativeFunction(Rc<NativeFunction>),
+    Class(Rc<Class>),
+    Instance(Rc<RefCell<Instance>>),
+    List(Rc<RefCell<List>>),
+    Module(Rc<Module>),
+}
+
+/// The items of a list value. Dropping it frees nested lists and instances
+/// without recursing, so arbitrarily deep structures can be released.
+#[derive(Debug, Default)]
+pub struct List(Vec<Value>);
+
+impl Deref for List {
+    type Target = Vec<Value>;
+
+    #[inline]
+    fn deref(&self) -> &Vec<Value> {
+        &self.0
+    }
+}
+
+impl DerefMut for List {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Vec<Value> {
+        &mut self.0
+    }
+}
+
+impl From<Vec<Value>> for List {
+    #[inline]
+    fn from(items: Vec<Value>) -> Self {
+        Self(items)
+    }
+}
+
+impl Drop for List {
+    fn drop(&mut self) {
+        if !self.0.is_empty() {
+            drop_values(mem::take(&mut self.0));
+        }
+    }
+}
+
+/// Drops `values` with a worklist instead of the call stack: the contents
+/// of every list and instance freed along the way are queued rather than
+/// dropped in place.
+pub(crate) fn drop_values(values: impl IntoIterator<Item = Value>) {
+    let mut pending: Vec<Value> = values.into_iter().collect();
+    while let Some(value) = pending.pop() {
+        match value {
+            Value::Instance(instance) => {
+                if let Ok(instance) = Rc::try_unwrap(instance) {
+                    let mut instance = instance.into_inner();
+                    pending.extend(mem::take(&mut instance.fields).into_values());
+                }
+            }
+            Value::List(list) => {
+                if let Ok(list) = Rc::try_unwrap(list) {
+                    pending.append(&mut list.into_inner().0);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Value {
+    #[inline]
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Self::Nil | Self::Boolean(false))
+    }
+
+    #[inline]
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Self::Nil)
+    }
+
+    #[inline]
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Self::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Nil => "nil",
+            Self::Boolean(_) => "boolean",
+            Self::Number(_) => "number",
+            Self::String(_) => "string",
+            Self::Function(_) | Self::NativeFunction(_) => "function",
+            Self::Class(_) => "class",
+            Self::Instance(_) => "instance",
+            Self::List(_) => "list",
+            Self::Module(_) => "module",
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Nil, Self::Nil) => true,
+            (Self::Boolean(a), Self::Boolean(b)) => a == b,
+            (Self::Number(a), Self::Number(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Function(a), Self::Function(b)) => Rc::ptr_eq(a, b),
+            (Self::NativeFunction(a), Self::NativeFunction(b)) => Rc::ptr_eq(a, b),
+            (Self::Class(a), Self::Class(b)) => Rc::ptr_eq(a, b),
+            (Self::Instance(a), Self::Instance(b)) => Rc::ptr_eq(a, b),
+            (Self::List(a), Self::List(b)) => Rc::ptr_eq(a, b),
+            (Self::Module(a), Self::Module(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Nil => f.write_str("nil"),
+            Self::Boolean(v) => write!(f, "{v}"),
+            Self::Number(v) => f.write_str(&format_number(*v)),
+            Self::String(v) => f.write_str(v),
+            Self::Function(v) => write!(f, "<fn {}>", v.name()),
+            Self::NativeFunction(_) => f.write_str("<native fn>"),
+            Self::Class(v) => f.write_str(v.name()),
+            Self::Instance(v) => write!(f, "{} instance", v.borrow().class().name()),
+            Self::List(v) => {
+                // A list that contains itself would otherwise print forever.
+                let Ok(items) = v.try_borrow_mut() else {
+                    return f.write_str("[...]");
+                };
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_char(']')
+            }
+            Self::Module(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+impl Value {
+    fn type_error(&self, expected: &'static str) -> TypeError {
+        TypeError {
+            expected,
+            found: self.type_name(),
+        }
+    }
+}
+
+impl TryFrom<&Value> for f64 {
+    type Error = TypeError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(n) => Ok(*n),
+            v => Err(v.type_error("number")),
+        }
+    }
+}
+
+impl TryFrom<&Value> for bool {
+    type Error = TypeError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Boolean(b) => Ok(*b),
+            v => Err(v.type_error("boolean")),
+        }
+    }
+}
+
+impl TryFrom<&Value> for String {
+    type Error = TypeError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(s) => Ok(s.to_string()),
+            v => Err(v.type_error("string")),
+        }
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = TypeError;
+
+    #[inline]
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        Self::try_from(&value)
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = TypeError;
+
+    #[inline]
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        Self::try_from(&value)
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = TypeError;
+
+    #[inline]
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        Self::try_from(&value)
+    }
+}
+
+impl From<()> for Value {
+    #[inline]
+    fn from(_: ()) -> Self {
+        Self::Nil
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    #[inline]
+    fn from(value: Vec<Value>) -> Self {
+        Self::List(Rc::new(RefCell::new(List(value))))
+    }
+}
+
+impl From<f64> for Value {
+    #[inline]
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<bool> for Value {
+    #[inline]
+    fn from(value: bool) -> Self {
+        Self::Boolean(value)
+    }
+}
+
+impl From<&str> for Value {
+    #[inline]
+    fn from(value: &str) -> Self {
+        Self::String(Rc::from(value))
+    }
+}
+
+impl From<String> for Value {
+    #[inline]
+    fn from(value: String) -> Self {
+        Self::String(Rc::from(value))
+    }
+}
@@ -0,0 +1,906 @@
+//! Bytecode backend: compiles a resolved [`Ast`] into
+//! [`Chunk`]s and executes them on a stack-based virtual machine with the
+//! same observable behaviour as the tree-walking [`Interpreter`](crate::Interpreter).
+//!
+//! Modules are not supported: a program containing `import` is rejected
+//! with a [`CompileError`] before any of it runs.
+
+mod chunk;
+mod compiler;
+mod heap;
+mod native;
+mod object;
+mod value;
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    rc::Rc,
+    time::Instant,
+};
+
+pub use chunk::{Chunk, OpCode};
+pub use compiler::{CompileError, Compiler};
+pub use heap::{Heap, HeapStats};
+pub use object::{ObjRef, Object};
+pub use value::Value;
+
+use crate::{
+    ast::Ast,
+    builtins::list_index,
+    error::{Frame, RuntimeError},
+    limits::{InterruptHandle, Limit, Limits},
+    number::format_number,
+    WithSpan,
+};
+
+use object::{BoundMethod, Class, Closure, Instance, Native, NativeFn, Upvalue};
+
+/// Global variables are addressed by a slot assigned at compile time.
+#[derive(Debug, Default)]
+pub struct Globals {
+    names: Vec<Rc<str>>,
+    slots: HashMap<Rc<str>, u16>,
+    values: Vec<Option<Value>>,
+}
+
+impl Globals {
+    /// Returns the slot for `name`, allocating one on first use, or `None`
+    /// once every slot is taken.
+    pub fn slot(&mut self, name: &str) -> Option<u16> {
+        if let Some(&slot) = self.slots.get(name) {
+            return Some(slot);
+        }
+        let slot = u16::try_from(self.names.len()).ok()?;
+        let name: Rc<str> = Rc::from(name);
+        self.names.push(Rc::clone(&name));
+        self.slots.insert(name, slot);
+        self.values.push(None);
+        Some(slot)
+    }
+
+    /// Returns the value of a defined global.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        let &slot = self.slots.get(name)?;
+        self.values[slot as usize]
+    }
+
+    #[inline]
+    pub fn name(&self, slot: u16) -> &str {
+        &self.names[slot as usize]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmError {
+    Compile(CompileError),
+    Runtime(RuntimeError),
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Compile(e) => write!(f, "{e}"),
+            Self::Runtime(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+impl From<CompileError> for VmError {
+    fn from(e: CompileError) -> Self {
+        Self::Compile(e)
+    }
+}
+
+impl From<RuntimeError> for VmError {
+    fn from(e: RuntimeError) -> Self {
+        Self::Runtime(e)
+    }
+}
+
+struct CallFrame {
+    closure: ObjRef,
+    chunk: Rc<Chunk>,
+    ip: usize,
+    base: usize,
+}
+
+pub struct Vm {
+    heap: Heap,
+    globals: Globals,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    open_upvalues: Vec<ObjRef>,
+    limits: Limits,
+    /// Instructions executed in the current call to [`Vm::interpret`].
+    steps: u64,
+    deadline: Option<Instant>,
+    interrupt: InterruptHandle,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        let mut vm = Self {
+            heap: Heap::new(),
+            globals: Globals::default(),
+            stack: Vec::with_capacity(256),
+            frames: Vec::with_capacity(64),
+            open_upvalues: Vec::new(),
+            limits: Limits::default(),
+            steps: 0,
+            deadline: None,
+            interrupt: InterruptHandle::default(),
+        };
+        vm.define_native("clock", 0, native::clock);
+        vm.define_native("len", 1, native::len);
+        vm.define_native("push", 2, native::push);
+        vm.define_native("pop", 1, native::pop);
+        vm.define_native("insert", 3, native::insert);
+        vm.define_native("remove", 2, native::remove);
+        vm
+    }
+}
+
+type VmResult<T> = Result<T, RuntimeError>;
+
+impl Vm {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bounds the work done by each call to [`Vm::interpret`]. A program
+    /// that exceeds a limit fails with a runtime error whose
+    /// [`limit`](RuntimeError::limit) says which.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    #[inline]
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    #[inline]
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Returns a handle that stops the running program when interrupted.
+    /// It can be sent to another thread.
+    #[inline]
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    #[inline]
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    #[inline]
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.globals.get(name)
+    }
+
+    #[inline]
+    pub fn heap_stats(&self) -> HeapStats {
+        self.heap.stats()
+    }
+
+    /// Enables or disables collecting garbage before every allocation.
+    #[inline]
+    pub fn set_gc_stress(&mut self, stress: bool) {
+        self.heap.set_stress(stress);
+    }
+
+    /// Runs a full collection, returning the number of objects freed.
+    pub fn collect_garbage(&mut self) -> usize {
+        for &value in &self.stack {
+            self.heap.mark_value(value);
+        }
+        for value in self.globals.values.iter().flatten() {
+            self.heap.mark_value(*value);
+        }
+        for frame in &self.frames {
+            self.heap.mark(frame.closure);
+        }
+        for &upvalue in &self.open_upvalues {
+            self.heap.mark(upvalue);
+        }
+        self.heap.collect()
+    }
+
+    /// Allocates an object, collecting first if the heap has grown past
+    /// its threshold. Everything `object` refers to must already be rooted.
+    fn alloc(&mut self, object: Object) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        self.heap.alloc(object)
+    }
+
+    /// Defines a native function as the global `name`. Panics once every
+    /// global slot is taken.
+    pub fn define_native(&mut self, name: &str, arity: usize, func: NativeFn) {
+        let native = self.alloc(Object::Native(Native { arity, func }));
+        let slot = self.globals.slot(name).expect("too many globals");
+        self.globals.values[slot as usize] = Some(Value::Obj(native));
+    }
+
+    /// Compiles and runs a program, printing to stdout. Nothing runs if
+    /// compiling fails, e.g. because the program uses `import`.
+    pub fn interpret(&mut self, ast: &Ast) -> Result<(), VmError> {
+        let function = Compiler::new(&mut self.heap, &mut self.globals).compile(ast)?;
+        self.stack.push(Value::Obj(function));
+        let closure = self.alloc(Object::Closure(Closure {
+            function,
+            upvalues: Vec::new(),
+        }));
+        self.stack.pop();
+        self.stack.push(Value::Obj(closure));
+        self.interrupt.clear();
+        self.steps = 0;
+        self.deadline = self.limits.time().map(|time| Instant::now() + time);
+        self.call(closure, 0, 0)?;
+        let result = self.run(&mut io::stdout().lock());
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result.map_err(VmError::Runtime)
+    }
+
+    /// Renders a value the way `print` does.
+    pub fn format_value(&self, value: Value) -> String {
+        self.format_nested(value, &mut HashSet::new())
+    }
+
+    /// Renders `value` inside the lists in `printing`, which are shown as
+    /// `[...]` if they occur again so that cycles print finitely.
+    fn format_nested(&self, value: Value, printing: &mut HashSet<ObjRef>) -> String {
+        match value {
+            Value::Nil => "nil".to_string(),
+            Value::Boolean(v) => v.to_string(),
+            Value::Number(v) => format_number(v),
+            Value::Obj(r) => match self.heap.get(r) {
+                Object::String(s) => s.to_string(),
+                Object::Function(f) => match &f.name {
+                    Some(name) => format!("<fn {name}>"),
+                    None => "<script>".to_string(),
+                },
+                Object::Native(_) => "<native fn>".to_string(),
+                Object::Closure(c) => self.format_nested(Value::Obj(c.function), printing),
+                Object::Upvalue(_) => "upvalue".to_string(),
+                Object::Class(c) => c.name.to_string(),
+                Object::Instance(i) => format!("{} instance", self.heap.as_class(i.class).name),
+                Object::BoundMethod(b) => self.format_nested(Value::Obj(b.method), printing),
+                Object::List(items) => {
+                    if !printing.insert(r) {
+                        return "[...]".to_string();
+                    }
+                    let items = items
+                        .iter()
+                        .map(|&item| self.format_nested(item, printing))
+                        .collect::<Vec<_>>();
+                    printing.remove(&r);
+                    format!("[{}]", items.join(", "))
+                }
+            },
+        }
+    }
+
+    fn values_equal(&self, a: Value, b: Value) -> bool {
+        match (a, b) {
+            (Value::Obj(x), Value::Obj(y)) => {
+                x == y
+                    || matches!(
+                        (self.heap.as_string(x), self.heap.as_string(y)),
+                        (Some(s), Some(t)) if s == t
+                    )
+            }
+            _ => a == b,
+        }
+    }
+
+    #[inline]
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    #[inline]
+    fn peek(&self, distance: usize) -> Value {
+        self.stack[self.stack.len() - 1 - distance]
+    }
+
+    #[inline]
+    fn frame(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("no active call frame")
+    }
+
+    #[inline]
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame();
+        let byte = frame.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    #[inline]
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frame();
+        let value =
+            u16::from_be_bytes([frame.chunk.code[frame.ip], frame.chunk.code[frame.ip + 1]]);
+        frame.ip += 2;
+        value
+    }
+
+    #[inline]
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_u16() as usize;
+        self.frame().chunk.constants[index]
+    }
+
+    fn read_name(&mut self) -> Rc<str> {
+        let constant = self.read_constant();
+        let r = constant.as_obj().expect("name constant must be a string");
+        Rc::clone(
+            self.heap
+                .as_string(r)
+                .expect("name constant must be a string"),
+        )
+    }
+
+    /// Builds a runtime error located at the instruction being executed,
+    /// with a [`Frame`] for every function call on the stack.
+    fn error<M: Into<String>>(&self, message: M) -> RuntimeError {
+        self.locate(|line| RuntimeError::new(message, line))
+    }
+
+    fn exceeded(&self, limit: Limit) -> RuntimeError {
+        self.locate(|line| RuntimeError::exceeded(limit, line))
+    }
+
+    /// Builds the error made by `error` for the line being executed, and
+    /// locates it as [`Vm::error`] does.
+    fn locate(&self, error: impl FnOnce(usize) -> RuntimeError) -> RuntimeError {
+        let site_of = |frame: &CallFrame| {
+            let offset = frame.ip.saturating_sub(1);
+            let site = WithSpan::new((), frame.chunk.line_at(offset), 0..0);
+            match frame.chunk.span_at(offset) {
+                Some((span, file)) => site.span(span).file(file),
+                None => site,
+            }
+        };
+        let trace = self
+            .frames
+            .windows(2)
+            .rev()
+            .map(|pair| {
+                let function = self
+                    .heap
+                    .as_function(self.heap.as_closure(pair[1].closure).function);
+                let name = function.name.as_deref().unwrap_or("script");
+                Frame::new(name, site_of(&pair[0]))
+            })
+            .collect();
+        let error = match self.frames.last().map(site_of) {
+            Some(site) => error(site.get_line())
+                .with_span(site.get_span())
+                .in_file(site.get_file()),
+            None => error(0),
+        };
+        error.with_trace(trace)
+    }
+
+    fn number_operands(&mut self) -> VmResult<(f64, f64)> {
+        match (self.peek(1), self.peek(0)) {
+            (Value::Number(a), Value::Number(b)) => {
+                self.stack.truncate(self.stack.len() - 2);
+                Ok((a, b))
+            }
+            _ => Err(self.error("Operands must be numbers.")),
+        }
+    }
+
+    /// Counts one instruction and checks every limit but depth.
+    fn tick(&mut self) -> VmResult<()> {
+        self.steps += 1;
+        let exceeded = if self.limits.steps().is_some_and(|max| self.steps > max) {
+            Some(Limit::Steps)
+        } else if self.over_allocation() {
+            Some(Limit::Allocation)
+        } else if self.interrupt.take() {
+            Some(Limit::Interrupted)
+        } else if self.steps % 256 == 0 && self.deadline.is_some_and(|d| Instant::now() >= d) {
+            // Reading the clock on every instruction would dominate run time.
+            Some(Limit::Time)
+        } else {
+            None
+        };
+        match exceeded {
+            Some(limit) => Err(self.exceeded(limit)),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the heap holds more than the allocation limit once garbage
+    /// has been collected.
+    fn over_allocation(&mut self) -> bool {
+        let Some(max) = self.limits.allocation() else {
+            return false;
+        };
+        if self.heap.stats().live_bytes <= max {
+            return false;
+        }
+        self.collect_garbage();
+        self.heap.stats().live_bytes > max
+    }
+
+    fn run<W: io::Write>(&mut self, out: &mut W) -> VmResult<()> {
+        loop {
+            self.tick()?;
+            let byte = self.read_byte();
+            let Ok(op) = OpCode::try_from(byte) else {
+                return Err(self.error(format!("Unknown opcode {byte}.")));
+            };
+            match op {
+                OpCode::Constant => {
+                    let constant = self.read_constant();
+                    self.push(constant);
+                }
+                OpCode::Nil => self.push(Value::Nil),
+                OpCode::True => self.push(Value::Boolean(true)),
+                OpCode::False => self.push(Value::Boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.read_byte() as usize;
+                    let base = self.frame().base;
+                    self.push(self.stack[base + slot]);
+                }
+                OpCode::SetLocal => {
+                    let slot = self.read_byte() as usize;
+                    let base = self.frame().base;
+                    self.stack[base + slot] = self.peek(0);
+                }
+                OpCode::GetGlobal => {
+                    let slot = self.read_u16();
+                    match self.globals.values[slot as usize] {
+                        Some(value) => self.push(value),
+                        None => {
+                            let name = self.globals.name(slot);
+                            return Err(self.error(format!("Undefined variable '{name}'.")));
+                        }
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let slot = self.read_u16();
+                    self.globals.values[slot as usize] = Some(self.pop());
+                }
+                OpCode::SetGlobal => {
+                    let slot = self.read_u16();
+                    let value = self.peek(0);
+                    match self.globals.values.get_mut(slot as usize) {
+                        Some(Some(global)) => *global = value,
+                        _ => {
+                            let name = self.globals.name(slot);
+                            return Err(self.error(format!("Undefined variable '{name}'.")));
+                        }
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let slot = self.read_byte() as usize;
+                    let upvalue = self.current_upvalue(slot);
+                    let value = match self.heap.get(upvalue) {
+                        Object::Upvalue(Upvalue::Open(index)) => self.stack[*index],
+                        Object::Upvalue(Upvalue::Closed(value)) => *value,
+                        _ => unreachable!("object is not an upvalue"),
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let slot = self.read_byte() as usize;
+                    let upvalue = self.current_upvalue(slot);
+                    let value = self.peek(0);
+                    match self.heap.get_mut(upvalue) {
+                        Object::Upvalue(Upvalue::Open(index)) => {
+                            let index = *index;
+                            self.stack[index] = value;
+                        }
+                        Object::Upvalue(Upvalue::Closed(closed)) => *closed = value,
+                        _ => unreachable!("object is not an upvalue"),
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_name();
+                    let instance = self.instance_ref(self.peek(0));
+                    let Some(instance) = instance else {
+                        return Err(self.error("Only instances have properties."));
+                    };
+                    let (field, class) = match self.heap.get(instance) {
+                        Object::Instance(i) => (i.fields.get(&name).copied(), i.class),
+                        _ => unreachable!(),
+                    };
+                    if let Some(value) = field {
+                        self.pop();
+                        self.push(value);
+                    } else {
+                        self.bind_method(class, &name)?;
+                    }
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_name();
+                    let Some(instance) = self.instance_ref(self.peek(1)) else {
+                        return Err(self.error("Only instances have fields."));
+                    };
+                    let value = self.pop();
+                    if let Object::Instance(i) = self.heap.get_mut(instance) {
+                        i.fields.insert(name, value);
+                    }
+                    self.heap.resize(instance);
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_name();
+                    let superclass = self.pop().as_obj().expect("superclass must be a class");
+                    self.bind_method(superclass, &name)?;
+                }
+                OpCode::Equal => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.push(Value::Boolean(self.values_equal(a, b)));
+                }
+                OpCode::Greater => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Boolean(a > b));
+                }
+                OpCode::Less => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Boolean(a < b));
+                }
+                OpCode::Add => self.add()?,
+                OpCode::Subtract => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Number(a - b));
+                }
+                OpCode::Multiply => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Number(a * b));
+                }
+                OpCode::Divide => {
+                    let (a, b) = self.number_operands()?;
+                    self.push(Value::Number(a / b));
+                }
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.push(Value::Boolean(value.is_falsey()));
+                }
+                OpCode::Negate => match self.peek(0) {
+                    Value::Number(n) => {
+                        self.pop();
+                        self.push(Value::Number(-n));
+                    }
+                    _ => return Err(self.error("Operand must be a number.")),
+                },
+                OpCode::Print => {
+                    let value = self.pop();
+                    writeln!(out, "{}", self.format_value(value))
+                        .map_err(|e| self.error(e.to_string()))?;
+                }
+                OpCode::Jump => {
+                    let offset = self.read_u16() as usize;
+                    self.frame().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16() as usize;
+                    if self.peek(0).is_falsey() {
+                        self.frame().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_u16() as usize;
+                    self.frame().ip -= offset;
+                }
+                OpCode::Call => {
+                    let argc = self.read_byte() as usize;
+                    self.call_value(self.peek(argc), argc)?;
+                }
+                OpCode::Invoke => {
+                    let name = self.read_name();
+                    let argc = self.read_byte() as usize;
+                    self.invoke(&name, argc)?;
+                }
+                OpCode::Closure => {
+                    let function = self.read_constant().as_obj().expect("closure constant");
+                    let count = self.heap.as_function(function).upvalue_count;
+                    let mut upvalues = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+                        let upvalue = if is_local {
+                            let base = self.frame().base;
+                            self.capture_upvalue(base + index)
+                        } else {
+                            self.current_upvalue(index)
+                        };
+                        upvalues.push(upvalue);
+                    }
+                    let closure = self.alloc(Object::Closure(Closure { function, upvalues }));
+                    self.push(Value::Obj(closure));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("no active call frame");
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_name();
+                    let class = self.alloc(Object::Class(Class {
+                        name,
+                        methods: HashMap::new(),
+                    }));
+                    self.push(Value::Obj(class));
+                }
+                OpCode::Inherit => {
+                    let superclass = match self.peek(1) {
+                        Value::Obj(r) if matches!(self.heap.get(r), Object::Class(_)) => r,
+                        _ => return Err(self.error("Superclass must be a class.")),
+                    };
+                    let subclass = self.peek(0).as_obj().expect("subclass must be a class");
+                    let methods = self.heap.as_class(superclass).methods.clone();
+                    self.heap.as_class_mut(subclass).methods.extend(methods);
+                    self.heap.resize(subclass);
+                    self.pop();
+                }
+                OpCode::BuildList => {
+                    let count = self.read_u16() as usize;
+                    let start = self.stack.len() - count;
+                    let items = self.stack[start..].to_vec();
+                    let list = self.alloc(Object::List(items));
+                    self.stack.truncate(start);
+                    self.push(Value::Obj(list));
+                }
+                OpCode::Interpolate => {
+                    let count = self.read_u16() as usize;
+                    let start = self.stack.len() - count;
+                    let s = self.stack[start..]
+                        .iter()
+                        .map(|&part| self.format_value(part))
+                        .collect::<String>();
+                    let s = self.alloc(Object::String(s.into()));
+                    self.stack.truncate(start);
+                    self.push(Value::Obj(s));
+                }
+                OpCode::GetIndex => {
+                    let (list, index) = self.list_index(self.peek(1), self.peek(0))?;
+                    let item = self.heap.as_list(list).expect("checked above")[index];
+                    self.stack.truncate(self.stack.len() - 2);
+                    self.push(item);
+                }
+                OpCode::SetIndex => {
+                    let (list, index) = self.list_index(self.peek(2), self.peek(1))?;
+                    let value = self.pop();
+                    self.heap.as_list_mut(list).expect("checked above")[index] = value;
+                    self.stack.truncate(self.stack.len() - 2);
+                    self.push(value);
+                }
+                OpCode::Method => {
+                    let name = self.read_name();
+                    let method = self.peek(0).as_obj().expect("method must be a closure");
+                    let class = self.peek(1).as_obj().expect("method owner must be a class");
+                    self.heap.as_class_mut(class).methods.insert(name, method);
+                    self.heap.resize(class);
+                    self.pop();
+                }
+            }
+        }
+    }
+
+    fn current_upvalue(&mut self, index: usize) -> ObjRef {
+        let closure = self.frame().closure;
+        self.heap.as_closure(closure).upvalues[index]
+    }
+
+    /// Checks that `list[index]` is in bounds.
+    fn list_index(&self, list: Value, index: Value) -> VmResult<(ObjRef, usize)> {
+        let Some((r, items)) = list
+            .as_obj()
+            .and_then(|r| self.heap.as_list(r).map(|items| (r, items)))
+        else {
+            return Err(self.error("Only lists can be indexed."));
+        };
+        let Value::Number(n) = index else {
+            return Err(self.error("List index must be a number."));
+        };
+        let index = list_index(n, items.len(), false).map_err(|e| self.error(e))?;
+        Ok((r, index))
+    }
+
+    fn instance_ref(&self, value: Value) -> Option<ObjRef> {
+        match value {
+            Value::Obj(r) if matches!(self.heap.get(r), Object::Instance(_)) => Some(r),
+            _ => None,
+        }
+    }
+
+    fn add(&mut self) -> VmResult<()> {
+        match (self.peek(1), self.peek(0)) {
+            (Value::Number(a), Value::Number(b)) => {
+                self.stack.truncate(self.stack.len() - 2);
+                self.push(Value::Number(a + b));
+                Ok(())
+            }
+            (Value::Obj(a), Value::Obj(b)) => {
+                let (Some(a), Some(b)) = (self.heap.as_string(a), self.heap.as_string(b)) else {
+                    return Err(self.error("Operands must be two numbers or two strings."));
+                };
+                let mut s = String::with_capacity(a.len() + b.len());
+                s.push_str(a);
+                s.push_str(b);
+                let result = self.alloc(Object::String(s.into()));
+                self.stack.truncate(self.stack.len() - 2);
+                self.push(Value::Obj(result));
+                Ok(())
+            }
+            _ => Err(self.error("Operands must be two numbers or two strings.")),
+        }
+    }
+
+    fn call(&mut self, closure: ObjRef, argc: usize, base: usize) -> VmResult<()> {
+        let function = self
+            .heap
+            .as_function(self.heap.as_closure(closure).function);
+        if argc != function.arity {
+            return Err(self.error(format!(
+                "Expected {} arguments but got {}.",
+                function.arity, argc
+            )));
+        }
+        // The script itself takes a frame too.
+        if self.frames.len() > self.limits.depth() {
+            return Err(self.exceeded(Limit::Depth));
+        }
+        let chunk = Rc::clone(&function.chunk);
+        self.frames.push(CallFrame {
+            closure,
+            chunk,
+            ip: 0,
+            base,
+        });
+        Ok(())
+    }
+
+    fn call_value(&mut self, callee: Value, argc: usize) -> VmResult<()> {
+        let base = self.stack.len() - argc - 1;
+        let Value::Obj(r) = callee else {
+            return Err(self.error("Can only call functions and classes."));
+        };
+        match self.heap.get(r) {
+            Object::Closure(_) => self.call(r, argc, base),
+            Object::Native(native) => {
+                if argc != native.arity {
+                    return Err(self.error(format!(
+                        "Expected {} arguments but got {}.",
+                        native.arity, argc
+                    )));
+                }
+                let func = native.func;
+                let result =
+                    func(&mut self.heap, &self.stack[base + 1..]).map_err(|e| self.error(e))?;
+                self.stack.truncate(base);
+                self.push(result);
+                Ok(())
+            }
+            Object::Class(class) => {
+                let init = class.methods.get("init").copied();
+                let instance = self.alloc(Object::Instance(Instance {
+                    class: r,
+                    fields: HashMap::new(),
+                }));
+                self.stack[base] = Value::Obj(instance);
+                match init {
+                    Some(init) => self.call(init, argc, base),
+                    None if argc != 0 => {
+                        Err(self.error(format!("Expected 0 arguments but got {argc}.")))
+                    }
+                    None => Ok(()),
+                }
+            }
+            Object::BoundMethod(bound) => {
+                let method = bound.method;
+                self.stack[base] = bound.receiver;
+                self.call(method, argc, base)
+            }
+            _ => Err(self.error("Can only call functions and classes.")),
+        }
+    }
+
+    fn invoke(&mut self, name: &str, argc: usize) -> VmResult<()> {
+        let Some(instance) = self.instance_ref(self.peek(argc)) else {
+            return Err(self.error("Only instances have properties."));
+        };
+        let (field, class) = match self.heap.get(instance) {
+            Object::Instance(i) => (i.fields.get(name).copied(), i.class),
+            _ => unreachable!(),
+        };
+        if let Some(field) = field {
+            let slot = self.stack.len() - argc - 1;
+            self.stack[slot] = field;
+            return self.call_value(field, argc);
+        }
+        match self.heap.as_class(class).methods.get(name).copied() {
+            Some(method) => {
+                let base = self.stack.len() - argc - 1;
+                self.call(method, argc, base)
+            }
+            None => Err(self.error(format!("Undefined property '{name}'."))),
+        }
+    }
+
+    /// Replaces the receiver on top of the stack with `name` bound to it.
+    fn bind_method(&mut self, class: ObjRef, name: &str) -> VmResult<()> {
+        let Some(method) = self.heap.as_class(class).methods.get(name).copied() else {
+            return Err(self.error(format!("Undefined property '{name}'.")));
+        };
+        let receiver = self.peek(0);
+        let bound = self.alloc(Object::BoundMethod(BoundMethod { receiver, method }));
+        self.pop();
+        self.push(Value::Obj(bound));
+        Ok(())
+    }
+
+    fn capture_upvalue(&mut self, index: usize) -> ObjRef {
+        for &upvalue in &self.open_upvalues {
+            if let Object::Upvalue(Upvalue::Open(i)) = self.heap.get(upvalue) {
+                if *i == index {
+                    return upvalue;
+                }
+            }
+        }
+        let upvalue = self.alloc(Object::Upvalue(Upvalue::Open(index)));
+        self.open_upvalues.push(upvalue);
+        upvalue
+    }
+
+    /// Moves every open upvalue pointing at or above `last` off the stack.
+    fn close_upvalues(&mut self, last: usize) {
+        let stack = &self.stack;
+        let heap = &mut self.heap;
+        self.open_upvalues
+            .retain(|&upvalue| match heap.get_mut(upvalue) {
+                Object::Upvalue(u @ Upvalue::Open(_)) => {
+                    let Upvalue::Open(index) = *u else {
+                        unreachable!()
+                    };
+                    if index >= last {
+                        *u = Upvalue::Closed(stack[index]);
+                        false
+                    } else {
+                        true
+                    }
+                }
+                _ => false,
+            });
+    }
+}
@@ -0,0 +1,219 @@
+use std::ops::Range;
+
+use crate::FileId;
+
+use super::value::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal,
+    SetLocal,
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    GetUpvalue,
+    SetUpvalue,
+    GetProperty,
+    SetProperty,
+    GetSuper,
+    Equal,
+    Greater,
+    Less,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    Call,
+    Invoke,
+    Closure,
+    CloseUpvalue,
+    Return,
+    Class,
+    Inherit,
+    Method,
+    BuildList,
+    GetIndex,
+    SetIndex,
+    Interpolate,
+}
+
+impl OpCode {
+    const ALL: [OpCode; 40] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::GetProperty,
+        OpCode::SetProperty,
+        OpCode::GetSuper,
+        OpCode::Equal,
+        OpCode::Greater,
+        OpCode::Less,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Invoke,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+        OpCode::Class,
+        OpCode::Inherit,
+        OpCode::Method,
+        OpCode::BuildList,
+        OpCode::GetIndex,
+        OpCode::SetIndex,
+        OpCode::Interpolate,
+    ];
+}
+
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+    #[inline]
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::ALL.get(value as usize).copied().ok_or(value)
+    }
+}
+
+/// A compiled function body: bytecode, its constant pool and run-length
+/// encoded tables mapping bytecode offsets back to source lines and spans.
+#[derive(Debug, Default, Clone)]
+pub struct Chunk {
+    pub(crate) code: Vec<u8>,
+    pub(crate) constants: Vec<Value>,
+    lines: Vec<(usize, usize)>,
+    spans: Vec<(usize, Range<usize>, FileId)>,
+}
+
+impl Chunk {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    #[inline]
+    pub fn constants(&self) -> &[Value] {
+        &self.constants
+    }
+
+    pub fn write(&mut self, byte: u8, line: usize) {
+        if self.lines.last().map_or(true, |&(_, last)| last != line) {
+            self.lines.push((self.code.len(), line));
+        }
+        self.code.push(byte);
+    }
+
+    /// Writes `byte` like [`write`](Self::write), recording that it was
+    /// compiled from `span` in `file`.
+    pub fn write_spanned(&mut self, byte: u8, line: usize, span: Range<usize>, file: FileId) {
+        let same = |(_, last_span, last_file): &(usize, Range<usize>, FileId)| {
+            *last_span == span && *last_file == file
+        };
+        if !self.spans.last().is_some_and(same) {
+            self.spans.push((self.code.len(), span, file));
+        }
+        self.write(byte, line);
+    }
+
+    #[inline]
+    pub fn write_op(&mut self, op: OpCode, line: usize) {
+        self.write(op as u8, line);
+    }
+
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    /// Returns the source line of the instruction at `offset`.
+    pub fn line_at(&self, offset: usize) -> usize {
+        match self
+            .lines
+            .binary_search_by_key(&offset, |&(start, _)| start)
+        {
+            Ok(i) => self.lines[i].1,
+            Err(0) => 0,
+            Err(i) => self.lines[i - 1].1,
+        }
+    }
+
+    /// Returns the source span and file of the instruction at `offset`, if
+    /// it was written with one.
+    pub fn span_at(&self, offset: usize) -> Option<(Range<usize>, FileId)> {
+        let i = match self
+            .spans
+            .binary_search_by_key(&offset, |&(start, ..)| start)
+        {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let (_, span, file) = &self.spans[i];
+        Some((span.clone(), *file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chunk, OpCode};
+    use crate::FileId;
+
+    #[test]
+    fn test_line_table() {
+        let mut chunk = Chunk::new();
+        chunk.write_op(OpCode::Nil, 1);
+        chunk.write_op(OpCode::Nil, 1);
+        chunk.write_op(OpCode::Pop, 3);
+        chunk.write_op(OpCode::Return, 7);
+        assert_eq!(chunk.line_at(0), 1);
+        assert_eq!(chunk.line_at(1), 1);
+        assert_eq!(chunk.line_at(2), 3);
+        assert_eq!(chunk.line_at(3), 7);
+        assert_eq!(OpCode::try_from(OpCode::Method as u8), Ok(OpCode::Method));
+        assert!(OpCode::try_from(200).is_err());
+    }
+
+    #[test]
+    fn test_span_table() {
+        let mut chunk = Chunk::new();
+        assert_eq!(chunk.span_at(0), None);
+        chunk.write_spanned(OpCode::Nil as u8, 1, 0..3, FileId(0));
+        chunk.write_spanned(OpCode::Pop as u8, 1, 0..3, FileId(0));
+        chunk.write_spanned(OpCode::Nil as u8, 2, 4..9, FileId(1));
+        assert_eq!(chunk.span_at(1), Some((0..3, FileId(0))));
+        assert_eq!(chunk.span_at(2), Some((4..9, FileId(1))));
+        assert_eq!(chunk.line_at(2), 2);
+    }
+}
@@ -0,0 +1,694 @@
+use std::{fmt, mem, ops::Range, rc::Rc};
+
+use crate::{
+    ast::{
+        Ast, BinaryOperator, ClassDecl, Expr, FunctionDecl, LogicalOperator, Stmt, UnaryOperator,
+    },
+    FileId, WithSpan,
+};
+
+use super::{
+    chunk::{Chunk, OpCode},
+    heap::Heap,
+    object::{Function, ObjRef, Object},
+    value::Value,
+    Globals,
+};
+
+const MAX_LOCALS: usize = u8::MAX as usize + 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    message: String,
+    line: usize,
+}
+
+impl CompileError {
+    #[inline]
+    pub fn new<M: Into<String>>(message: M, line: usize) -> Self {
+        Self {
+            message: message.into(),
+            line,
+        }
+    }
+
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    #[inline]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Error: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+type CompileResult<T> = Result<T, CompileError>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+struct Local {
+    name: Rc<str>,
+    depth: usize,
+    captured: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct UpvalueRef {
+    index: u8,
+    is_local: bool,
+}
+
+struct FunctionState {
+    kind: FunctionKind,
+    name: Option<Rc<str>>,
+    arity: usize,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+}
+
+impl FunctionState {
+    fn new(kind: FunctionKind, name: Option<Rc<str>>) -> Self {
+        // Slot zero holds the callee, which methods expose as `this`.
+        let receiver = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            _ => "",
+        };
+        Self {
+            kind,
+            name,
+            arity: 0,
+            chunk: Chunk::new(),
+            locals: vec![Local {
+                name: Rc::from(receiver),
+                depth: 0,
+                captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+        }
+    }
+
+    fn resolve_local(&self, name: &str) -> Option<u8> {
+        self.locals
+            .iter()
+            .rposition(|local| local.name.as_ref() == name)
+            .map(|i| i as u8)
+    }
+}
+
+/// Compiles a program into a script function allocated on `heap`.
+pub struct Compiler<'h> {
+    heap: &'h mut Heap,
+    globals: &'h mut Globals,
+    states: Vec<FunctionState>,
+    classes: Vec<bool>,
+    /// The span and file of the innermost node being compiled, recorded
+    /// against every byte emitted for it.
+    site: (Range<usize>, FileId),
+}
+
+impl<'h> Compiler<'h> {
+    pub fn new(heap: &'h mut Heap, globals: &'h mut Globals) -> Self {
+        Self {
+            heap,
+            globals,
+            states: Vec::new(),
+            classes: Vec::new(),
+            site: (0..0, FileId::default()),
+        }
+    }
+
+    /// Compiles `ast` into the script function. Fails on `import`, which
+    /// only the tree-walking interpreter supports.
+    pub fn compile(mut self, ast: &Ast) -> CompileResult<ObjRef> {
+        self.states
+            .push(FunctionState::new(FunctionKind::Script, None));
+        for stmt in ast {
+            self.statement(stmt)?;
+        }
+        let last_line = ast.last().map_or(1, |stmt| stmt.get_line());
+        Ok(self.end_function(last_line))
+    }
+
+    #[inline]
+    fn state(&mut self) -> &mut FunctionState {
+        self.states.last_mut().expect("no function being compiled")
+    }
+
+    #[inline]
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.state().chunk
+    }
+
+    #[inline]
+    fn emit(&mut self, op: OpCode, line: usize) {
+        self.emit_byte(op as u8, line);
+    }
+
+    #[inline]
+    fn emit_byte(&mut self, byte: u8, line: usize) {
+        let (span, file) = self.site.clone();
+        self.chunk().write_spanned(byte, line, span, file);
+    }
+
+    fn emit_u16(&mut self, value: u16, line: usize) {
+        let [hi, lo] = value.to_be_bytes();
+        self.emit_byte(hi, line);
+        self.emit_byte(lo, line);
+    }
+
+    fn make_constant(&mut self, value: Value, line: usize) -> CompileResult<u16> {
+        let index = self.chunk().add_constant(value);
+        u16::try_from(index)
+            .map_err(|_| CompileError::new("Too many constants in one chunk.", line))
+    }
+
+    fn emit_constant(&mut self, value: Value, line: usize) -> CompileResult<()> {
+        let index = self.make_constant(value, line)?;
+        self.emit(OpCode::Constant, line);
+        self.emit_u16(index, line);
+        Ok(())
+    }
+
+    fn name_constant(&mut self, name: &str, line: usize) -> CompileResult<u16> {
+        let s = self.heap.alloc_string(name);
+        self.make_constant(Value::Obj(s), line)
+    }
+
+    fn emit_jump(&mut self, op: OpCode, line: usize) -> usize {
+        self.emit(op, line);
+        self.emit_u16(u16::MAX, line);
+        self.chunk().code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize, line: usize) -> CompileResult<()> {
+        let jump = self.chunk().code.len() - offset - 2;
+        let jump = u16::try_from(jump)
+            .map_err(|_| CompileError::new("Too much code to jump over.", line))?;
+        let [hi, lo] = jump.to_be_bytes();
+        self.chunk().code[offset] = hi;
+        self.chunk().code[offset + 1] = lo;
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, loop_start: usize, line: usize) -> CompileResult<()> {
+        self.emit(OpCode::Loop, line);
+        let offset = self.chunk().code.len() - loop_start + 2;
+        let offset =
+            u16::try_from(offset).map_err(|_| CompileError::new("Loop body too large.", line))?;
+        self.emit_u16(offset, line);
+        Ok(())
+    }
+
+    fn emit_return(&mut self, line: usize) {
+        if self.state().kind == FunctionKind::Initializer {
+            self.emit(OpCode::GetLocal, line);
+            self.emit_byte(0, line);
+        } else {
+            self.emit(OpCode::Nil, line);
+        }
+        self.emit(OpCode::Return, line);
+    }
+
+    fn end_function(&mut self, line: usize) -> ObjRef {
+        self.emit_return(line);
+        let state = self.states.pop().expect("no function being compiled");
+        self.heap.alloc(Object::Function(Function {
+            name: state.name,
+            arity: state.arity,
+            upvalue_count: state.upvalues.len(),
+            chunk: Rc::new(state.chunk),
+        }))
+    }
+
+    fn begin_scope(&mut self) {
+        self.state().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self, line: usize) {
+        let state = self.state();
+        state.scope_depth -= 1;
+        let depth = state.scope_depth;
+        while let Some(local) = self.state().locals.last() {
+            if local.depth <= depth {
+                break;
+            }
+            let op = if local.captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            };
+            self.emit(op, line);
+            self.state().locals.pop();
+        }
+    }
+
+    fn add_local(&mut self, name: &str, line: usize) -> CompileResult<()> {
+        let state = self.state();
+        if state.locals.len() >= MAX_LOCALS {
+            return Err(CompileError::new(
+                "Too many local variables in function.",
+                line,
+            ));
+        }
+        let depth = state.scope_depth;
+        state.locals.push(Local {
+            name: Rc::from(name),
+            depth,
+            captured: false,
+        });
+        Ok(())
+    }
+
+    /// Declares `name` in the current scope, returning the global slot to
+    /// define when compiling at the top level.
+    fn declare_variable(&mut self, name: &str, line: usize) -> CompileResult<Option<u16>> {
+        if self.state().scope_depth > 0 {
+            self.add_local(name, line)?;
+            Ok(None)
+        } else {
+            self.global(name, line).map(Some)
+        }
+    }
+
+    fn global(&mut self, name: &str, line: usize) -> CompileResult<u16> {
+        self.globals
+            .slot(name)
+            .ok_or_else(|| CompileError::new("Too many global variables.", line))
+    }
+
+    fn define_variable(&mut self, global: Option<u16>, line: usize) {
+        if let Some(slot) = global {
+            self.emit(OpCode::DefineGlobal, line);
+            self.emit_u16(slot, line);
+        }
+    }
+
+    fn resolve_upvalue(
+        &mut self,
+        depth: usize,
+        name: &str,
+        line: usize,
+    ) -> CompileResult<Option<u8>> {
+        if depth == 0 {
+            return Ok(None);
+        }
+        if let Some(local) = self.states[depth - 1].resolve_local(name) {
+            self.states[depth - 1].locals[local as usize].captured = true;
+            return self.add_upvalue(depth, local, true, line).map(Some);
+        }
+        match self.resolve_upvalue(depth - 1, name, line)? {
+            Some(upvalue) => self.add_upvalue(depth, upvalue, false, line).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn add_upvalue(
+        &mut self,
+        depth: usize,
+        index: u8,
+        is_local: bool,
+        line: usize,
+    ) -> CompileResult<u8> {
+        let upvalue = UpvalueRef { index, is_local };
+        let upvalues = &mut self.states[depth].upvalues;
+        if let Some(i) = upvalues.iter().position(|u| *u == upvalue) {
+            return Ok(i as u8);
+        }
+        if upvalues.len() >= MAX_LOCALS {
+            return Err(CompileError::new(
+                "Too many closure variables in function.",
+                line,
+            ));
+        }
+        upvalues.push(upvalue);
+        Ok((upvalues.len() - 1) as u8)
+    }
+
+    fn named_variable(&mut self, name: &str, assign: bool, line: usize) -> CompileResult<()> {
+        if let Some(slot) = self.state().resolve_local(name) {
+            self.emit_access(OpCode::GetLocal, OpCode::SetLocal, assign, line);
+            self.emit_byte(slot, line);
+            return Ok(());
+        }
+        let depth = self.states.len() - 1;
+        if let Some(slot) = self.resolve_upvalue(depth, name, line)? {
+            self.emit_access(OpCode::GetUpvalue, OpCode::SetUpvalue, assign, line);
+            self.emit_byte(slot, line);
+        } else {
+            let slot = self.global(name, line)?;
+            self.emit_access(OpCode::GetGlobal, OpCode::SetGlobal, assign, line);
+            self.emit_u16(slot, line);
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn emit_access(&mut self, get: OpCode, set: OpCode, assign: bool, line: usize) {
+        self.emit(if assign { set } else { get }, line);
+    }
+
+    /// Compiles `node` with `compile`, attributing the code emitted outside
+    /// its nested nodes to its span.
+    fn at<T, R>(&mut self, node: &WithSpan<T>, compile: impl FnOnce(&mut Self) -> R) -> R {
+        let outer = mem::replace(&mut self.site, (node.get_span(), node.get_file()));
+        let result = compile(self);
+        self.site = outer;
+        result
+    }
+
+    fn statement(&mut self, stmt: &WithSpan<Stmt>) -> CompileResult<()> {
+        self.at(stmt, |this| this.statement_inner(stmt))
+    }
+
+    fn statement_inner(&mut self, stmt: &WithSpan<Stmt>) -> CompileResult<()> {
+        let line = stmt.get_line();
+        match stmt.get_value() {
+            Stmt::Expression(expr) => {
+                self.expression(expr)?;
+                self.emit(OpCode::Pop, line);
+            }
+            Stmt::Print(expr) => {
+                self.expression(expr)?;
+                self.emit(OpCode::Print, line);
+            }
+            Stmt::Import(..) => {
+                return Err(CompileError::new(
+                    "Imports are only supported by the tree-walking backend.",
+                    line,
+                ))
+            }
+            Stmt::Var(name, initializer) => {
+                let global = self.declare_variable(name.get_value(), name.get_line())?;
+                match initializer {
+                    Some(expr) => self.expression(expr)?,
+                    None => self.emit(OpCode::Nil, line),
+                }
+                self.define_variable(global, line);
+            }
+            Stmt::Block(stmts) => {
+                self.begin_scope();
+                for stmt in stmts {
+                    self.statement(stmt)?;
+                }
+                self.end_scope(line);
+            }
+            Stmt::If(condition, then_branch, else_branch) => {
+                self.expression(condition)?;
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse, line);
+                self.emit(OpCode::Pop, line);
+                self.statement(then_branch)?;
+                let else_jump = self.emit_jump(OpCode::Jump, line);
+                self.patch_jump(then_jump, line)?;
+                self.emit(OpCode::Pop, line);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch)?;
+                }
+                self.patch_jump(else_jump, line)?;
+            }
+            Stmt::While(condition, body) => {
+                let loop_start = self.chunk().code.len();
+                self.expression(condition)?;
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse, line);
+                self.emit(OpCode::Pop, line);
+                self.statement(body)?;
+                self.emit_loop(loop_start, line)?;
+                self.patch_jump(exit_jump, line)?;
+                self.emit(OpCode::Pop, line);
+            }
+            Stmt::Function(decl) => {
+                let name = decl.name.get_value();
+                let global = self.declare_variable(name, line)?;
+                self.function(decl, FunctionKind::Function)?;
+                self.define_variable(global, line);
+            }
+            Stmt::Return(value) => {
+                match value {
+                    Some(expr) => {
+                        self.expression(expr)?;
+                        self.emit(OpCode::Return, line);
+                    }
+                    None => self.emit_return(line),
+                };
+            }
+            Stmt::Class(decl) => self.class(decl, line)?,
+        }
+        Ok(())
+    }
+
+    fn function(&mut self, decl: &FunctionDecl, kind: FunctionKind) -> CompileResult<()> {
+        let line = decl.name.get_line();
+        let name = Rc::from(decl.name.get_value().as_ref());
+        self.states.push(FunctionState::new(kind, Some(name)));
+        self.begin_scope();
+        for param in &decl.params {
+            self.state().arity += 1;
+            self.add_local(param.get_value(), param.get_line())?;
+        }
+        for stmt in &decl.body {
+            self.statement(stmt)?;
+        }
+        let upvalues = self.state().upvalues.clone();
+        let end_line = decl.body.last().map_or(line, |stmt| stmt.get_line());
+        let function = self.end_function(end_line);
+        let index = self.make_constant(Value::Obj(function), line)?;
+        self.emit(OpCode::Closure, line);
+        self.emit_u16(index, line);
+        for upvalue in upvalues {
+            self.emit_byte(upvalue.is_local as u8, line);
+            self.emit_byte(upvalue.index, line);
+        }
+        Ok(())
+    }
+
+    fn class(&mut self, decl: &ClassDecl, line: usize) -> CompileResult<()> {
+        let name = decl.name.get_value();
+        let name_constant = self.name_constant(name, line)?;
+        let global = self.declare_variable(name, line)?;
+        self.emit(OpCode::Class, line);
+        self.emit_u16(name_constant, line);
+        self.define_variable(global, line);
+        self.classes.push(decl.superclass.is_some());
+
+        if let Some(superclass) = &decl.superclass {
+            self.expression(superclass)?;
+            self.begin_scope();
+            self.add_local("super", line)?;
+            self.named_variable(name, false, line)?;
+            self.emit(OpCode::Inherit, superclass.get_line());
+        }
+
+        self.named_variable(name, false, line)?;
+        for method in &decl.methods {
+            let method_line = method.name.get_line();
+            let kind = if method.name.get_value() == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            let constant = self.name_constant(method.name.get_value(), method_line)?;
+            self.function(method, kind)?;
+            self.emit(OpCode::Method, method_line);
+            self.emit_u16(constant, method_line);
+        }
+        self.emit(OpCode::Pop, line);
+
+        if decl.superclass.is_some() {
+            self.end_scope(line);
+        }
+        self.classes.pop();
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &WithSpan<Expr>) -> CompileResult<()> {
+        self.at(expr, |this| this.expression_inner(expr))
+    }
+
+    fn expression_inner(&mut self, expr: &WithSpan<Expr>) -> CompileResult<()> {
+        let line = expr.get_line();
+        match expr.get_value() {
+            Expr::Nil => self.emit(OpCode::Nil, line),
+            Expr::Boolean(true) => self.emit(OpCode::True, line),
+            Expr::Boolean(false) => self.emit(OpCode::False, line),
+            Expr::Number(v) => self.emit_constant(Value::Number(*v), line)?,
+            Expr::String(v) => {
+                let s = self.heap.alloc_string(v.as_ref());
+                self.emit_constant(Value::Obj(s), line)?;
+            }
+            Expr::Grouping(inner) => self.expression(inner)?,
+            Expr::Unary(operator, right) => {
+                self.expression(right)?;
+                let op = match operator.get_value() {
+                    UnaryOperator::Bang => OpCode::Not,
+                    UnaryOperator::Minus => OpCode::Negate,
+                };
+                self.emit(op, operator.get_line());
+            }
+            Expr::Binary(left, operator, right) => {
+                self.expression(left)?;
+                self.expression(right)?;
+                let line = operator.get_line();
+                match operator.get_value() {
+                    BinaryOperator::Plus => self.emit(OpCode::Add, line),
+                    BinaryOperator::Minus => self.emit(OpCode::Subtract, line),
+                    BinaryOperator::Star => self.emit(OpCode::Multiply, line),
+                    BinaryOperator::Slash => self.emit(OpCode::Divide, line),
+                    BinaryOperator::Greater => self.emit(OpCode::Greater, line),
+                    BinaryOperator::Less => self.emit(OpCode::Less, line),
+                    BinaryOperator::EqualEqual => self.emit(OpCode::Equal, line),
+                    BinaryOperator::GreaterEqual => {
+                        self.emit(OpCode::Less, line);
+                        self.emit(OpCode::Not, line);
+                    }
+                    BinaryOperator::LessEqual => {
+                        self.emit(OpCode::Greater, line);
+                        self.emit(OpCode::Not, line);
+                    }
+                    BinaryOperator::BangEqual => {
+                        self.emit(OpCode::Equal, line);
+                        self.emit(OpCode::Not, line);
+                    }
+                }
+            }
+            Expr::Logical(left, operator, right) => {
+                self.expression(left)?;
+                match operator.get_value() {
+                    LogicalOperator::And => {
+                        let end_jump = self.emit_jump(OpCode::JumpIfFalse, line);
+                        self.emit(OpCode::Pop, line);
+                        self.expression(right)?;
+                        self.patch_jump(end_jump, line)?;
+                    }
+                    LogicalOperator::Or => {
+                        let else_jump = self.emit_jump(OpCode::JumpIfFalse, line);
+                        let end_jump = self.emit_jump(OpCode::Jump, line);
+                        self.patch_jump(else_jump, line)?;
+                        self.emit(OpCode::Pop, line);
+                        self.expression(right)?;
+                        self.patch_jump(end_jump, line)?;
+                    }
+                }
+            }
+            Expr::Variable(name, _) => self.named_variable(name, false, line)?,
+            Expr::Assign(name, value, _) => {
+                self.expression(value)?;
+                self.named_variable(name.get_value(), true, line)?;
+            }
+            Expr::Call(callee, args) => {
+                if args.len() > u8::MAX as usize {
+                    return Err(CompileError::new(
+                        "Can't have more than 255 arguments.",
+                        line,
+                    ));
+                }
+                if let Expr::Get(object, name) = callee.get_value() {
+                    self.expression(object)?;
+                    for arg in args {
+                        self.expression(arg)?;
+                    }
+                    let constant = self.name_constant(name.get_value(), name.get_line())?;
+                    self.emit(OpCode::Invoke, line);
+                    self.emit_u16(constant, line);
+                    self.emit_byte(args.len() as u8, line);
+                } else {
+                    self.expression(callee)?;
+                    for arg in args {
+                        self.expression(arg)?;
+                    }
+                    self.emit(OpCode::Call, line);
+                    self.emit_byte(args.len() as u8, line);
+                }
+            }
+            Expr::Get(object, name) => {
+                self.expression(object)?;
+                let constant = self.name_constant(name.get_value(), name.get_line())?;
+                self.emit(OpCode::GetProperty, name.get_line());
+                self.emit_u16(constant, name.get_line());
+            }
+            Expr::Set(object, name, value) => {
+                self.expression(object)?;
+                self.expression(value)?;
+                let constant = self.name_constant(name.get_value(), name.get_line())?;
+                self.emit(OpCode::SetProperty, name.get_line());
+                self.emit_u16(constant, name.get_line());
+            }
+            Expr::List(items) => {
+                let Ok(count) = u16::try_from(items.len()) else {
+                    return Err(CompileError::new(
+                        "Too many elements in list literal.",
+                        line,
+                    ));
+                };
+                for item in items {
+                    self.expression(item)?;
+                }
+                self.emit(OpCode::BuildList, line);
+                self.emit_u16(count, line);
+            }
+            Expr::Interpolation(parts) => {
+                let Ok(count) = u16::try_from(parts.len()) else {
+                    return Err(CompileError::new(
+                        "Too many parts in interpolated string.",
+                        line,
+                    ));
+                };
+                for part in parts {
+                    self.expression(part)?;
+                }
+                self.emit(OpCode::Interpolate, line);
+                self.emit_u16(count, line);
+            }
+            Expr::Index(list, index) => {
+                self.expression(list)?;
+                self.expression(index)?;
+                self.emit(OpCode::GetIndex, line);
+            }
+            Expr::SetIndex(list, index, value) => {
+                self.expression(list)?;
+                self.expression(index)?;
+                self.expression(value)?;
+                self.emit(OpCode::SetIndex, line);
+            }
+            Expr::This(_) => {
+                if self.classes.is_empty() {
+                    return Err(CompileError::new(
+                        "Can't use 'this' outside of a class.",
+                        line,
+                    ));
+                }
+                self.named_variable("this", false, line)?;
+            }
+            Expr::Super(method, _) => {
+                if self.classes.last() != Some(&true) {
+                    return Err(CompileError::new(
+                        "Can't use 'super' in a class with no superclass.",
+                        line,
+                    ));
+                }
+                let constant = self.name_constant(method.get_value(), line)?;
+                self.named_variable("this", false, line)?;
+                self.named_variable("super", false, line)?;
+                self.emit(OpCode::GetSuper, line);
+                self.emit_u16(constant, line);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+use std::rc::Rc;
+
+use super::{
+    object::{Class, Closure, Function, ObjRef, Object},
+    value::Value,
+};
+
+/// Bytes that may be allocated before the first collection runs.
+const INITIAL_THRESHOLD: usize = 1024 * 1024;
+const GROWTH_FACTOR: usize = 2;
+
+/// Snapshot of the heap's memory usage, from [`Vm::heap_stats`](super::Vm::heap_stats)
+/// or [`Interpreter::heap_stats`](crate::Interpreter::heap_stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Objects currently alive.
+    pub live_objects: usize,
+    /// Approximate bytes owned by live objects.
+    pub live_bytes: usize,
+    /// Number of collections run so far.
+    pub collections: usize,
+    /// Total number of objects reclaimed by all collections.
+    pub freed_objects: usize,
+    /// Live bytes at which the next collection will be triggered.
+    pub next_collection: usize,
+}
+
+/// Owner of every object allocated by the compiler and the VM.
+///
+/// Objects are reclaimed by a mark-and-sweep collector: the VM marks its
+/// roots with [`Heap::mark_value`]/[`Heap::mark`] and then calls
+/// [`Heap::collect`], which traces everything reachable and frees the rest.
+pub struct Heap {
+    objects: Vec<Option<Object>>,
+    sizes: Vec<usize>,
+    marks: Vec<bool>,
+    free: Vec<u32>,
+    gray: Vec<ObjRef>,
+    bytes: usize,
+    next_collection: usize,
+    collections: usize,
+    freed: usize,
+    stress: bool,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self {
+            objects: Vec::new(),
+            sizes: Vec::new(),
+            marks: Vec::new(),
+            free: Vec::new(),
+            gray: Vec::new(),
+            bytes: 0,
+            next_collection: INITIAL_THRESHOLD,
+            collections: 0,
+            freed: 0,
+            stress: false,
+        }
+    }
+}
+
+impl Heap {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// When enabled, [`Heap::should_collect`] is always true so that the
+    /// VM collects before every allocation.
+    #[inline]
+    pub fn set_stress(&mut self, stress: bool) {
+        self.stress = stress;
+    }
+
+    #[inline]
+    pub fn is_stress(&self) -> bool {
+        self.stress
+    }
+
+    #[inline]
+    pub fn should_collect(&self) -> bool {
+        self.stress || self.bytes > self.next_collection
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        HeapStats {
+            live_objects: self.len(),
+            live_bytes: self.bytes,
+            collections: self.collections,
+            freed_objects: self.freed,
+            next_collection: self.next_collection,
+        }
+    }
+
+    pub fn alloc(&mut self, object: Object) -> ObjRef {
+        let size = object.size();
+        self.bytes += size;
+        match self.free.pop() {
+            Some(index) => {
+                self.objects[index as usize] = Some(object);
+                self.sizes[index as usize] = size;
+                ObjRef(index)
+            }
+            None => {
+                self.objects.push(Some(object));
+                self.sizes.push(size);
+                self.marks.push(false);
+                ObjRef((self.objects.len() - 1) as u32)
+            }
+        }
+    }
+
+    #[inline]
+    pub fn alloc_string<S: Into<Rc<str>>>(&mut self, s: S) -> ObjRef {
+        self.alloc(Object::String(s.into()))
+    }
+
+    #[inline]
+    pub fn get(&self, r: ObjRef) -> &Object {
+        self.objects[r.0 as usize]
+            .as_ref()
+            .expect("use of freed object")
+    }
+
+    /// Gives mutable access to an object. Callers that grow an object
+    /// should follow up with [`Heap::resize`].
+    #[inline]
+    pub fn get_mut(&mut self, r: ObjRef) -> &mut Object {
+        self.objects[r.0 as usize]
+            .as_mut()
+            .expect("use of freed object")
+    }
+
+    /// Recomputes the accounted size of an object after it was mutated.
+    pub fn resize(&mut self, r: ObjRef) {
+        let size = self.get(r).size();
+        let old = std::mem::replace(&mut self.sizes[r.0 as usize], size);
+        self.bytes = self.bytes + size - old;
+    }
+
+    /// Number of objects currently alive.
+    pub fn len(&self) -> usize {
+        self.objects.len() - self.free.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Marks `value` as a root if it refers to an object.
+    #[inline]
+    pub fn mark_value(&mut self, value: Value) {
+        if let Value::Obj(r) = value {
+            self.mark(r);
+        }
+    }
+
+    /// Marks an object as a root for the next [`Heap::collect`].
+    #[inline]
+    pub fn mark(&mut self, r: ObjRef) {
+        self.gray.push(r);
+    }
+
+    /// Traces from the marked roots and frees every unreachable object.
+    /// Returns the number of objects freed.
+    pub fn collect(&mut self) -> usize {
+        self.trace();
+        let freed = self.sweep();
+        self.collections += 1;
+        self.freed += freed;
+        self.next_collection = (self.bytes * GROWTH_FACTOR).max(INITIAL_THRESHOLD);
+        freed
+    }
+
+    fn trace(&mut self) {
+        let mut gray = std::mem::take(&mut self.gray);
+        while let Some(r) = gray.pop() {
+            let index = r.0 as usize;
+            if self.marks[index] {
+                continue;
+            }
+            self.marks[index] = true;
+            self.get(r).trace(&mut gray);
+        }
+        self.gray = gray;
+    }
+
+    fn sweep(&mut self) -> usize {
+        let mut freed = 0;
+        for (index, object) in self.objects.iter_mut().enumerate() {
+            if std::mem::take(&mut self.marks[index]) || object.is_none() {
+                continue;
+            }
+            *object = None;
+            self.bytes -= self.sizes[index];
+            self.free.push(index as u32);
+            freed += 1;
+        }
+        freed
+    }
+
+    pub fn as_string(&self, r: ObjRef) -> Option<&Rc<str>> {
+        match self.get(r) {
+            Object::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self, r: ObjRef) -> Option<&Vec<Value>> {
+        match self.get(r) {
+            Object::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_list_mut(&mut self, r: ObjRef) -> Option<&mut Vec<Value>> {
+        match self.get_mut(r) {
+            Object::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_function(&self, r: ObjRef) -> &Function {
+        match self.get(r) {
+            Object::Function(f) => f,
+            _ => unreachable!("object is not a function"),
+        }
+    }
+
+    pub fn as_closure(&self, r: ObjRef) -> &Closure {
+        match self.get(r) {
+            Object::Closure(c) => c,
+            _ => unreachable!("object is not a closure"),
+        }
+    }
+
+    pub fn as_class(&self, r: ObjRef) -> &Class {
+        match self.get(r) {
+            Object::Class(c) => c,
+            _ => unreachable!("object is not a class"),
+        }
+    }
+
+    pub fn as_class_mut(&mut self, r: ObjRef) -> &mut Class {
+        match self.get_mut(r) {
+            Object::Class(c) => c,
+            _ => unreachable!("object is not a class"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::Heap;
+    use crate::vm::{
+        object::{Instance, Object},
+        value::Value,
+    };
+
+    #[test]
+    fn test_collect_cycle() {
+        let mut heap = Heap::new();
+        let name = heap.alloc_string("kept");
+        let class = heap.alloc(Object::Class(crate::vm::object::Class {
+            name: "Node".into(),
+            methods: HashMap::new(),
+        }));
+        let a = heap.alloc(Object::Instance(Instance {
+            class,
+            fields: HashMap::new(),
+        }));
+        let b = heap.alloc(Object::Instance(Instance {
+            class,
+            fields: HashMap::new(),
+        }));
+        for (from, to) in [(a, b), (b, a)] {
+            if let Object::Instance(i) = heap.get_mut(from) {
+                i.fields.insert("next".into(), Value::Obj(to));
+            }
+            heap.resize(from);
+        }
+        assert_eq!(heap.len(), 4);
+
+        heap.mark(name);
+        assert_eq!(heap.collect(), 3);
+        assert_eq!(heap.len(), 1);
+        assert_eq!(heap.as_string(name).map(|s| s.as_ref()), Some("kept"));
+
+        let stats = heap.stats();
+        assert_eq!(stats.collections, 1);
+        assert_eq!(stats.freed_objects, 3);
+        assert_eq!(stats.live_bytes, heap.get(name).size());
+
+        // Freed slots are reused by later allocations.
+        let c = heap.alloc_string("new");
+        assert_ne!(c, name);
+        assert_eq!(heap.len(), 2);
+    }
+}
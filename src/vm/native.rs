@@ -0,0 +1,103 @@
+//! Native functions the VM defines as globals, mirroring
+//! [`builtins`](crate::builtins) in the tree-walker.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{heap::Heap, object::Object, value::Value};
+use crate::builtins::list_index;
+
+fn type_name(heap: &Heap, value: Value) -> &'static str {
+    match value {
+        Value::Nil => "nil",
+        Value::Boolean(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::Obj(r) => match heap.get(r) {
+            Object::String(_) => "string",
+            Object::Function(_)
+            | Object::Native(_)
+            | Object::Closure(_)
+            | Object::BoundMethod(_) => "function",
+            Object::Upvalue(_) => "upvalue",
+            Object::Class(_) => "class",
+            Object::Instance(_) => "instance",
+            Object::List(_) => "list",
+        },
+    }
+}
+
+fn list<'h>(heap: &'h mut Heap, name: &str, value: Value) -> Result<&'h mut Vec<Value>, String> {
+    let actual = type_name(heap, value);
+    value
+        .as_obj()
+        .and_then(|r| heap.as_list_mut(r))
+        .ok_or_else(|| format!("{name}() expects a list, got {actual}."))
+}
+
+fn number(heap: &Heap, name: &str, value: Value) -> Result<f64, String> {
+    match value {
+        Value::Number(n) => Ok(n),
+        v => Err(format!(
+            "{name}() expects a number, got {}.",
+            type_name(heap, v)
+        )),
+    }
+}
+
+pub(super) fn clock(_: &mut Heap, _: &[Value]) -> Result<Value, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?;
+    Ok(Value::Number(now.as_secs_f64()))
+}
+
+pub(super) fn len(heap: &mut Heap, args: &[Value]) -> Result<Value, String> {
+    let len = match args[0].as_obj().map(|r| heap.get(r)) {
+        Some(Object::List(items)) => items.len(),
+        Some(Object::String(s)) => s.chars().count(),
+        _ => {
+            return Err(format!(
+                "len() expects a list or string, got {}.",
+                type_name(heap, args[0])
+            ))
+        }
+    };
+    Ok(Value::Number(len as f64))
+}
+
+pub(super) fn push(heap: &mut Heap, args: &[Value]) -> Result<Value, String> {
+    list(heap, "push", args[0])?.push(args[1]);
+    resize(heap, args[0]);
+    Ok(Value::Nil)
+}
+
+pub(super) fn pop(heap: &mut Heap, args: &[Value]) -> Result<Value, String> {
+    let item = list(heap, "pop", args[0])?
+        .pop()
+        .ok_or_else(|| "Can't pop from an empty list.".to_string())?;
+    resize(heap, args[0]);
+    Ok(item)
+}
+
+pub(super) fn insert(heap: &mut Heap, args: &[Value]) -> Result<Value, String> {
+    let n = number(heap, "insert", args[1])?;
+    let items = list(heap, "insert", args[0])?;
+    let index = list_index(n, items.len(), true)?;
+    items.insert(index, args[2]);
+    resize(heap, args[0]);
+    Ok(Value::Nil)
+}
+
+pub(super) fn remove(heap: &mut Heap, args: &[Value]) -> Result<Value, String> {
+    let n = number(heap, "remove", args[1])?;
+    let items = list(heap, "remove", args[0])?;
+    let index = list_index(n, items.len(), false)?;
+    let item = items.remove(index);
+    resize(heap, args[0]);
+    Ok(item)
+}
+
+fn resize(heap: &mut Heap, list: Value) {
+    if let Some(r) = list.as_obj() {
+        heap.resize(r);
+    }
+}
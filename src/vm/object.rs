@@ -0,0 +1,108 @@
+use std::{collections::HashMap, mem, rc::Rc};
+
+use super::{chunk::Chunk, heap::Heap, value::Value};
+
+/// Handle to an object owned by the [`Heap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(pub(super) u32);
+
+/// Natives may inspect and mutate heap objects but must not allocate.
+pub type NativeFn = fn(&mut Heap, &[Value]) -> Result<Value, String>;
+
+pub enum Object {
+    String(Rc<str>),
+    Function(Function),
+    Native(Native),
+    Closure(Closure),
+    Upvalue(Upvalue),
+    Class(Class),
+    Instance(Instance),
+    BoundMethod(BoundMethod),
+    List(Vec<Value>),
+}
+
+impl Object {
+    /// Approximate number of bytes owned by this object, used to decide
+    /// when the next collection should run.
+    pub fn size(&self) -> usize {
+        let payload = match self {
+            Self::String(s) => s.len(),
+            Self::Function(f) => {
+                f.chunk.code.len() + f.chunk.constants.len() * mem::size_of::<Value>()
+            }
+            Self::Native(_) | Self::Upvalue(_) | Self::BoundMethod(_) => 0,
+            Self::Closure(c) => c.upvalues.len() * mem::size_of::<ObjRef>(),
+            Self::Class(c) => c.methods.len() * mem::size_of::<(Rc<str>, ObjRef)>(),
+            Self::Instance(i) => i.fields.len() * mem::size_of::<(Rc<str>, Value)>(),
+            Self::List(items) => items.capacity() * mem::size_of::<Value>(),
+        };
+        mem::size_of::<Self>() + payload
+    }
+
+    /// Pushes every object directly reachable from this one onto `gray`.
+    pub(super) fn trace(&self, gray: &mut Vec<ObjRef>) {
+        fn value(gray: &mut Vec<ObjRef>, v: &Value) {
+            if let Value::Obj(r) = v {
+                gray.push(*r);
+            }
+        }
+        match self {
+            Self::String(_) | Self::Native(_) | Self::Upvalue(Upvalue::Open(_)) => {}
+            Self::Function(f) => f.chunk.constants.iter().for_each(|v| value(gray, v)),
+            Self::Closure(c) => {
+                gray.push(c.function);
+                gray.extend(c.upvalues.iter().copied());
+            }
+            Self::Upvalue(Upvalue::Closed(v)) => value(gray, v),
+            Self::Class(c) => gray.extend(c.methods.values().copied()),
+            Self::Instance(i) => {
+                gray.push(i.class);
+                i.fields.values().for_each(|v| value(gray, v));
+            }
+            Self::BoundMethod(b) => {
+                value(gray, &b.receiver);
+                gray.push(b.method);
+            }
+            Self::List(items) => items.iter().for_each(|v| value(gray, v)),
+        }
+    }
+}
+
+pub struct Function {
+    pub(crate) name: Option<Rc<str>>,
+    pub(crate) arity: usize,
+    pub(crate) upvalue_count: usize,
+    pub(crate) chunk: Rc<Chunk>,
+}
+
+pub struct Native {
+    pub(crate) arity: usize,
+    pub(crate) func: NativeFn,
+}
+
+pub struct Closure {
+    pub(crate) function: ObjRef,
+    pub(crate) upvalues: Vec<ObjRef>,
+}
+
+/// A captured variable: it points into the stack while the enclosing
+/// function is running and holds the value itself once it has returned.
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct Class {
+    pub(crate) name: Rc<str>,
+    pub(crate) methods: HashMap<Rc<str>, ObjRef>,
+}
+
+pub struct Instance {
+    pub(crate) class: ObjRef,
+    pub(crate) fields: HashMap<Rc<str>, Value>,
+}
+
+pub struct BoundMethod {
+    pub(crate) receiver: Value,
+    pub(crate) method: ObjRef,
+}
@@ -0,0 +1,25 @@
+use super::object::ObjRef;
+
+/// A value on the VM stack. Heap-allocated data is referred to by handle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    Obj(ObjRef),
+}
+
+impl Value {
+    #[inline]
+    pub fn is_falsey(&self) -> bool {
+        matches!(self, Self::Nil | Self::Boolean(false))
+    }
+
+    #[inline]
+    pub fn as_obj(&self) -> Option<ObjRef> {
+        match self {
+            Self::Obj(r) => Some(*r),
+            _ => None,
+        }
+    }
+}
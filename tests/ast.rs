@@ -0,0 +1,175 @@
+use std::{borrow::Cow, collections::BTreeMap, io};
+
+use codecrafters_interpreter::{
+    ast::{
+        fold::{self, Fold},
+        visit::{self, Visitor},
+        visit_mut::{self, VisitorMut},
+        Ast, BinaryOperator, Expr, FunctionDecl, Stmt,
+    },
+    Lexer, Parser, Token, WithSpan,
+};
+
+const SOURCE: &str = r#"
+class Base { greet() { return "hi"; } }
+class Child < Base {
+    init(n) { this.n = n * (2 + 3); }
+    greet() { return "${super.greet()} ${this.n}"; }
+}
+fun count(limit) {
+    var total = 0;
+    for (var i = 0; i < limit; i = i + 1) {
+        if (i > 1 and total < 100) total = total + i; else total = total - 1;
+    }
+    return [total, -total][0];
+}
+print Child(count(1 + 4)).greet();
+"#;
+
+fn tokenize(source: &str) -> Vec<WithSpan<Token<'_>>> {
+    Lexer::from(source).collect()
+}
+
+fn parse<'a>(tokens: &'a [WithSpan<Token<'a>>]) -> Ast<'a> {
+    Parser::<io::Sink>::new(tokens, None)
+        .parse_program()
+        .unwrap()
+}
+
+fn print(ast: &Ast) -> Vec<String> {
+    ast.iter()
+        .map(|stmt| stmt.get_value().to_string())
+        .collect()
+}
+
+/// Counts how often each variable is read, and the functions visited.
+#[derive(Default)]
+struct Uses {
+    reads: BTreeMap<String, usize>,
+    functions: Vec<String>,
+}
+
+impl<'a> Visitor<'a> for Uses {
+    fn visit_expr(&mut self, expr: &WithSpan<Expr<'a>>) {
+        if let Expr::Variable(name, _) = expr.get_value() {
+            *self.reads.entry(name.to_string()).or_default() += 1;
+        }
+        visit::walk_expr(self, expr);
+    }
+
+    fn visit_function(&mut self, decl: &FunctionDecl<'a>) {
+        self.functions.push(decl.name.get_value().to_string());
+        visit::walk_function(self, decl);
+    }
+}
+
+#[test]
+fn test_ast_visitor() {
+    let tokens = tokenize(SOURCE);
+    let ast = parse(&tokens);
+    let mut uses = Uses::default();
+    for stmt in &ast {
+        uses.visit_stmt(stmt);
+    }
+    assert_eq!(uses.functions, ["greet", "init", "greet", "count"]);
+    assert_eq!(
+        uses.reads.into_iter().collect::<Vec<_>>(),
+        [
+            ("Base".to_string(), 1),
+            ("Child".to_string(), 1),
+            ("count".to_string(), 1),
+            ("i".to_string(), 4),
+            ("limit".to_string(), 1),
+            ("n".to_string(), 1),
+            ("total".to_string(), 5),
+        ]
+    );
+}
+
+/// Renames a variable everywhere it is read or assigned.
+struct Rename(&'static str, &'static str);
+
+impl<'a> VisitorMut<'a> for Rename {
+    fn visit_expr_mut(&mut self, expr: &mut WithSpan<Expr<'a>>) {
+        match expr.get_value_mut() {
+            Expr::Variable(name, _) if name == self.0 => *name = Cow::Borrowed(self.1),
+            Expr::Assign(name, _, _) if name.get_value() == self.0 => {
+                *name.get_value_mut() = Cow::Borrowed(self.1);
+            }
+            _ => {}
+        }
+        visit_mut::walk_expr(self, expr);
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut WithSpan<Stmt<'a>>) {
+        if let Stmt::Var(name, _) = stmt.get_value_mut() {
+            if name.get_value() == self.0 {
+                *name.get_value_mut() = Cow::Borrowed(self.1);
+            }
+        }
+        visit_mut::walk_stmt(self, stmt);
+    }
+}
+
+#[test]
+fn test_ast_visitor_mut() {
+    let tokens = tokenize(SOURCE);
+    let mut ast = parse(&tokens);
+    for stmt in &mut ast {
+        Rename("total", "sum").visit_stmt_mut(stmt);
+    }
+    let printed = print(&ast).join("\n");
+    assert!(!printed.contains("total"), "{printed}");
+    assert!(printed.contains("(var sum 0.0)"), "{printed}");
+    assert!(
+        printed.contains("(return (index (list sum (- sum)) 0.0))"),
+        "{printed}"
+    );
+}
+
+/// Folds arithmetic on number literals into a single literal.
+struct ConstantFold;
+
+impl<'a> Fold<'a> for ConstantFold {
+    fn fold_expr(&mut self, expr: WithSpan<Expr<'a>>) -> WithSpan<Expr<'a>> {
+        let expr = fold::walk_expr(self, expr);
+        let folded = match expr.get_value() {
+            Expr::Grouping(inner) => match inner.get_value() {
+                Expr::Number(n) => Some(*n),
+                _ => None,
+            },
+            Expr::Binary(left, operator, right) => {
+                match (left.get_value(), operator.get_value(), right.get_value()) {
+                    (Expr::Number(a), BinaryOperator::Plus, Expr::Number(b)) => Some(a + b),
+                    (Expr::Number(a), BinaryOperator::Star, Expr::Number(b)) => Some(a * b),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        match folded {
+            Some(n) => expr.map(|_| Expr::Number(n)),
+            None => expr,
+        }
+    }
+}
+
+#[test]
+fn test_ast_fold() {
+    let tokens = tokenize("print (1 + 2) * 3 + x; fun f() { return [2 * (3 + 4)]; }");
+    let ast = parse(&tokens);
+    let span = ast[0].get_span();
+    let ast = ast
+        .into_iter()
+        .map(|stmt| ConstantFold.fold_stmt(stmt))
+        .collect::<Ast>();
+    assert_eq!(ast[0].get_span(), span);
+    assert_eq!(
+        print(&ast),
+        ["(print (+ 9.0 x))", "(fun f () (return (list 14.0)))"]
+    );
+    let Stmt::Print(expr) = ast[0].get_value() else {
+        panic!("expected a print statement");
+    };
+    assert!(matches!(expr.get_value(), Expr::Binary(..)));
+}
@@ -0,0 +1,64 @@
+//! The harness shared by the integration tests: runs the interpreter
+//! binary on sources written to temporary files.
+
+// Every test crate compiles its own copy and uses only part of it.
+#![allow(dead_code)]
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::{self, Command},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, PartialEq)]
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+    pub code: i32,
+}
+
+impl From<process::Output> for Output {
+    fn from(output: process::Output) -> Self {
+        Self {
+            stdout: String::from_utf8(output.stdout).unwrap(),
+            stderr: String::from_utf8(output.stderr).unwrap(),
+            code: output.status.code().unwrap(),
+        }
+    }
+}
+
+/// A path in the temporary directory that no other test uses.
+pub fn temp_path(suffix: &str) -> PathBuf {
+    env::temp_dir().join(format!(
+        "lox-test-{}-{}{suffix}",
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    ))
+}
+
+/// The interpreter binary, run without a `LOXPATH`.
+pub fn lox() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter"));
+    command.env_remove("LOXPATH");
+    command
+}
+
+/// Runs `lox <command> <flags> <file>` with `source` written to the file.
+pub fn command(name: &str, flags: &[&str], source: &str) -> Output {
+    let path = temp_path(".lox");
+    fs::write(&path, source).unwrap();
+    let output = lox().arg(name).args(flags).arg(&path).output().unwrap();
+    fs::remove_file(&path).ok();
+    output.into()
+}
+
+pub fn run(source: &str) -> Output {
+    run_with(&[], source)
+}
+
+pub fn run_with(flags: &[&str], source: &str) -> Output {
+    command("run", flags, source)
+}
@@ -0,0 +1,161 @@
+use std::io;
+
+use codecrafters_interpreter::{
+    Interpreter, Lexer, LoxError, Parser, Resolver, SourceMap, TypeError, Value, WithSpan,
+};
+
+fn output(lox: Interpreter<Vec<u8>>) -> String {
+    String::from_utf8(lox.into_output()).unwrap()
+}
+
+#[test]
+fn test_embed_eval_str() {
+    let mut lox = Interpreter::with_output(Vec::new());
+    assert_eq!(lox.eval_str("1 + 2").unwrap(), Value::Number(3.0));
+    assert_eq!(
+        lox.eval_str("var greeting = \"hi\"; print greeting;")
+            .unwrap(),
+        Value::Nil
+    );
+    assert_eq!(
+        lox.eval_str("greeting + \"!\"").unwrap(),
+        Value::from("hi!")
+    );
+    assert_eq!(output(lox), "hi\n");
+}
+
+#[test]
+fn test_embed_interpret_borrowed() {
+    let mut lox = Interpreter::with_output(Vec::new());
+    {
+        let source = String::from(
+            "var n = 0;\n\
+             for (var i = 0; i < 2; i = i + 1) { fun add(x) { return x + i; } n = add(n); }\n\
+             class Counter { init(n) { this.n = n; } get() { return this.n; } }\n\
+             fun counter() { return Counter(n).get(); }\n",
+        );
+        let tokens = Lexer::from(source.as_str()).collect::<Vec<WithSpan<_>>>();
+        let mut ast = Parser::<io::Sink>::new(&tokens, None)
+            .parse_program()
+            .unwrap();
+        Resolver::<io::Sink>::new(None).resolve(&mut ast).unwrap();
+        lox.interpret(&ast).unwrap();
+        lox.interpret(&ast).unwrap();
+    }
+    // The functions outlive the source they were declared in.
+    assert_eq!(
+        lox.call_function("counter", &[]).unwrap(),
+        Value::Number(1.0)
+    );
+}
+
+#[test]
+fn test_embed_errors() {
+    let mut lox = Interpreter::with_output(Vec::new());
+    assert!(matches!(
+        lox.eval_str("print (1;"),
+        Err(LoxError::Syntax(_))
+    ));
+    assert!(matches!(
+        lox.eval_str("{ var a = a; }"),
+        Err(LoxError::Resolve(_))
+    ));
+    let error = lox.eval_str("print -nil;").unwrap_err();
+    assert!(matches!(error, LoxError::Runtime(_)));
+    assert_eq!(error.message(), "Operand must be a number.");
+    let error = lox.call_function("missing", &[]).unwrap_err();
+    assert_eq!(error.message(), "Undefined variable 'missing'.");
+}
+
+#[test]
+fn test_embed_stack_trace() {
+    let mut lox = Interpreter::with_output(Vec::new());
+    let source = "class A {
+  init() { fail(); }
+}
+fun fail() { return -nil; }
+A();";
+    let LoxError::Runtime(error) = lox.eval_str(source).unwrap_err() else {
+        panic!("expected a runtime error");
+    };
+    let trace = error
+        .trace()
+        .iter()
+        .map(|frame| {
+            let site = frame.call_site();
+            (frame.function(), site.get_line(), &source[site.get_span()])
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(trace, [("fail", 2, "fail()"), ("init", 5, "A()")]);
+    assert_eq!(
+        error.traceback(),
+        "[line 4] in fail()\n[line 2] in init()\n[line 5] in script"
+    );
+    // Files missing from the map fall back to bare lines.
+    assert_eq!(error.traceback_in(&SourceMap::new()), error.traceback());
+
+    let error = lox.eval_str("print -nil;").unwrap_err();
+    let LoxError::Runtime(error) = error else {
+        panic!("expected a runtime error");
+    };
+    assert!(error.trace().is_empty());
+}
+
+#[test]
+fn test_embed_globals_and_calls() {
+    let mut lox = Interpreter::with_output(Vec::new());
+    lox.set_global("limit", 3.0);
+    lox.eval_str(
+        r#"
+fun clamp(x) {
+    if (x > limit) return limit;
+    return x;
+}
+class Pair {
+    init(a, b) { this.a = a; this.b = b; }
+}
+var calls = 0;
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        lox.call_function("clamp", &[Value::from(10.0)]).unwrap(),
+        Value::Number(3.0)
+    );
+    let pair = lox
+        .call_function("Pair", &[Value::from(1.0), Value::from(true)])
+        .unwrap();
+    assert_eq!(pair.to_string(), "Pair instance");
+    assert_eq!(lox.get_global("calls"), Some(Value::Number(0.0)));
+    assert_eq!(lox.get_global("nope"), None);
+    let error = lox.call_function("clamp", &[]).unwrap_err();
+    assert_eq!(error.message(), "Expected 1 arguments but got 0.");
+}
+
+#[test]
+fn test_embed_register_native() {
+    let mut lox = Interpreter::with_output(Vec::new());
+    lox.register_native("repeat", 2, |args| {
+        let s = String::try_from(&args[0])?;
+        let n = f64::try_from(&args[1])?;
+        Ok(Value::from(s.repeat(n as usize)))
+    });
+    lox.register_native("not", 1, |args| Ok(Value::from(!bool::try_from(&args[0])?)));
+    lox.eval_str("print repeat(\"ab\", 3);\nprint not(false);")
+        .unwrap();
+    let error = lox.eval_str("\nrepeat(1, 2);").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Expected string but got number.\n[line 2]"
+    );
+    assert_eq!(output(lox), "ababab\ntrue\n");
+
+    assert_eq!(f64::try_from(Value::Number(1.5)), Ok(1.5));
+    assert_eq!(
+        bool::try_from(Value::Nil),
+        Err(TypeError {
+            expected: "boolean",
+            found: "nil"
+        })
+    );
+}
@@ -0,0 +1,79 @@
+use std::io;
+
+use codecrafters_interpreter::{Interpreter, Lexer, Parser, RuntimeError, Token, Value, WithSpan};
+
+fn evaluate_err(input: &str) -> RuntimeError {
+    let tokens = Lexer::from(input).collect::<Vec<WithSpan<Token>>>();
+    let expr = Parser::<io::Sink>::new(&tokens, None).parse().unwrap();
+    Interpreter::new().evaluate(&expr).unwrap_err()
+}
+
+fn evaluate(input: &str) -> Result<Value, String> {
+    let tokens = Lexer::from(input).collect::<Vec<WithSpan<Token>>>();
+    let mut parser = Parser::<io::Sink>::new(&tokens, None);
+    let expr = parser.parse().map_err(|e| e.to_string())?;
+    Interpreter::new()
+        .evaluate(&expr)
+        .map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_evaluate_literals() {
+    assert_eq!(evaluate("nil").unwrap(), Value::Nil);
+    assert_eq!(evaluate("true").unwrap(), Value::Boolean(true));
+    assert_eq!(evaluate("12.5").unwrap(), Value::Number(12.5));
+    assert_eq!(evaluate(r#""hello""#).unwrap(), Value::from("hello"));
+}
+
+#[test]
+fn test_evaluate_arithmetic() {
+    assert_eq!(evaluate("1 + 2 * 3").unwrap(), Value::Number(7.0));
+    assert_eq!(evaluate("(1 + 2) * 3").unwrap(), Value::Number(9.0));
+    assert_eq!(evaluate("10 / 4 - -1").unwrap(), Value::Number(3.5));
+    assert_eq!(evaluate(r#""foo" + "bar""#).unwrap(), Value::from("foobar"));
+    assert_eq!(evaluate("10 / 4").unwrap().to_string(), "2.5");
+    assert_eq!(evaluate("20 / 4").unwrap().to_string(), "5");
+}
+
+#[test]
+fn test_evaluate_truthiness_and_equality() {
+    assert_eq!(evaluate("!nil").unwrap(), Value::Boolean(true));
+    assert_eq!(evaluate("!0").unwrap(), Value::Boolean(false));
+    assert_eq!(evaluate(r#"!"""#).unwrap(), Value::Boolean(false));
+    assert_eq!(evaluate("nil == nil").unwrap(), Value::Boolean(true));
+    assert_eq!(evaluate(r#"1 == "1""#).unwrap(), Value::Boolean(false));
+    assert_eq!(evaluate(r#""a" != "a""#).unwrap(), Value::Boolean(false));
+    assert_eq!(evaluate("2 >= 3").unwrap(), Value::Boolean(false));
+}
+
+#[test]
+fn test_evaluate_runtime_errors() {
+    assert_eq!(
+        evaluate(r#"-"a""#).unwrap_err(),
+        "Operand must be a number.\n[line 1]"
+    );
+    assert_eq!(
+        evaluate("1 + true").unwrap_err(),
+        "Operands must be two numbers or two strings.\n[line 1]"
+    );
+    assert_eq!(
+        evaluate("\n\n1 <\n nil").unwrap_err(),
+        "Operands must be numbers.\n[line 3]"
+    );
+}
+
+#[test]
+fn test_evaluate_lists() {
+    assert_eq!(evaluate("[1, 2, 3][1]").unwrap(), Value::Number(2.0));
+    assert_eq!(evaluate("[[1], [2, 3]][1][0]").unwrap(), Value::Number(2.0));
+    assert_eq!(
+        evaluate(r#"[1, "a", nil]"#).unwrap().to_string(),
+        "[1, a, nil]"
+    );
+    assert_eq!(evaluate("len([1, 2])").unwrap(), Value::Number(2.0));
+
+    let error = evaluate_err("[1, 2][1 + 1]");
+    assert_eq!(error.message(), "List index 2 out of range for length 2.");
+    assert_eq!(error.span(), Some(7..12));
+    assert_eq!(evaluate_err("nil[0]").span(), Some(0..3));
+}
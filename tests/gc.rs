@@ -0,0 +1,148 @@
+use std::io;
+
+use codecrafters_interpreter::{
+    vm::{Value, Vm},
+    Interpreter, Lexer, Parser, Resolver, Token, WithSpan,
+};
+
+fn run(vm: &mut Vm, source: &str) {
+    let tokens = Lexer::from(source).collect::<Vec<WithSpan<Token>>>();
+    let mut ast = Parser::<io::Sink>::new(&tokens, None)
+        .parse_program()
+        .unwrap();
+    Resolver::<io::Sink>::new(None).resolve(&mut ast).unwrap();
+    vm.interpret(&ast).unwrap();
+}
+
+const CYCLES: &str = r#"
+class Node {
+    init(value) {
+        this.value = value;
+        this.next = nil;
+        this.callback = this.get;
+    }
+    get() { return this.value; }
+}
+fun makeRing(n) {
+    var first = Node(0);
+    var last = first;
+    for (var i = 1; i < n; i = i + 1) {
+        var node = Node(i);
+        last.next = node;
+        last = node;
+    }
+    last.next = first;
+    return first;
+}
+var total = 0;
+for (var i = 0; i < 50; i = i + 1) {
+    var ring = makeRing(10);
+    total = total + ring.next.callback();
+}
+"#;
+
+const STRESS: &str = r#"
+fun makeCounter() {
+    var count = 0;
+    fun increment() {
+        count = count + 1;
+        return count;
+    }
+    return increment;
+}
+class Base {
+    greet(name) { return "hello " + name; }
+}
+class Derived < Base {
+    greet(name) { return super.greet(name) + "!"; }
+}
+var counter = makeCounter();
+var greeting;
+for (var i = 0; i < 20; i = i + 1) {
+    counter();
+    greeting = Derived().greet("x" + "y");
+}
+"#;
+
+#[test]
+fn test_gc_collects_cycles() {
+    let mut vm = Vm::new();
+    run(&mut vm, CYCLES);
+    let before = vm.heap_stats().live_objects;
+    let freed = vm.collect_garbage();
+    let stats = vm.heap_stats();
+    assert!(freed > 0);
+    assert_eq!(stats.live_objects, before - freed);
+    assert_eq!(stats.collections, 1);
+
+    // Only the program's globals and whatever they reference survive.
+    let again = Vm::new().heap_stats().live_objects;
+    assert!(stats.live_objects < again + 64, "{stats:?}");
+}
+
+#[test]
+fn test_gc_stress() {
+    let mut vm = Vm::new();
+    vm.set_gc_stress(true);
+    run(&mut vm, STRESS);
+    run(&mut vm, CYCLES);
+    let stats = vm.heap_stats();
+    assert!(stats.collections > 1000, "{stats:?}");
+    assert!(stats.freed_objects > 0);
+
+    run(&mut vm, "var result = counter() + 100;");
+    assert!(vm.heap_stats().collections > stats.collections);
+    assert_eq!(vm.global("result"), Some(Value::Number(121.0)));
+    let greeting = vm.global("greeting").unwrap();
+    assert_eq!(vm.format_value(greeting), "hello xy!");
+}
+
+#[test]
+fn test_gc_tree_heap_stats() {
+    let mut lox = Interpreter::with_output(Vec::new());
+    lox.eval_str("class Point {}\nvar kept = [Point(), Point()];\nfor (var i = 0; i < 2000; i = i + 1) Point();")
+        .unwrap();
+    let stats = lox.heap_stats();
+    assert_eq!(stats.live_objects, 3);
+    assert_eq!(stats.freed_objects, 2000);
+    assert!(stats.live_bytes > 0);
+    assert_eq!(stats.collections, 0);
+
+    // The rings outlive the program until a collection runs.
+    lox.eval_str(CYCLES).unwrap();
+    assert_eq!(lox.heap_stats().live_objects, 3 + 50 * 10);
+    assert_eq!(lox.collect_garbage(), 50 * 10);
+    let stats = lox.heap_stats();
+    assert_eq!(stats.live_objects, 3);
+    assert_eq!(stats.freed_objects, 2000 + 50 * 10);
+    assert_eq!(stats.collections, 1);
+    assert_eq!(
+        lox.eval_str("kept[1]").unwrap().to_string(),
+        "Point instance"
+    );
+}
+
+#[test]
+fn test_gc_tree_collects_cycles() {
+    let mut lox = Interpreter::with_output(Vec::new());
+    lox.eval_str(&CYCLES.replace("i < 50", "i < 5000")).unwrap();
+    let stats = lox.heap_stats();
+    assert!(stats.collections > 0, "{stats:?}");
+    assert!(stats.live_objects < 50 * 1000, "{stats:?}");
+    assert_eq!(lox.get_global("total").unwrap().to_string(), "5000");
+}
+
+#[test]
+fn test_gc_tree_stress() {
+    let mut lox = Interpreter::with_output(Vec::new());
+    lox.set_gc_stress(true);
+    lox.eval_str(STRESS).unwrap();
+    lox.eval_str(CYCLES).unwrap();
+    let stats = lox.heap_stats();
+    assert!(stats.collections > 500, "{stats:?}");
+    assert!(stats.freed_objects > 0);
+
+    lox.eval_str("var result = counter() + 100;").unwrap();
+    assert_eq!(lox.get_global("result").unwrap().to_string(), "121");
+    assert_eq!(lox.get_global("greeting").unwrap().to_string(), "hello xy!");
+}
@@ -0,0 +1,197 @@
+mod common;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use codecrafters_interpreter::{Interpreter, LoxError};
+use common::{lox, temp_path, Output};
+
+/// Writes `files` into a fresh directory and returns its path.
+fn project(files: &[(&str, &str)]) -> PathBuf {
+    let dir = temp_path("");
+    for (name, source) in files {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+    }
+    dir
+}
+
+fn run(main: &Path, loxpath: Option<&Path>) -> Output {
+    let mut command = lox();
+    command.arg("run").arg(main);
+    if let Some(loxpath) = loxpath {
+        command.env("LOXPATH", loxpath);
+    }
+    command.output().unwrap().into()
+}
+
+#[test]
+fn test_import_namespace_and_cache() {
+    let dir = project(&[
+        (
+            "main.lox",
+            r#"
+import "lib/counter.lox";
+import "lib/counter.lox" as again;
+print counter.count;
+counter.increment();
+counter.increment();
+print again.count;
+print counter == again;
+print counter;
+print counter.Point(1, 2).sum();
+"#,
+        ),
+        (
+            "lib/counter.lox",
+            r#"
+import "point.lox";
+print "loading counter";
+var count = 0;
+fun increment() { count = count + 1; }
+var Point = point.Point;
+"#,
+        ),
+        (
+            "lib/point.lox",
+            r#"
+class Point {
+    init(x, y) { this.x = x; this.y = y; }
+    sum() { return this.x + this.y; }
+}
+"#,
+        ),
+    ]);
+    let out = run(&dir.join("main.lox"), None);
+    assert_eq!(out.stderr, "");
+    assert_eq!(
+        out.stdout,
+        "loading counter\n0\n2\ntrue\n<module counter>\n3\n"
+    );
+    assert_eq!(out.code, 0);
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn test_import_globals_are_per_module() {
+    let dir = project(&[
+        (
+            "main.lox",
+            "var name = \"main\";\nimport \"a.lox\";\nprint a.get();\nprint name;\n",
+        ),
+        ("a.lox", "var name = \"a\";\nfun get() { return name; }\n"),
+    ]);
+    let out = run(&dir.join("main.lox"), None);
+    assert_eq!(out.stdout, "a\nmain\n");
+    assert_eq!(out.code, 0);
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn test_import_loxpath() {
+    let lib = project(&[("strings.lox", "fun greet(who) { return \"hi \" + who; }\n")]);
+    let dir = project(&[(
+        "main.lox",
+        "import \"strings.lox\" as s;\nprint s.greet(\"you\");\n",
+    )]);
+    let out = run(&dir.join("main.lox"), Some(&lib));
+    assert_eq!(out.stdout, "hi you\n");
+    assert_eq!(out.code, 0);
+
+    let out = run(&dir.join("main.lox"), None);
+    assert_eq!(out.stderr, "Can't find module \"strings.lox\".\n[line 1]\n");
+    assert_eq!(out.code, 70);
+    fs::remove_dir_all(dir).ok();
+    fs::remove_dir_all(lib).ok();
+}
+
+#[test]
+fn test_import_cycle() {
+    let dir = project(&[
+        ("main.lox", "import \"a.lox\";\n"),
+        ("a.lox", "import \"b.lox\";\n"),
+        ("b.lox", "\n\nimport \"main.lox\";\n"),
+    ]);
+    let main = dir.join("main.lox");
+    let out = run(&main, None);
+    assert_eq!(
+        out.stderr,
+        format!(
+            "Circular import: {} -> {} -> {} -> {}.\n[line 3]\n",
+            main.display(),
+            dir.join("a.lox").display(),
+            dir.join("b.lox").display(),
+            main.display()
+        )
+    );
+    assert_eq!(out.code, 70);
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn test_import_errors() {
+    let dir = project(&[
+        ("main.lox", "import \"a.lox\";\nprint a.missing;\n"),
+        ("bad.lox", "import \"broken.lox\";\n"),
+        ("broken.lox", "var x = ;\n"),
+        ("dash.lox", "import \"my-lib.lox\";\n"),
+        ("a.lox", "var hidden;\n{ var local = 1; }\n"),
+    ]);
+    let out = run(&dir.join("main.lox"), None);
+    assert_eq!(
+        out.stderr,
+        "Module 'a' has no member 'missing'.\n[line 2]\n"
+    );
+    assert_eq!(out.code, 70);
+
+    let out = run(&dir.join("bad.lox"), None);
+    assert_eq!(
+        out.stderr,
+        format!(
+            "Error in module \"{}\": [line 1] Error at ';': Expect expression.\n[line 1]\n",
+            dir.join("broken.lox").display()
+        )
+    );
+    assert_eq!(out.code, 70);
+
+    let out = run(&dir.join("dash.lox"), None);
+    assert_eq!(
+        out.stderr,
+        "[line 1] Error at '\"my-lib.lox\"': Module file name is not an identifier; name it with 'as'.\n"
+    );
+    assert_eq!(out.code, 65);
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn test_import_error_locations() {
+    let dir = project(&[
+        ("main.lox", ""),
+        ("lib.lox", "fun fail(x) {\n  return -x;\n}\n"),
+    ]);
+    let mut lox = Interpreter::with_output(Vec::new()).with_path(dir.join("main.lox"));
+    let error = lox
+        .eval_str("import \"lib.lox\";\nlib.fail(nil);")
+        .unwrap_err();
+    let LoxError::Runtime(error) = error else {
+        panic!("{error:?}");
+    };
+    let sources = lox.sources();
+    let lib = dir.join("lib.lox").display().to_string();
+    assert_eq!(sources.get(error.file()).unwrap().name(), lib);
+    assert_eq!(
+        sources
+            .locate(error.trace()[0].call_site())
+            .unwrap()
+            .to_string(),
+        "<eval>:2:1"
+    );
+    assert_eq!(
+        error.traceback_in(sources),
+        format!("[{lib}:2:10] in fail()\n[<eval>:2:1] in script")
+    );
+    fs::remove_dir_all(dir).ok();
+}
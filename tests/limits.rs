@@ -0,0 +1,212 @@
+use std::{io, thread, time::Duration};
+
+use codecrafters_interpreter::{
+    ast::{Expr, Stmt},
+    vm::{Vm, VmError},
+    Interpreter, Lexer, Limit, Limits, LoxError, Parser, ResolveError, Resolver, RuntimeError,
+    WithSpan, MAX_NESTING,
+};
+
+fn limited(limits: Limits, source: &str) -> LoxError {
+    Interpreter::with_output(Vec::new())
+        .with_limits(limits)
+        .eval_str(source)
+        .unwrap_err()
+}
+
+fn vm_limited(vm: &mut Vm, source: &str) -> RuntimeError {
+    let tokens = Lexer::from(source).collect::<Vec<_>>();
+    let mut ast = Parser::<io::Sink>::new(&tokens, None)
+        .parse_program()
+        .unwrap();
+    Resolver::<io::Sink>::new(None).resolve(&mut ast).unwrap();
+    match vm.interpret(&ast) {
+        Err(VmError::Runtime(error)) => error,
+        result => panic!("expected a runtime error, got {result:?}"),
+    }
+}
+
+#[test]
+fn test_limits_steps() {
+    let error = limited(Limits::new().max_steps(1000), "while (true) {}");
+    assert!(matches!(error, LoxError::Limit(_)));
+    assert_eq!(error.message(), "Step limit exceeded.");
+    assert_eq!(error.code(), "E0302");
+
+    // The budget starts afresh with each call.
+    let mut lox = Interpreter::with_output(Vec::new()).with_limits(Limits::new().max_steps(50));
+    for _ in 0..10 {
+        lox.eval_str("var a = 1 + 2 * 3;").unwrap();
+    }
+}
+
+#[test]
+fn test_limits_depth() {
+    let error = limited(Limits::default(), "fun f(n) { return f(n + 1); }\nf(0);");
+    let LoxError::Limit(e) = &error else {
+        panic!("{error:?}");
+    };
+    assert_eq!(e.limit(), Some(Limit::Depth));
+    assert_eq!(error.to_string(), "Stack overflow.\n[line 1]");
+
+    let source = "fun f(n) { if (n == 0) return 0; return f(n - 1) + 1; }\n";
+    let mut lox = Interpreter::with_output(Vec::new()).with_limits(Limits::new().max_depth(10));
+    lox.eval_str(source).unwrap();
+    assert!(lox.call_function("f", &[9.0.into()]).is_ok());
+    let error = lox.call_function("f", &[10.0.into()]).unwrap_err();
+    assert!(matches!(error, LoxError::Limit(e) if e.limit() == Some(Limit::Depth)));
+    // The depth is unwound after the error.
+    assert!(lox.call_function("f", &[9.0.into()]).is_ok());
+}
+
+#[test]
+fn test_limits_nesting() {
+    let mut lox = Interpreter::with_output(Vec::new());
+    let source = format!("{}1{}", "(".repeat(20000), ")".repeat(20000));
+    let error = lox.eval_str(&source).unwrap_err();
+    assert!(matches!(error, LoxError::Syntax(_)));
+    assert_eq!(error.code(), "E0109");
+    assert_eq!(
+        error.to_string(),
+        "[line 1] Error at '(': Too much nesting."
+    );
+
+    let chain = format!("var a = 0{};", " + 1".repeat(20000));
+    assert_eq!(lox.eval_str(&chain).unwrap_err().code(), "E0109");
+    let shallow = format!("{}1{}", "(".repeat(500), ")".repeat(500));
+    assert_eq!(lox.eval_str(&shallow).unwrap(), 1.0.into());
+
+    // Trees built by hand are checked when they are resolved and run.
+    let mut expr = WithSpan::new(Expr::Number(1.0), 1, 0..1);
+    for _ in 0..MAX_NESTING {
+        expr = WithSpan::new(Expr::Grouping(Box::new(expr)), 1, 0..1);
+    }
+    let error = lox.evaluate(&expr).unwrap_err();
+    assert_eq!(error.limit(), Some(Limit::Depth));
+    let mut ast = vec![WithSpan::new(Stmt::Print(Box::new(expr)), 1, 0..1)];
+    let error = Resolver::<io::Sink>::new(None)
+        .resolve(&mut ast)
+        .unwrap_err();
+    assert!(
+        matches!(error, LoxError::Resolve(ref e) if *e.get_value() == ResolveError::TooMuchNesting)
+    );
+    assert_eq!(error.to_string(), "[line 1] Error: Too much nesting.");
+}
+
+#[test]
+fn test_limits_allocation() {
+    let source = r#"
+var s = "x";
+while (true) s = s + s;
+"#;
+    let error = limited(Limits::new().max_allocation(1 << 20), source);
+    assert!(matches!(error, LoxError::Limit(ref e) if e.limit() == Some(Limit::Allocation)));
+    assert_eq!(error.to_string(), "Allocation limit exceeded.\n[line 3]");
+
+    let source = "var l = [];\nwhile (true) push(l, nil);";
+    let error = limited(Limits::new().max_allocation(1 << 16), source);
+    assert_eq!(error.message(), "Allocation limit exceeded.");
+
+    // Only what the program keeps counts, cycles included.
+    let mut lox =
+        Interpreter::with_output(Vec::new()).with_limits(Limits::new().max_allocation(1 << 16));
+    let source = "class Node { init() { this.next = this; } }
+for (var i = 0; i < 20000; i = i + 1) { var s = \"ab\" + \"cd\"; var l = [s, Node()]; }";
+    lox.eval_str(source).unwrap();
+    let stats = lox.heap_stats();
+    assert!(stats.collections > 0, "{stats:?}");
+    assert!(stats.live_bytes <= 1 << 16, "{stats:?}");
+
+    // The limit carries over between calls.
+    lox.eval_str("var kept = []; for (var i = 0; i < 1000; i = i + 1) push(kept, \"x\");")
+        .unwrap();
+    let error = lox.eval_str("while (true) push(kept, nil);").unwrap_err();
+    assert_eq!(error.message(), "Allocation limit exceeded.");
+}
+
+#[test]
+fn test_limits_timeout() {
+    let error = limited(
+        Limits::new().timeout(Duration::from_millis(50)),
+        "while (true) {}",
+    );
+    assert!(matches!(error, LoxError::Limit(ref e) if e.limit() == Some(Limit::Time)));
+    assert_eq!(error.message(), "Time limit exceeded.");
+}
+
+#[test]
+fn test_limits_interrupt() {
+    let mut lox = Interpreter::with_output(Vec::new());
+    let handle = lox.interrupt_handle();
+    let interrupter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        handle.interrupt();
+    });
+    let error = lox.eval_str("while (true) {}").unwrap_err();
+    interrupter.join().unwrap();
+    assert!(matches!(error, LoxError::Limit(ref e) if e.limit() == Some(Limit::Interrupted)));
+    assert_eq!(error.message(), "Interrupted.");
+
+    // The interrupt is consumed, so the interpreter can be used again.
+    assert_eq!(lox.eval_str("1 + 1").unwrap(), 2.0.into());
+
+    // One raised between runs does not stop the next run.
+    lox.interrupt_handle().interrupt();
+    lox.eval_str("fun f() { return 1; }").unwrap();
+    lox.interrupt_handle().interrupt();
+    assert_eq!(lox.call_function("f", &[]).unwrap(), 1.0.into());
+}
+
+#[test]
+fn test_limits_drop_deep_chains() {
+    let mut lox = Interpreter::with_output(Vec::new());
+    lox.eval_str(
+        "class N { init(n) { this.next = n; } }
+         var l = nil;
+         for (var i = 0; i < 100000; i = i + 1) l = N(l);
+         l = nil;
+         for (var i = 0; i < 100000; i = i + 1) l = [l];
+         l = nil;
+         print \"freed\";",
+    )
+    .unwrap();
+    assert_eq!(lox.into_output(), b"freed\n");
+}
+
+#[test]
+fn test_limits_vm() {
+    let mut vm = Vm::new().with_limits(Limits::new().max_steps(1000));
+    let error = vm_limited(&mut vm, "while (true) {}");
+    assert_eq!(error.limit(), Some(Limit::Steps));
+    assert_eq!(error.to_string(), "Step limit exceeded.\n[line 1]");
+
+    let mut vm = Vm::new().with_limits(Limits::new().max_depth(10));
+    let error = vm_limited(&mut vm, "fun f(n) { return f(n + 1); }\nf(0);");
+    assert_eq!(error.limit(), Some(Limit::Depth));
+    assert_eq!(error.trace().len(), 10);
+
+    let mut vm = Vm::new().with_limits(Limits::new().max_allocation(1 << 20));
+    let error = vm_limited(&mut vm, "var s = \"x\";\nwhile (true) s = s + s;");
+    assert_eq!(error.limit(), Some(Limit::Allocation));
+    let error = vm_limited(
+        &mut vm,
+        "for (var i = 0; i < 20000; i = i + 1) { var l = [\"ab\" + \"cd\"]; }
+         var l = []; while (true) push(l, nil);",
+    );
+    assert_eq!(error.limit(), Some(Limit::Allocation));
+    assert!(vm.heap_stats().collections > 0);
+
+    let mut vm = Vm::new().with_limits(Limits::new().timeout(Duration::from_millis(50)));
+    let error = vm_limited(&mut vm, "while (true) {}");
+    assert_eq!(error.limit(), Some(Limit::Time));
+
+    let mut vm = Vm::new();
+    let handle = vm.interrupt_handle();
+    let interrupter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        handle.interrupt();
+    });
+    let error = vm_limited(&mut vm, "while (true) {}");
+    interrupter.join().unwrap();
+    assert_eq!(error.limit(), Some(Limit::Interrupted));
+}
@@ -0,0 +1,107 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use codecrafters_interpreter::Repl;
+
+struct Output {
+    stdout: String,
+    stderr: String,
+}
+
+fn repl(input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter"))
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    Output {
+        stdout: String::from_utf8(output.stdout).unwrap(),
+        stderr: String::from_utf8(output.stderr).unwrap(),
+    }
+}
+
+#[test]
+fn test_repl_keeps_globals() {
+    let out = repl("var a = 1;\na = a + 1;\nprint a;\na * 10\n");
+    assert_eq!(out.stdout, "2\n20\n");
+    assert_eq!(out.stderr, "");
+}
+
+#[test]
+fn test_repl_multiline_input() {
+    let out = repl(
+        r#"fun greet(name) {
+  return "hello " +
+    name;
+}
+var b = greet("lox")
+;
+b
+print "multi
+line";
+"#,
+    );
+    assert_eq!(out.stdout, "hello lox\nmulti\nline\n");
+    assert_eq!(out.stderr, "");
+}
+
+#[test]
+fn test_repl_errors_do_not_end_session() {
+    let out = repl("print missing;\n-\"a\"\nprint 1\n\nprint 2;\n");
+    assert_eq!(out.stdout, "2\n");
+    assert!(out.stderr.starts_with(
+        "Undefined variable 'missing'.\n[line 1]\nOperand must be a number.\n[line 1]\n"
+    ));
+    assert!(out.stderr.ends_with("Expect ';'.\n"), "{}", out.stderr);
+}
+
+#[test]
+fn test_repl_meta_commands() {
+    let out = repl(":tokens var x = 1;\n:ast print 1 + 2;\n:ast -x\n:nope\n:quit\nprint 1;\n");
+    assert_eq!(
+        out.stdout,
+        "VAR var null\nIDENTIFIER x null\nEQUAL = null\nNUMBER 1 1.0\nSEMICOLON ; null\nEOF  null\n\
+         (print (+ 1.0 2.0))\n(- x)\n"
+    );
+    assert_eq!(out.stderr, "Unknown command: :nope\n");
+}
+
+#[test]
+fn test_repl_load_and_time() {
+    let path = std::env::temp_dir().join(format!("lox-repl-{}.lox", std::process::id()));
+    std::fs::write(&path, "var loaded = \"yes\";\n").unwrap();
+    let out = repl(&format!(":load {}\nloaded\n:time 1 + 1\n", path.display()));
+    std::fs::remove_file(&path).ok();
+    let lines = out.stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines[..2], ["yes", "2"]);
+    assert!(lines[2].starts_with('[') && lines[2].ends_with(']'));
+    assert_eq!(out.stderr, "");
+}
+
+#[test]
+fn test_repl_writes_to_output() {
+    let mut output = Vec::new();
+    Repl::new()
+        .run("print 1;\nvar a = 2;\na\n".as_bytes(), &mut output)
+        .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "> 1\n> > 2\n> \n");
+
+    let mut output = Vec::new();
+    Repl::new()
+        .prompts(false)
+        .run(":time print 1;\n".as_bytes(), &mut output)
+        .unwrap();
+    assert!(String::from_utf8(output).unwrap().starts_with("1\n["));
+}
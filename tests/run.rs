@@ -0,0 +1,452 @@
+mod common;
+
+use common::{run, run_with};
+
+#[test]
+fn test_run_print() {
+    let out = run(r#"print "hello"; print 1 + 2; print nil; print 3 > 2;"#);
+    assert_eq!(out.stdout, "hello\n3\nnil\ntrue\n");
+    assert_eq!(out.code, 0);
+}
+
+#[test]
+fn test_run_variables_and_assignment() {
+    let out = run("var a; print a; var b = 2; a = b = b * 3; print a; print b;");
+    assert_eq!(out.stdout, "nil\n6\n6\n");
+    assert_eq!(out.code, 0);
+}
+
+#[test]
+fn test_run_block_scope() {
+    let out = run(r#"
+var a = "global";
+{
+    var a = "outer";
+    {
+        var a = "inner";
+        print a;
+    }
+    print a;
+    a = "changed";
+    print a;
+}
+print a;
+"#);
+    assert_eq!(out.stdout, "inner\nouter\nchanged\nglobal\n");
+}
+
+#[test]
+fn test_run_errors() {
+    let out = run("print 1;\nprint missing;");
+    assert_eq!(out.stdout, "1\n");
+    assert_eq!(out.stderr, "Undefined variable 'missing'.\n[line 2]\n");
+    assert_eq!(out.code, 70);
+
+    let out = run("var a = 1;\na + 1 = 2;");
+    assert_eq!(out.stdout, "");
+    assert_eq!(out.code, 65);
+}
+
+#[test]
+fn test_run_if_else() {
+    let out = run(r#"
+if (true) print "then"; else print "else";
+if (nil) print "then"; else print "else";
+if (false) print "no";
+if (true) if (false) print "inner"; else print "dangling";
+"#);
+    assert_eq!(out.stdout, "then\nelse\ndangling\n");
+}
+
+#[test]
+fn test_run_logical() {
+    let out = run(r#"
+print "hi" or 2;
+print nil or "yes";
+print nil and "no";
+print 1 and 2;
+var called = false;
+false and (called = true);
+true or (called = true);
+print called;
+"#);
+    assert_eq!(out.stdout, "hi\nyes\nnil\n2\nfalse\n");
+}
+
+#[test]
+fn test_run_loops() {
+    let out = run(r#"
+var i = 0;
+while (i < 3) { print i; i = i + 1; }
+var a = 0;
+var temp;
+for (var b = 1; a < 50; b = temp + b) {
+    print a;
+    temp = a;
+    a = b;
+}
+for (; i > 0;) i = i - 1;
+print i;
+"#);
+    assert_eq!(out.stdout, "0\n1\n2\n0\n1\n1\n2\n3\n5\n8\n13\n21\n34\n0\n");
+}
+
+#[test]
+fn test_run_functions() {
+    let out = run(r#"
+fun add(a, b, c) { return a + b + c; }
+print add(1, 2, 3);
+fun fib(n) {
+    if (n < 2) return n;
+    return fib(n - 1) + fib(n - 2);
+}
+print fib(15);
+fun early() { while (true) { return "done"; } }
+print early();
+fun nothing() {}
+print nothing();
+print add;
+print clock() > 0;
+"#);
+    assert_eq!(out.stdout, "6\n610\ndone\nnil\n<fn add>\ntrue\n");
+    assert_eq!(out.code, 0);
+}
+
+#[test]
+fn test_run_closures() {
+    let out = run(r#"
+fun makeCounter() {
+    var i = 0;
+    fun count() {
+        i = i + 1;
+        return i;
+    }
+    return count;
+}
+var a = makeCounter();
+var b = makeCounter();
+print a();
+print a();
+print b();
+"#);
+    assert_eq!(out.stdout, "1\n2\n1\n");
+}
+
+#[test]
+fn test_run_call_errors() {
+    let out = run("fun f(a) {}\nprint 1;\nf(1,\n 2);");
+    assert_eq!(out.stdout, "1\n");
+    assert_eq!(out.stderr, "Expected 1 arguments but got 2.\n[line 4]\n");
+    assert_eq!(out.code, 70);
+
+    let out = run(r#"var x = "not fn"; x();"#);
+    assert_eq!(
+        out.stderr,
+        "Can only call functions and classes.\n[line 1]\n"
+    );
+    assert_eq!(out.code, 70);
+}
+
+#[test]
+fn test_run_classes() {
+    let out = run(r#"
+class Point {
+    init(x, y) {
+        this.x = x;
+        this.y = y;
+    }
+    sum() { return this.x + this.y; }
+}
+var p = Point(1, 2);
+print Point;
+print p;
+print p.sum();
+p.x = 10;
+var sum = p.sum;
+print sum();
+print p.init(3, 4) == p;
+print p.x;
+"#);
+    assert_eq!(out.stdout, "Point\nPoint instance\n3\n12\ntrue\n3\n");
+    assert_eq!(out.code, 0);
+}
+
+#[test]
+fn test_run_inheritance() {
+    let out = run(r#"
+class A {
+    method() { return "A method"; }
+    name() { return "A"; }
+}
+class B < A {
+    method() { return "B method"; }
+    test() { return super.method() + " " + this.name(); }
+}
+class C < B {}
+print C().test();
+print C().method();
+"#);
+    assert_eq!(out.stdout, "A method A\nB method\n");
+}
+
+#[test]
+fn test_run_class_errors() {
+    let out = run("var a = 1;\nprint a.b;");
+    assert_eq!(out.stderr, "Only instances have properties.\n[line 2]\n");
+    assert_eq!(out.code, 70);
+
+    let out = run("class A {}\nA().missing;");
+    assert_eq!(out.stderr, "Undefined property 'missing'.\n[line 2]\n");
+
+    let out = run("var A = 1;\nclass B < A {}");
+    assert_eq!(out.stderr, "Superclass must be a class.\n[line 2]\n");
+
+    let out = run("class A { init(a) {} }\nA();");
+    assert_eq!(out.stderr, "Expected 1 arguments but got 0.\n[line 2]\n");
+}
+
+#[test]
+fn test_run_static_binding() {
+    let out = run(r#"
+var a = "global";
+{
+    fun showA() { print a; }
+    showA();
+    var a = "block";
+    showA();
+    print a;
+}
+"#);
+    assert_eq!(out.stdout, "global\nglobal\nblock\n");
+}
+
+#[test]
+fn test_run_resolver_errors() {
+    let out = run(r#"{ var a = a; }
+return 1;
+print this;
+fun f() { var b; var b; }
+class A < A {}
+class B { m() { super.x(); } }
+class C { init() { return 1; } }
+print "unreachable";
+"#);
+    assert_eq!(out.stdout, "");
+    assert_eq!(
+        out.stderr,
+        "[line 1] Error at 'a': Can't read local variable in its own initializer.
+[line 2] Error at 'return': Can't return from top-level code.
+[line 3] Error at 'this': Can't use 'this' outside of a class.
+[line 4] Error at 'b': Already a variable with this name in this scope.
+[line 5] Error at 'A': A class can't inherit from itself.
+[line 6] Error at 'super': Can't use 'super' in a class with no superclass.
+[line 7] Error at 'return': Can't return a value from an initializer.
+"
+    );
+    assert_eq!(out.code, 65);
+}
+
+#[test]
+fn test_run_syntax_error_recovery() {
+    let out = run(r#"
+var a = ;
+print "reached"
+var 1 = 2;
+a = 1 +;
+print (1;
+print "done";
+class A { method() { return this.; } }
+"#);
+    assert_eq!(
+        out.stderr,
+        "[line 2] Error at ';': Expect expression.\n\
+         [line 4] Error at 'var': Expect ';'.\n\
+         [line 4] Error at '1': Expect identifier.\n\
+         [line 5] Error at ';': Expect expression.\n\
+         [line 6] Error at ';': Expect ')'.\n\
+         [line 8] Error at ';': Expect identifier.\n\
+         [line 8] Error at '}': Expect expression.\n"
+    );
+    assert_eq!(out.stdout, "");
+    assert_eq!(out.code, 65);
+
+    let out = run("print \"unterminated;\n");
+    let lines = out.stderr.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with("Error: Unterminated string."));
+    assert!(lines[1].ends_with("Error at end: Expect expression."));
+    assert_eq!(out.code, 65);
+
+    let out = run("print 1 @ 2;");
+    assert_eq!(
+        out.stderr,
+        "[line 1] Error: Unexpected character: @\n[line 1] Error at '2': Expect ';'.\n"
+    );
+
+    // The literal still parses, so the bad escape is the only error.
+    let out = run("var s = \"a\\q\";\nprint s;\n");
+    assert_eq!(out.stderr, "[line 1] Error: Invalid escape sequence: \\q\n");
+    assert_eq!(out.code, 65);
+}
+
+#[test]
+fn test_run_diagnostics_format() {
+    let source = "var a = 1;\nprint a +\n  ;\n";
+    let short = run_with(&["--diagnostics=short"], source);
+    assert_eq!(short.stderr, "[line 3] Error at ';': Expect expression.\n");
+    assert_eq!(short.code, 65);
+
+    let rich = run_with(&["--diagnostics=rich"], source);
+    let path = rich.stderr.lines().nth(1).unwrap();
+    assert!(
+        path.starts_with(" --> ") && path.ends_with(".lox:3:3"),
+        "{path}"
+    );
+    let excerpt = rich.stderr.lines().skip(2).collect::<Vec<_>>();
+    assert_eq!(excerpt, ["  |", "3 |   ;", "  |   ^"]);
+    assert!(rich
+        .stderr
+        .starts_with("error[E0102]: Expect expression.\n"));
+    assert_eq!(rich.code, 65);
+
+    let rich = run_with(
+        &["--diagnostics=rich"],
+        "{\n  var x = 1;\n  var x = 2;\n}\n",
+    );
+    let excerpt = rich.stderr.lines().skip(3).collect::<Vec<_>>();
+    assert_eq!(excerpt, ["3 |   var x = 2;", "  |       ^"]);
+}
+
+#[test]
+fn test_run_number_literals() {
+    let out = run("print 0xFF + 0b1 + 0o7;\nprint 1_000 * 2.5e-1;");
+    assert_eq!(out.stdout, "263\n250\n");
+    assert_eq!(out.code, 0);
+
+    let out = run("print 1;\nprint 0x;");
+    assert_eq!(out.stdout, "");
+    assert!(out
+        .stderr
+        .starts_with("[line 2] Error: Invalid number literal '0x'"));
+    assert_eq!(out.code, 65);
+}
+
+#[test]
+fn test_run_number_formatting() {
+    let source = "print 1e21; print 0x7FFF_FFFF_FFFF_FFFF * 4; print 1 / 3; \
+                  print -0; print 1e-9; print 1e308 * 10; print -(1e308 * 10); \
+                  print [1, 2.5, 0 - 0];";
+    let expected =
+        "1e21\n36893488147419103000\n0.3333333333333333\n-0\n1e-9\ninf\n-inf\n[1, 2.5, 0]\n";
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let out = run_with(&[backend], source);
+        assert_eq!(out.stdout, expected, "{backend}");
+    }
+}
+
+#[test]
+fn test_run_string_interpolation() {
+    let source = r#"var name = "Ann";
+var age = 41;
+class A {}
+print "Hello ${name}, you are ${age + 1}";
+print "${ "nested ${ [1, 2][0] }" } ${nil} ${A()} ${1 / 4}";
+print "not \${interpolated}";"#;
+    let expected = "Hello Ann, you are 42\nnested 1 nil A instance 0.25\nnot ${interpolated}\n";
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let out = run_with(&[backend], source);
+        assert_eq!(out.stdout, expected, "{backend}");
+        assert_eq!(out.code, 0);
+    }
+
+    let rich = run_with(&["--diagnostics=rich"], "print \"a ${1 +} b\";\n");
+    let excerpt = rich.stderr.lines().skip(3).take(2).collect::<Vec<_>>();
+    assert_eq!(
+        excerpt,
+        ["1 | print \"a ${1 +} b\";", "  |               ^"]
+    );
+    assert_eq!(rich.code, 65);
+}
+
+#[test]
+fn test_run_string_escapes() {
+    let out = run(r#"print "a\tb\n\"c\" \u{e9}";"#);
+    assert_eq!(out.stdout, "a\tb\n\"c\" é\n");
+    assert_eq!(out.code, 0);
+
+    let rich = run_with(&["--diagnostics=rich"], "print 1;\nprint \"bad \\q\";\n");
+    assert!(rich
+        .stderr
+        .starts_with("error[E0003]: Invalid escape sequence: \\q\n"));
+    let excerpt = rich.stderr.lines().skip(3).take(2).collect::<Vec<_>>();
+    assert_eq!(excerpt, ["2 | print \"bad \\q\";", "  |            ^^"]);
+    assert_eq!(rich.code, 65);
+}
+
+#[test]
+fn test_run_stack_trace() {
+    let source = "fun a() { b(); }\nfun b() {\n  c();\n}\nfun c() { nil(); }\na();\n";
+    let short = run_with(&["--diagnostics=short"], source);
+    assert_eq!(
+        short.stderr,
+        "Can only call functions and classes.\n[line 5]\n"
+    );
+    let operator = "fun f() {\n  return 1 + -\"a\";\n}\nprint f();\n";
+    let cases = [
+        (
+            source,
+            &[
+                "Can only call functions and classes.",
+                "5:11] in c()",
+                "3:3] in b()",
+                "1:11] in a()",
+                "6:1] in script",
+            ][..],
+        ),
+        (
+            operator,
+            &[
+                "Operand must be a number.",
+                "2:14] in f()",
+                "4:7] in script",
+            ][..],
+        ),
+    ];
+    for backend in ["--backend=tree", "--backend=vm"] {
+        for (source, expected) in cases {
+            let out = run_with(&[backend, "--diagnostics=rich"], source);
+            let lines = out
+                .stderr
+                .lines()
+                .map(|line| line.split_once(".lox:").map_or(line, |(_, rest)| rest))
+                .collect::<Vec<_>>();
+            assert_eq!(lines, expected, "{backend}");
+            assert_eq!(out.code, 70, "{backend}");
+        }
+    }
+}
+
+#[test]
+fn test_run_recursion_depth() {
+    let source = "fun f(n) { if (n == 0) return 0; return f(n - 1) + 1; }\n\
+                  print f(1023);\nprint f(1024);\n";
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let out = run_with(&[backend, "--diagnostics=short"], source);
+        assert_eq!(out.stdout, "1023\n", "{backend}");
+        assert_eq!(out.stderr, "Stack overflow.\n[line 1]\n", "{backend}");
+        assert_eq!(out.code, 70, "{backend}");
+    }
+}
+
+#[test]
+fn test_run_nesting() {
+    let source = format!("print {}1{};\n", "(".repeat(20000), ")".repeat(20000));
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let out = run_with(&[backend, "--diagnostics=short"], &source);
+        assert_eq!(
+            out.stderr, "[line 1] Error at '(': Too much nesting.\n",
+            "{backend}"
+        );
+        assert_eq!(out.code, 65, "{backend}");
+    }
+}
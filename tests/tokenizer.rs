@@ -1,4 +1,7 @@
-use codecrafters_interpreter::{Lexer, Token};
+mod common;
+
+use codecrafters_interpreter::{LexError, Lexer, Token};
+use common::command;
 
 #[test]
 fn test_lexer_empty() {
@@ -88,17 +91,348 @@ fn test_lexer_string() {
     let mut lx = Lexer::from(input);
     assert_eq!(
         lx.next(),
-        Some((Token::String("hello".into()), 1, 0..7).into())
+        Some((Token::String("hello".into(), input.into()), 1, 0..7).into())
     );
     assert_eq!(lx.next(), Some((Token::Eof, 1, 6..6).into()));
     let input = r#"+"""hello // world""#;
     let mut lx = Lexer::from(input);
     assert_eq!(lx.next(), Some((Token::Plus, 1, 0..1).into()));
-    assert_eq!(lx.next(), Some((Token::String("".into()), 1, 1..3).into()));
     assert_eq!(
         lx.next(),
-        Some((Token::String("hello // world".into()), 1, 3..19).into())
+        Some((Token::String("".into(), "\"\"".into()), 1, 1..3).into())
+    );
+    assert_eq!(
+        lx.next(),
+        Some(
+            (
+                Token::String("hello // world".into(), input[3..].into()),
+                1,
+                3..19
+            )
+                .into()
+        )
     );
     assert_eq!(lx.next(), Some((Token::Eof, 1, 18..18).into()));
     assert_eq!(lx.next(), None);
 }
+
+#[test]
+fn test_lexer_multiline_string_line() {
+    let input = "\"a\nb\nc\" +\n\"unterminated\n";
+    let mut lx = Lexer::from(input);
+    assert_eq!(
+        lx.next(),
+        Some((Token::String("a\nb\nc".into(), input[..7].into()), 1, 0..7).into())
+    );
+    assert_eq!(lx.next(), Some((Token::Plus, 3, 8..9).into()));
+    assert_eq!(
+        lx.next(),
+        Some((Token::Error(LexError::UnterminatedStr(4)), 4, 10..24).into())
+    );
+    assert_eq!(lx.get_line(), 5);
+
+    let mut lx = Lexer::from("\n-".as_bytes());
+    assert_eq!(lx.next(), Some((Token::Minus, 2, 1..2).into()));
+}
+
+#[test]
+fn test_lexer_string_escapes() {
+    use std::borrow::Cow;
+
+    let input = r#""a\tb\n\"q\" \\ \u{1F600}" "plain""#;
+    let mut lx = Lexer::from(input);
+    let token = lx.next().unwrap();
+    assert_eq!(token.get_span(), 0..26);
+    match token.get_value() {
+        Token::String(Cow::Owned(s), _) => assert_eq!(s, "a\tb\n\"q\" \\ 😀"),
+        other => panic!("expected an owned string, got {other:?}"),
+    }
+    // The lexeme is the literal as written, escapes and all.
+    assert_eq!(token.get_value().lexeme(), &input[..26]);
+    assert_eq!(
+        token.get_value().to_string(),
+        format!("STRING {} a\tb\n\"q\" \\ 😀", &input[..26])
+    );
+    assert!(matches!(
+        lx.next().unwrap().get_value(),
+        Token::String(Cow::Borrowed("plain"), Cow::Borrowed("\"plain\""))
+    ));
+}
+
+#[test]
+fn test_lexer_invalid_escape() {
+    let input = "\"ok\"\n\"a\\qb\\z\" +";
+    let mut lx = Lexer::from(input);
+    assert_eq!(
+        lx.next(),
+        Some((Token::String("ok".into(), "\"ok\"".into()), 1, 0..4).into())
+    );
+    // The literal keeps its invalid escapes, and an error for the first
+    // one follows it.
+    assert_eq!(
+        lx.next(),
+        Some(
+            (
+                Token::String("a\\qb\\z".into(), "\"a\\qb\\z\"".into()),
+                2,
+                5..13
+            )
+                .into()
+        )
+    );
+    assert_eq!(
+        lx.next(),
+        Some(
+            (
+                Token::Error(LexError::InvalidEscape(2, "\\q".into())),
+                2,
+                7..9
+            )
+                .into()
+        )
+    );
+    assert_eq!(lx.next(), Some((Token::Plus, 2, 14..15).into()));
+
+    for input in [
+        r#""\u{D800}""#,
+        r#""\u{}""#,
+        r#""\u{1234567}""#,
+        r#""\u41""#,
+    ] {
+        let token = Lexer::from(input).nth(1).unwrap();
+        assert!(
+            matches!(
+                token.get_value(),
+                Token::Error(LexError::InvalidEscape(1, _))
+            ),
+            "{input}"
+        );
+    }
+}
+
+#[test]
+fn test_lexer_invalid_unicode_char() {
+    let input = "a é";
+    let mut lx = Lexer::from(input);
+    assert_eq!(
+        lx.next(),
+        Some((Token::Identifier("a".into()), 1, 0..1).into())
+    );
+    #[cfg(not(feature = "unicode-identifiers"))]
+    assert_eq!(
+        lx.next(),
+        Some((Token::Error(LexError::InvalidChar(1, 'é')), 1, 2..4).into())
+    );
+    #[cfg(feature = "unicode-identifiers")]
+    assert_eq!(
+        lx.next(),
+        Some((Token::Identifier("é".into()), 1, 2..4).into())
+    );
+}
+
+#[test]
+fn test_lexer_block_comments() {
+    let input = "1 /* a /* nested\n */ still */ + /**/ 2";
+    let tokens = Lexer::from(input)
+        .map(|t| (t.get_value().clone(), t.get_line()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        tokens,
+        [
+            (Token::Number(1.0, "1".into()), 1),
+            (Token::Plus, 2),
+            (Token::Number(2.0, "2".into()), 2),
+            (Token::Eof, 2),
+        ]
+    );
+
+    let mut lx = Lexer::from("-\n/* open /* */\n");
+    assert_eq!(lx.next(), Some((Token::Minus, 1, 0..1).into()));
+    assert_eq!(
+        lx.next(),
+        Some((Token::Error(LexError::UnterminatedComment(2)), 2, 2..16).into())
+    );
+    assert_eq!(lx.get_line(), 3);
+}
+
+#[test]
+fn test_lexer_doc_comments() {
+    let input = "/// Adds one.\n//// not docs\n// plain\nfun";
+    let mut lx = Lexer::from(input);
+    assert_eq!(
+        lx.next(),
+        Some((Token::DocComment(" Adds one.".into()), 1, 0..13).into())
+    );
+    assert_eq!(lx.next(), Some((Token::Fun, 4, 37..40).into()));
+
+    // Doc comments are tokens in their own right, not trivia, though the
+    // parser passes over them.
+    let doc = Token::DocComment(" Adds one.".into());
+    assert!(!doc.is_trivia());
+    assert!(doc.is_skipped());
+    assert_eq!(doc.to_string(), "DOC_COMMENT /// Adds one.  Adds one.");
+}
+
+#[test]
+fn test_lexer_trivia() {
+    let input = "var a = 1; // one\r\n\t/* two\n */print a;\n/// doc\n";
+    let tokens = Lexer::from(input).with_trivia().collect::<Vec<_>>();
+    let source = tokens
+        .iter()
+        .map(|t| t.get_value().lexeme())
+        .collect::<String>();
+    assert_eq!(source, input);
+    for token in &tokens {
+        let lexeme = token.get_value().lexeme();
+        assert_eq!(&input[token.get_span()], lexeme, "{token:?}");
+    }
+    let trivia = tokens
+        .iter()
+        .filter(|t| t.get_value().is_trivia())
+        .map(|t| (t.get_value().clone(), t.get_line()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        trivia,
+        [
+            (Token::Whitespace(" ".into()), 1),
+            (Token::Whitespace(" ".into()), 1),
+            (Token::Whitespace(" ".into()), 1),
+            (Token::Whitespace(" ".into()), 1),
+            (Token::Comment("// one\r".into()), 1),
+            (Token::Newline, 1),
+            (Token::Whitespace("\t".into()), 2),
+            (Token::Comment("/* two\n */".into()), 2),
+            (Token::Whitespace(" ".into()), 3),
+            (Token::Newline, 3),
+            (Token::Newline, 4),
+        ]
+    );
+}
+
+#[test]
+fn test_lexer_number_literals() {
+    let input = "0x1F 0b1010 0o17 1e-9 2.5E3 1_000_000 0XFF_FF 7.";
+    let numbers = Lexer::from(input)
+        .filter_map(|t| match t.get_value() {
+            Token::Number(value, lexeme) => Some((*value, lexeme.to_string())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        numbers,
+        [
+            (31.0, "0x1F".to_string()),
+            (10.0, "0b1010".to_string()),
+            (15.0, "0o17".to_string()),
+            (1e-9, "1e-9".to_string()),
+            (2500.0, "2.5E3".to_string()),
+            (1_000_000.0, "1_000_000".to_string()),
+            (65535.0, "0XFF_FF".to_string()),
+            (7.0, "7".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_lexer_invalid_numbers() {
+    let cases = [
+        ("0x", "expected digits after the prefix"),
+        ("1e", "expected digits in the exponent"),
+        ("2.5e+", "expected digits in the exponent"),
+        ("0b102", "invalid digit for the base"),
+        ("0o8", "invalid digit for the base"),
+        ("1__0", "'_' must separate two digits"),
+        ("1_", "'_' must separate two digits"),
+        ("0x_1", "'_' must separate two digits"),
+        ("1_e5", "'_' must separate two digits"),
+    ];
+    for (input, reason) in cases {
+        let source = format!("+ {input};");
+        let mut lx = Lexer::from(source.as_str());
+        assert_eq!(lx.next().unwrap().get_value(), &Token::Plus);
+        let error = LexError::InvalidNumber(1, input.to_string(), reason);
+        let span = 2..2 + input.len();
+        assert_eq!(lx.next(), Some((Token::Error(error), 1, span).into()));
+        assert_eq!(lx.next().unwrap().get_value(), &Token::Semicolon);
+    }
+    assert_eq!(
+        LexError::InvalidNumber(3, "0x".into(), "expected digits after the prefix").to_string(),
+        "[line 3] Error: Invalid number literal '0x': expected digits after the prefix."
+    );
+}
+
+#[test]
+fn test_lexer_interpolation() {
+    let input = r#""a ${x + "${y}"} b\${c}""#;
+    let tokens = Lexer::from(input)
+        .map(|t| (t.get_value().clone(), t.get_span()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        tokens,
+        [
+            (Token::StringFragment("a ".into(), "\"a ".into()), 0..3),
+            (Token::InterpolationStart, 3..5),
+            (Token::Identifier("x".into()), 5..6),
+            (Token::Plus, 7..8),
+            (Token::StringFragment("".into(), "\"".into()), 9..10),
+            (Token::InterpolationStart, 10..12),
+            (Token::Identifier("y".into()), 12..13),
+            (Token::InterpolationEnd, 13..14),
+            (Token::StringFragment("".into(), "\"".into()), 14..15),
+            (Token::InterpolationEnd, 15..16),
+            (
+                Token::StringFragment(" b${c}".into(), " b\\${c}\"".into()),
+                16..24
+            ),
+            (Token::Eof, 23..23),
+        ]
+    );
+
+    let mut lx = Lexer::from("\"${ {} }\" {}");
+    let kinds = lx
+        .by_ref()
+        .map(|t| t.get_value().clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            Token::StringFragment("".into(), "\"".into()),
+            Token::InterpolationStart,
+            Token::LeftBrace,
+            Token::RightBrace,
+            Token::InterpolationEnd,
+            Token::StringFragment("".into(), "\"".into()),
+            Token::LeftBrace,
+            Token::RightBrace,
+            Token::Eof,
+        ]
+    );
+
+    let mut lx = Lexer::from("\"a ${b}\n c");
+    let last = lx.by_ref().filter(|t| t.get_value().is_err()).last();
+    assert_eq!(
+        last,
+        Some((Token::Error(LexError::UnterminatedStr(1)), 1, 7..10).into())
+    );
+}
+
+#[test]
+fn test_tokenize_command_trivia() {
+    let source = "/// doc\nvar a; // c\n";
+    let out = command("tokenize", &[], source);
+    assert_eq!(
+        out.stdout,
+        "VAR var null\nIDENTIFIER a null\nSEMICOLON ; null\nEOF  null\n"
+    );
+    let out = command("tokenize", &["--trivia"], source);
+    assert_eq!(
+        out.stdout.lines().take(3).collect::<Vec<_>>(),
+        [
+            "DOC_COMMENT /// doc  doc",
+            "NEWLINE \"\\n\" null",
+            "VAR var null"
+        ]
+    );
+    assert!(out.stdout.contains("COMMENT // c null\n"));
+    assert_eq!(out.code, 0);
+}
@@ -0,0 +1,290 @@
+mod common;
+
+use common::{run_with, Output};
+
+/// Runs `source` on both backends and checks they agree.
+fn run_both(source: &str) -> Output {
+    let vm = run_with(&["--backend=vm"], source);
+    assert_eq!(vm, run_with(&["--backend=tree"], source));
+    vm
+}
+
+#[test]
+fn test_vm_expressions() {
+    let out = run_both(
+        r#"
+print 1 + 2 * 3 - 4 / 2;
+print -(3 - 5);
+print "foo" + "bar";
+print "a" == "a";
+print 1 != 2;
+print 2 >= 3;
+print !nil;
+print nil or "x";
+print false and 1;
+"#,
+    );
+    assert_eq!(
+        out.stdout,
+        "5\n2\nfoobar\ntrue\ntrue\nfalse\ntrue\nx\nfalse\n"
+    );
+    assert_eq!(out.code, 0);
+}
+
+#[test]
+fn test_vm_variables_and_control_flow() {
+    let out = run_both(
+        r#"
+var a = 1;
+{
+    var c = a + 1;
+    var b = c * 10;
+    print b;
+}
+var sum = 0;
+for (var i = 0; i < 5; i = i + 1) {
+    if (i == 2) sum = sum + 100; else sum = sum + i;
+}
+print sum;
+var n = 3;
+while (n > 0) n = n - 1;
+print n;
+"#,
+    );
+    assert_eq!(out.stdout, "20\n108\n0\n");
+    assert_eq!(out.code, 0);
+}
+
+#[test]
+fn test_vm_functions_and_closures() {
+    let out = run_both(
+        r#"
+fun fib(n) {
+    if (n < 2) return n;
+    return fib(n - 1) + fib(n - 2);
+}
+print fib(15);
+
+fun makeCounter() {
+    var i = 0;
+    fun count() {
+        i = i + 1;
+        return i;
+    }
+    return count;
+}
+var c1 = makeCounter();
+var c2 = makeCounter();
+c1();
+print c1();
+print c2();
+print c1;
+print clock;
+
+var f;
+{
+    var shared = "before";
+    fun get() { return shared; }
+    f = get;
+    shared = "after";
+}
+print f();
+"#,
+    );
+    assert_eq!(out.stdout, "610\n2\n1\n<fn count>\n<native fn>\nafter\n");
+    assert_eq!(out.code, 0);
+}
+
+#[test]
+fn test_vm_classes() {
+    let out = run_both(
+        r#"
+class Point {
+    init(x, y) {
+        this.x = x;
+        this.y = y;
+    }
+    sum() { return this.x + this.y; }
+}
+var p = Point(1, 2);
+print p.sum();
+p.x = 10;
+var sum = p.sum;
+print sum();
+print p;
+print Point;
+
+class A {
+    name() { return "A"; }
+    greet() { print "Hello from " + this.name(); }
+}
+class B < A {
+    name() { return "B"; }
+    greet() {
+        super.greet();
+        print "and B";
+    }
+}
+B().greet();
+print B().init;
+"#,
+    );
+    assert_eq!(
+        out.stdout,
+        "3\n12\nPoint instance\nPoint\nHello from B\nand B\n"
+    );
+    assert_eq!(out.stderr, "Undefined property 'init'.\n[line 29]\n");
+    assert_eq!(out.code, 70);
+}
+
+#[test]
+fn test_vm_lists() {
+    let out = run_both(
+        r#"
+var xs = [1, "two", nil,];
+print xs;
+print xs[1];
+xs[2] = [3];
+print xs[2][0] + 1;
+print len(xs);
+print len("héllo");
+push(xs, 4);
+print pop(xs);
+insert(xs, 0, 0);
+insert(xs, len(xs), 5);
+print remove(xs, 1);
+print xs;
+print [];
+var ys = xs;
+print ys == xs;
+print [1] == [1];
+push(xs, xs);
+print xs;
+var a = [];
+var b = [a];
+push(a, b);
+print a;
+print [b, b];
+"#,
+    );
+    assert_eq!(
+        out.stdout,
+        "\
+[1, two, nil]
+two
+4
+3
+5
+4
+1
+[0, two, [3], 5]
+[]
+true
+false
+[0, two, [3], 5, [...]]
+[[[...]]]
+[[[[...]]], [[[...]]]]
+"
+    );
+    assert_eq!(out.code, 0);
+}
+
+#[test]
+fn test_vm_runtime_errors() {
+    for (source, message) in [
+        ("print -\"a\";", "Operand must be a number.\n[line 1]\n"),
+        (
+            "print 1 + nil;",
+            "Operands must be two numbers or two strings.\n[line 1]\n",
+        ),
+        ("\nprint x;", "Undefined variable 'x'.\n[line 2]\n"),
+        ("y = 1;", "Undefined variable 'y'.\n[line 1]\n"),
+        (
+            "\"f\"();",
+            "Can only call functions and classes.\n[line 1]\n",
+        ),
+        (
+            "fun f(a) {} f();",
+            "Expected 1 arguments but got 0.\n[line 1]\n",
+        ),
+        (
+            "var a = 1; a.b;",
+            "Only instances have properties.\n[line 1]\n",
+        ),
+        (
+            "var a = 1; class B < a {}",
+            "Superclass must be a class.\n[line 1]\n",
+        ),
+        ("print 1[0];", "Only lists can be indexed.\n[line 1]\n"),
+        (
+            "print [1][\"0\"];",
+            "List index must be a number.\n[line 1]\n",
+        ),
+        (
+            "print [1][0.5];",
+            "List index must be an integer, got 0.5.\n[line 1]\n",
+        ),
+        (
+            "var xs = [1, 2];\nxs[2] = 3;",
+            "List index 2 out of range for length 2.\n[line 2]\n",
+        ),
+        (
+            "print [][-1];",
+            "List index -1 out of range for length 0.\n[line 1]\n",
+        ),
+        ("pop([]);", "Can't pop from an empty list.\n[line 1]\n"),
+        (
+            "insert([], 1, 0);",
+            "List index 1 out of range for length 0.\n[line 1]\n",
+        ),
+        (
+            "push(nil, 1);",
+            "push() expects a list, got nil.\n[line 1]\n",
+        ),
+        (
+            "len(1);",
+            "len() expects a list or string, got number.\n[line 1]\n",
+        ),
+    ] {
+        let out = run_both(source);
+        assert_eq!(out.stderr, message, "{source}");
+        assert_eq!(out.code, 70);
+    }
+}
+
+#[test]
+fn test_vm_stack_overflow() {
+    let out = run_both("fun f() { f(); } f();");
+    assert_eq!(out.stderr, "Stack overflow.\n[line 1]\n");
+    assert_eq!(out.code, 70);
+}
+
+#[test]
+fn test_vm_too_many_globals() {
+    let source = (0..=u16::MAX)
+        .map(|i| format!("var g{i};\n"))
+        .collect::<String>();
+    let out = run_with(&["--backend=vm"], &source);
+    assert!(
+        out.stderr
+            .ends_with("] Error: Too many global variables.\n"),
+        "{}",
+        out.stderr
+    );
+    assert_eq!(out.code, 65);
+}
+
+#[test]
+fn test_vm_rejects_imports() {
+    let source = "print \"before\";\nfun f() {\n  import \"lib.lox\";\n}\n";
+    let out = run_with(&["--backend=vm"], source);
+    assert_eq!(
+        out,
+        Output {
+            stdout: String::new(),
+            stderr: "[line 3] Error: Imports are only supported by the tree-walking backend.\n"
+                .to_string(),
+            code: 65,
+        }
+    );
+}